@@ -0,0 +1,139 @@
+use crate::error::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    controller::{ExecAction, TahomaApiController, TahomaSetupResponse},
+    device::{Device, DeviceState},
+    events::Event,
+    execution::Execution,
+    model::Configuration,
+    recording::RecordingApi,
+    simulator::SimulatedTahomaApi,
+};
+
+/// Operations offered by a Tahoma gateway.
+///
+/// [`TahomaApiController`] implements it over the local HTTP API, but anything
+/// else (a simulator, a recorder, a test double) can be plugged into
+/// [`MatahoService`](crate::MatahoService) and the CLI instead.
+pub trait TahomaApi {
+    fn get_setup(&self) -> Result<TahomaSetupResponse>;
+
+    /// Execute a command on a single device and return the execution ID.
+    fn execute(&self, device: &Device, command: &str, params: &[String]) -> Result<String>;
+
+    /// Execute the same command on several devices and return the execution ID.
+    fn execute_multiple(
+        &self,
+        devices: Vec<&Device>,
+        command: &str,
+        params: &[String],
+    ) -> Result<String>;
+
+    /// Execute several commands, possibly different for each device, as a single
+    /// execution of the gateway and return the execution ID.
+    fn execute_batch(&self, label: &str, batch: &[ExecAction]) -> Result<String>;
+
+    fn get_device_states(&self, device: &Device) -> Result<Vec<DeviceState>>;
+
+    fn get_current_executions(&self) -> Result<Vec<Execution>>;
+
+    /// Execution still running on the gateway, `None` once it is finished.
+    fn get_execution(&self, exec_id: &str) -> Result<Option<Execution>> {
+        Ok(self
+            .get_current_executions()?
+            .into_iter()
+            .find(|execution| execution.id() == exec_id))
+    }
+
+    fn cancel_execution(&self, exec_id: &str) -> Result<()>;
+
+    fn cancel_all_executions(&self) -> Result<()>;
+
+    /// Register an event listener and return its ID.
+    fn register_event_listener(&self) -> Result<String>;
+
+    fn fetch_events(&self, listener_id: &str) -> Result<Vec<Event>>;
+
+    fn unregister_event_listener(&self, listener_id: &str) -> Result<()>;
+}
+
+impl<T: TahomaApi + ?Sized> TahomaApi for Box<T> {
+    fn get_setup(&self) -> Result<TahomaSetupResponse> {
+        (**self).get_setup()
+    }
+
+    fn execute(&self, device: &Device, command: &str, params: &[String]) -> Result<String> {
+        (**self).execute(device, command, params)
+    }
+
+    fn execute_multiple(
+        &self,
+        devices: Vec<&Device>,
+        command: &str,
+        params: &[String],
+    ) -> Result<String> {
+        (**self).execute_multiple(devices, command, params)
+    }
+
+    fn execute_batch(&self, label: &str, batch: &[ExecAction]) -> Result<String> {
+        (**self).execute_batch(label, batch)
+    }
+
+    fn get_device_states(&self, device: &Device) -> Result<Vec<DeviceState>> {
+        (**self).get_device_states(device)
+    }
+
+    fn get_current_executions(&self) -> Result<Vec<Execution>> {
+        (**self).get_current_executions()
+    }
+
+    fn get_execution(&self, exec_id: &str) -> Result<Option<Execution>> {
+        (**self).get_execution(exec_id)
+    }
+
+    fn cancel_execution(&self, exec_id: &str) -> Result<()> {
+        (**self).cancel_execution(exec_id)
+    }
+
+    fn cancel_all_executions(&self) -> Result<()> {
+        (**self).cancel_all_executions()
+    }
+
+    fn register_event_listener(&self) -> Result<String> {
+        (**self).register_event_listener()
+    }
+
+    fn fetch_events(&self, listener_id: &str) -> Result<Vec<Event>> {
+        (**self).fetch_events(listener_id)
+    }
+
+    fn unregister_event_listener(&self, listener_id: &str) -> Result<()> {
+        (**self).unregister_event_listener(listener_id)
+    }
+}
+
+/// Backend used to talk to the gateway, see [`Configuration::backend`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// The local API of a real gateway
+    #[default]
+    Http,
+    /// An in-memory gateway that never leaves the machine
+    Simulator,
+}
+
+/// Build the backend selected in the configuration. The API token is read from
+/// its source (see [`secret`](crate::secret)) at the first request sent to the gateway.
+pub fn from_configuration(config: &Configuration) -> Result<Box<dyn TahomaApi>> {
+    let api: Box<dyn TahomaApi> = match config.backend {
+        Backend::Http => Box::new(TahomaApiController::new(config)),
+        Backend::Simulator => Box::new(SimulatedTahomaApi::from_configuration(config)?),
+    };
+
+    match &config.record_file {
+        Some(path) => Ok(Box::new(RecordingApi::new(api, path))),
+        None => Ok(api),
+    }
+}
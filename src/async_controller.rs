@@ -0,0 +1,272 @@
+use log::{debug, info};
+use reqwest::{RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use std::time::Duration;
+
+use crate::{
+    error::{MatahoError, Result},
+    secret, tls, Configuration,
+};
+
+use super::controller::{
+    check_supported, exec_label, EventListenerResponse, ExecAction, ExecApplyResponse, ExecRequest,
+    TahomaSetupResponse,
+};
+use super::device::{Device, DeviceState};
+use super::events::Event;
+use super::execution::Execution;
+
+/// Non-blocking counterpart of [`TahomaApiController`](crate::TahomaApiController),
+/// for use inside an async runtime such as tokio.
+pub struct AsyncTahomaApiController {
+    hostname: String,
+    port: i32,
+    api_token: String,
+    client: reqwest::Client,
+}
+
+impl AsyncTahomaApiController {
+    /// The API token is read from its source (see [`secret`]) right away, not to
+    /// run `api_token_command` or open the keyring inside the async runtime.
+    pub fn new(configuration: &Configuration) -> Result<AsyncTahomaApiController> {
+        let mut builder =
+            reqwest::ClientBuilder::new().timeout(Duration::from_secs(configuration.timeout));
+        if tls::uses_tls(&configuration.hostname) {
+            builder = builder.use_preconfigured_tls(tls::client_config(&configuration.tls)?);
+        }
+
+        let client = builder
+            .build()
+            .map_err(|err| MatahoError::from_request("Failed to build the HTTP client", err))?;
+
+        Ok(AsyncTahomaApiController {
+            hostname: configuration.hostname.clone(),
+            port: configuration.port,
+            api_token: secret::resolve_token(configuration)?,
+            client,
+        })
+    }
+
+    /// Send the request and check its status, `context` prefixes the error message.
+    async fn send(&self, request: RequestBuilder, context: &str) -> Result<Response> {
+        let res = request
+            .bearer_auth(&self.api_token)
+            .send()
+            .await
+            .map_err(|err| MatahoError::from_request(context, err))?;
+        debug!("result: {:?}", res);
+
+        res.error_for_status()
+            .map_err(|err| MatahoError::from_request(context, err))
+    }
+
+    async fn send_json<T: DeserializeOwned>(
+        &self,
+        request: RequestBuilder,
+        context: &str,
+    ) -> Result<T> {
+        self.send(request, context)
+            .await?
+            .json()
+            .await
+            .map_err(|err| MatahoError::from_request(context, err))
+    }
+
+    pub async fn get_setup(&self) -> Result<TahomaSetupResponse> {
+        let url = self.endpoint("/enduser-mobile-web/1/enduserAPI/setup");
+        info!("GET {}", url);
+
+        let res = self
+            .send_json(
+                self.client.get(url),
+                "Failed to get setup. Check your configuration file",
+            )
+            .await?;
+        debug!("result: {:?}", res);
+
+        Ok(res)
+    }
+
+    pub async fn execute(
+        &self,
+        device: &Device,
+        command: &str,
+        params: &[String],
+    ) -> Result<String> {
+        self.execute_multiple(vec![device], command, params).await
+    }
+
+    pub async fn execute_multiple(
+        &self,
+        devices: Vec<&Device>,
+        command: &str,
+        params: &[String],
+    ) -> Result<String> {
+        let batch = ExecAction::same_command(&devices, command, params)?;
+
+        self.execute_batch(&exec_label(command, &devices), &batch)
+            .await
+    }
+
+    /// Execute several commands, possibly different for each device, as a single
+    /// execution of the gateway, see [`TahomaApi::execute_batch`](crate::TahomaApi::execute_batch).
+    pub async fn execute_batch(&self, label: &str, batch: &[ExecAction<'_>]) -> Result<String> {
+        check_supported(batch)?;
+
+        self.apply(&ExecRequest::new(label, batch), "Failed to execute command")
+            .await
+    }
+
+    async fn apply(&self, request: &ExecRequest, context: &str) -> Result<String> {
+        request.validate()?;
+
+        let url = self.endpoint("/enduser-mobile-web/1/enduserAPI/exec/apply");
+        info!("POST {} {}", url, serde_json::to_string(request)?);
+
+        let res = self
+            .client
+            .post(url)
+            .bearer_auth(&self.api_token)
+            .json(request)
+            .send()
+            .await
+            .map_err(|err| MatahoError::from_request(context, err))?;
+        debug!("result: {:?}", res);
+
+        let res: ExecApplyResponse = match res.error_for_status() {
+            Ok(res) => res.json().await,
+            Err(err) => Err(err),
+        }
+        .map_err(|err| MatahoError::from_execution(context, err))?;
+        debug!("exec id: {}", res.exec_id);
+
+        Ok(res.exec_id)
+    }
+
+    pub async fn get_device_states(&self, device: &Device) -> Result<Vec<DeviceState>> {
+        let url = self.endpoint(&format!(
+            "/enduser-mobile-web/1/enduserAPI/setup/devices/{}/states",
+            urlencoding::encode(device.url())
+        ));
+        info!("GET {}", url);
+
+        let res = self
+            .send_json(self.client.get(url), "Failed to get device states")
+            .await?;
+        debug!("result: {:?}", res);
+
+        Ok(res)
+    }
+
+    pub async fn get_current_executions(&self) -> Result<Vec<Execution>> {
+        let url = self.endpoint("/enduser-mobile-web/1/enduserAPI/exec/current");
+        info!("GET {}", url);
+
+        let res = self
+            .send_json(self.client.get(url), "Failed to get current executions")
+            .await?;
+        debug!("result: {:?}", res);
+
+        Ok(res)
+    }
+
+    /// The execution, `None` once the gateway no longer knows it (it has ended).
+    pub async fn get_execution(&self, exec_id: &str) -> Result<Option<Execution>> {
+        let context = "Failed to get execution";
+
+        let url = self.endpoint(&format!(
+            "/enduser-mobile-web/1/enduserAPI/exec/current/{}",
+            urlencoding::encode(exec_id)
+        ));
+        info!("GET {}", url);
+
+        let res = self
+            .client
+            .get(url)
+            .bearer_auth(&self.api_token)
+            .send()
+            .await
+            .map_err(|err| MatahoError::from_request(context, err))?;
+        debug!("result: {:?}", res);
+
+        // Finished executions are unknown to the gateway, or answered with no body
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let body = match res.error_for_status() {
+            Ok(res) => res.text().await,
+            Err(err) => Err(err),
+        }
+        .map_err(|err| MatahoError::from_request(context, err))?;
+
+        match body.trim().is_empty() {
+            true => Ok(None),
+            false => Ok(serde_json::from_str(&body)?),
+        }
+    }
+
+    pub async fn cancel_execution(&self, exec_id: &str) -> Result<()> {
+        self.cancel(&format!(
+            "/enduser-mobile-web/1/enduserAPI/exec/current/setup/{}",
+            urlencoding::encode(exec_id)
+        ))
+        .await
+    }
+
+    pub async fn cancel_all_executions(&self) -> Result<()> {
+        self.cancel("/enduser-mobile-web/1/enduserAPI/exec/current/setup")
+            .await
+    }
+
+    async fn cancel(&self, path: &str) -> Result<()> {
+        let url = self.endpoint(path);
+        info!("DELETE {}", url);
+
+        self.send(self.client.delete(url), "Failed to cancel execution")
+            .await?;
+        Ok(())
+    }
+
+    pub async fn register_event_listener(&self) -> Result<String> {
+        let url = self.endpoint("/enduser-mobile-web/1/enduserAPI/events/register");
+        info!("POST {}", url);
+
+        let res: EventListenerResponse = self
+            .send_json(self.client.post(url), "Failed to register event listener")
+            .await?;
+        debug!("result: {:?}", res);
+
+        Ok(res.id)
+    }
+
+    pub async fn fetch_events(&self, listener_id: &str) -> Result<Vec<Event>> {
+        let url = self.endpoint(&format!(
+            "/enduser-mobile-web/1/enduserAPI/events/{}/fetch",
+            urlencoding::encode(listener_id)
+        ));
+        info!("POST {}", url);
+
+        let res = self
+            .send_json(self.client.post(url), "Failed to fetch events")
+            .await?;
+        debug!("result: {:?}", res);
+
+        Ok(res)
+    }
+
+    pub async fn unregister_event_listener(&self, listener_id: &str) -> Result<()> {
+        let url = self.endpoint(&format!(
+            "/enduser-mobile-web/1/enduserAPI/events/{}/unregister",
+            urlencoding::encode(listener_id)
+        ));
+        info!("POST {}", url);
+
+        self.send(self.client.post(url), "Failed to unregister event listener")
+            .await?;
+        Ok(())
+    }
+
+    fn endpoint(&self, path: &str) -> String {
+        format!("{}:{}/{}", self.hostname, self.port, path)
+    }
+}
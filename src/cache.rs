@@ -0,0 +1,111 @@
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::error::Result;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+
+use crate::{controller::TahomaSetupResponse, model::Configuration};
+
+/// Copy of the gateway `/setup` response, persisted so that commands can run
+/// without contacting the gateway every time.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SetupCache {
+    /// Seconds since the UNIX epoch
+    fetched_at: u64,
+    /// Gateway the setup was fetched from, `hostname:port`
+    #[serde(default)]
+    gateway: Option<String>,
+    setup: TahomaSetupResponse,
+}
+
+impl SetupCache {
+    pub fn new(setup: TahomaSetupResponse) -> SetupCache {
+        let fetched_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        SetupCache {
+            fetched_at,
+            gateway: None,
+            setup,
+        }
+    }
+
+    /// Record the gateway of the configuration as the one the setup was fetched from.
+    pub fn with_gateway(mut self, config: &Configuration) -> SetupCache {
+        self.gateway = Some(gateway_address(config));
+        self
+    }
+
+    /// Same as [`SetupCache::load`], `None` when the setup was fetched from another
+    /// gateway than the one of the configuration (e.g. given with `--host`), or
+    /// when the cache does not tell.
+    pub fn load_for(path: &Path, config: &Configuration) -> Result<Option<SetupCache>> {
+        let cache = SetupCache::load(path)?;
+
+        Ok(cache.filter(|cache| {
+            let address = gateway_address(config);
+            if cache.gateway.as_deref() != Some(&address) {
+                info!(
+                    "ignore setup cache of gateway {:?}, not {}",
+                    cache.gateway, address
+                );
+                return false;
+            }
+
+            true
+        }))
+    }
+
+    pub fn load(path: &Path) -> Result<Option<SetupCache>> {
+        if !path.exists() {
+            info!("no setup cache at `{}`", path.to_string_lossy());
+            return Ok(None);
+        }
+
+        info!("read setup cache from file: `{}`", path.to_string_lossy());
+        let json = fs::read_to_string(path)?;
+        let cache: SetupCache = serde_json::from_str(&json)?;
+        debug!("setup cache fetched at: {}", cache.fetched_at);
+
+        Ok(Some(cache))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        info!("write setup cache to file: `{}`", path.to_string_lossy());
+
+        if let Some(cache_dir) = path.parent() {
+            fs::create_dir_all(cache_dir)?;
+        }
+
+        let json = serde_json::to_string(self)?;
+        fs::write(path, json)?;
+
+        Ok(())
+    }
+
+    pub fn age(&self) -> Duration {
+        let fetched_at = UNIX_EPOCH + Duration::from_secs(self.fetched_at);
+
+        SystemTime::now()
+            .duration_since(fetched_at)
+            .unwrap_or_default()
+    }
+
+    pub fn is_fresh(&self, ttl: Duration) -> bool {
+        self.age() < ttl
+    }
+
+    pub fn into_setup(self) -> TahomaSetupResponse {
+        self.setup
+    }
+}
+
+fn gateway_address(config: &Configuration) -> String {
+    format!("{}:{}", config.hostname, config.port)
+}
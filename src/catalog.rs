@@ -0,0 +1,85 @@
+//! Documentation of the commands and states of Somfy/Overkiz devices.
+//!
+//! The gateway only gives the name and the number of parameters of the commands
+//! of a device. The catalog bundled with mataho (`data/catalog.json`) tells what
+//! the well-known ones do, and the names, types, ranges and units of their
+//! parameters. It is used to check arguments before sending them (see
+//! [`Signature::parse`](crate::params::Signature::parse)) and by `info` and
+//! `help-command`.
+
+use std::{collections::BTreeMap, sync::OnceLock};
+
+use serde::Deserialize;
+
+use crate::params::ParamType;
+
+const BUILTIN_CATALOG: &str = include_str!("../data/catalog.json");
+
+/// Known commands and states, by name.
+#[derive(Debug, Deserialize)]
+pub struct Catalog {
+    commands: BTreeMap<String, CommandDoc>,
+    states: BTreeMap<String, StateDoc>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CommandDoc {
+    pub description: String,
+    #[serde(default)]
+    pub params: Vec<ParamDoc>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ParamDoc {
+    pub name: String,
+    #[serde(flatten)]
+    pub param_type: ParamType,
+    /// Unit of the value, e.g. `%` or `s`
+    pub unit: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StateDoc {
+    pub description: String,
+    #[serde(flatten)]
+    pub value_type: ParamType,
+    pub unit: Option<String>,
+}
+
+impl StateDoc {
+    /// Type of the value followed by its unit, e.g. `integer 0-100 %`.
+    pub fn type_description(&self) -> String {
+        match &self.unit {
+            Some(unit) => format!("{} {}", self.value_type, unit),
+            None => self.value_type.to_string(),
+        }
+    }
+}
+
+/// Catalog bundled with mataho.
+pub fn builtin() -> &'static Catalog {
+    static CATALOG: OnceLock<Catalog> = OnceLock::new();
+
+    CATALOG.get_or_init(|| {
+        serde_json::from_str(BUILTIN_CATALOG).expect("the built-in catalog is valid JSON")
+    })
+}
+
+impl Catalog {
+    pub fn command(&self, name: &str) -> Option<&CommandDoc> {
+        self.commands.get(name)
+    }
+
+    pub fn state(&self, name: &str) -> Option<&StateDoc> {
+        self.states.get(name)
+    }
+
+    /// Names of the documented commands, then of the documented states.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.commands
+            .keys()
+            .chain(self.states.keys())
+            .map(String::as_str)
+    }
+}
@@ -1,7 +1,9 @@
-use clap::{Parser, Subcommand};
-use std::ffi::OsString;
+use clap::{Args, Parser, Subcommand};
+use std::{ffi::OsString, path::PathBuf};
 
-use super::model::{DeviceTypeFilter, MatchMode};
+use mataho::model::{DeviceTypeFilter, MatchMode};
+
+use crate::output::OutputFormat;
 
 #[derive(Debug, Parser)]
 #[command(name = "mataho")]
@@ -12,6 +14,44 @@ pub struct Cli {
     pub command: Commands,
     #[command(flatten)]
     pub verbose: clap_verbosity_flag::Verbosity,
+    /// Ignore the cached device setup and fetch it from the gateway
+    #[arg(long, global = true, action, conflicts_with = "offline")]
+    pub refresh: bool,
+    /// Never contact the gateway, use the cached device setup whatever its age
+    #[arg(long, global = true, action)]
+    pub offline: bool,
+    /// Gateway profile of the config file to use (`default_profile` when not given)
+    #[arg(long, global = true, env = "MATAHO_PROFILE", value_name = "NAME")]
+    pub profile: Option<String>,
+    /// Address of the gateway, instead of the `hostname` of the profile
+    #[arg(long, global = true, value_name = "URL")]
+    pub host: Option<String>,
+    /// Port of the gateway, instead of the `port` of the profile
+    #[arg(long, global = true)]
+    pub port: Option<u16>,
+    /// File holding the API token, instead of the token source of the profile
+    #[arg(long, global = true, value_name = "PATH")]
+    pub token_file: Option<PathBuf>,
+    /// Format of the results, `json`, `yaml`, `csv` and `tsv` are meant for scripts
+    #[arg(
+        short = 'o',
+        long,
+        global = true,
+        value_enum,
+        default_value_t = OutputFormat::Table
+    )]
+    pub output: OutputFormat,
+    /// Leave out the header row of tables, CSV and TSV
+    #[arg(long, global = true, action)]
+    pub no_headers: bool,
+    /// Print each item of `ls`, `info`, `state` and `group ls` as a line from a template, e.g. '{{id}}\t{{label}}'
+    #[arg(
+        long,
+        global = true,
+        value_name = "TEMPLATE",
+        conflicts_with = "output"
+    )]
+    pub format: Option<String>,
 }
 
 #[derive(Debug, Subcommand)]
@@ -19,19 +59,23 @@ pub enum Commands {
     /// Print the list of known local devices
     #[command(visible_alias("ls"))]
     List {
-        /// Only display a subcategory of devices
+        /// Only display the devices of a category (cover, gate, light, climate, sensor,
+        /// security), of a type (e.g. roller-shutter) or with a controllable name
+        /// (`*` matching any text, e.g. io:RollerShutter*)
         #[arg(
             long,
             require_equals = true,
             value_name = "TYPE",
             num_args = 0..=1,
             default_value_t = DeviceTypeFilter::All,
-            default_missing_value = "all",
-            value_enum)]
+            default_missing_value = "all")]
         filter: DeviceTypeFilter,
         /// Use long listing format
         #[arg(short = 'l', action)]
         long_listing: bool,
+        /// List the devices of the gateways of every profile
+        #[arg(long, action)]
+        all_profiles: bool,
     },
     /// Get information about a particular device (id, label, supported actions, etc.)
     Info {
@@ -48,6 +92,26 @@ pub enum Commands {
             value_enum)]
         match_mode: MatchMode,
     },
+    /// Get the current states of a device (closure, open/closed, status, etc.)
+    State {
+        /// ID or label of the device. See match-mode for label matching
+        device: OsString,
+        /// Match mode for the device
+        #[arg(
+            long,
+            require_equals = true,
+            value_name = "MODE",
+            num_args = 0..=1,
+            default_value_t = MatchMode::Fuzzy,
+            default_missing_value = "fuzzy",
+            value_enum)]
+        match_mode: MatchMode,
+    },
+    /// Describe a command or a device state: what it does, its parameters, the devices having it
+    HelpCommand {
+        /// Name of the command or state, e.g. `setMemorized1Position` or `core:ClosureState`
+        name: String,
+    },
     /// Execute a Tahoma action on a single device
     #[command(visible_alias("ex"))]
     Exec {
@@ -65,9 +129,96 @@ pub enum Commands {
         match_mode: MatchMode,
         /// Name of the command
         command: OsString,
-        /// Command arguments
+        /// Command arguments (untyped ones that read as numbers, e.g. `007`, are sent as numbers)
         #[arg(num_args(0..))]
         args: Vec<String>,
+        /// Wait for the execution to complete or fail
+        #[arg(long, action)]
+        wait: bool,
+        /// Maximum number of seconds to wait for the execution (with --wait)
+        #[arg(long, value_name = "SECONDS", default_value_t = 60, requires = "wait")]
+        timeout: u64,
+    },
+    /// Open a device, or every device of a group, with the command each one takes
+    Open {
+        #[command(flatten)]
+        target: IntentTarget,
+    },
+    /// Close a device, or every device of a group, with the command each one takes
+    Close {
+        #[command(flatten)]
+        target: IntentTarget,
+    },
+    /// Stop the movement of a device, or of every device of a group
+    Stop {
+        #[command(flatten)]
+        target: IntentTarget,
+    },
+    /// Move a device, or every device of a group, to a closure percentage
+    Set {
+        #[command(flatten)]
+        target: IntentTarget,
+        /// Closure percentage: 0 = open, 100 = closed
+        #[arg(value_parser = clap::value_parser!(u8).range(0..=100))]
+        percent: u8,
+    },
+    /// Move a device to its favourite (`my`) position
+    My {
+        /// ID or label of the device. See match-mode for label matching
+        device: OsString,
+        /// Match mode for the device
+        #[arg(
+            long,
+            require_equals = true,
+            value_name = "MODE",
+            num_args = 0..=1,
+            default_value_t = MatchMode::Fuzzy,
+            default_missing_value = "fuzzy",
+            value_enum)]
+        match_mode: MatchMode,
+        /// Wait for the execution to complete or fail
+        #[arg(long, action)]
+        wait: bool,
+        /// Maximum number of seconds to wait for the execution (with --wait)
+        #[arg(long, value_name = "SECONDS", default_value_t = 60, requires = "wait")]
+        timeout: u64,
+    },
+    /// List the executions currently running on the gateway
+    Jobs {},
+    /// Cancel a running execution
+    Cancel {
+        /// ID of the execution to cancel, or `all` to cancel every running execution
+        exec_id: OsString,
+    },
+    /// Print gateway events (state changes, executions) as they happen
+    Watch {
+        /// Only print events about this device (ID or label)
+        #[arg(long, conflicts_with = "group")]
+        device: Option<OsString>,
+        /// Only print events about the devices of this group
+        #[arg(long)]
+        group: Option<OsString>,
+        /// Print one JSON object per event, same as `--output json`
+        #[arg(long, action)]
+        json: bool,
+    },
+    /// Find the gateways of the local network (mDNS)
+    Discover {
+        /// Number of seconds to listen for gateways
+        #[arg(long, value_name = "SECONDS", default_value_t = 3)]
+        timeout: u64,
+        /// Write the gateway with this PIN into the config file (the PIN can be omitted when a single gateway is found)
+        #[arg(long, value_name = "PIN", num_args = 0..=1, default_missing_value = "")]
+        save: Option<String>,
+        /// Save the resolved address instead of the `.local` host name
+        #[arg(long, action, requires = "save")]
+        by_address: bool,
+    },
+    /// Pin the certificate currently presented by the gateway (trust on first use)
+    Trust {
+        /// Do not ask for confirmation
+        #[arg(short = 'y', long, action)]
+        yes: bool,
     },
     /// Create and manage groups of devices
     #[command(visible_alias("grp"))]
@@ -75,6 +226,102 @@ pub enum Commands {
         #[command(subcommand)]
         command: GroupCommands,
     },
+    /// Create, inspect and run scenes: commands for several devices sent at once
+    #[command(visible_alias("sc"))]
+    Scene {
+        #[command(subcommand)]
+        command: SceneCommands,
+    },
+    /// Set up, inspect and check the config file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+    /// Store the API token in the system keyring and check it against the gateway
+    Auth {
+        #[command(subcommand)]
+        command: AuthCommands,
+    },
+}
+
+impl Commands {
+    /// Whether the command only touches local data and never needs the gateway.
+    pub fn is_local_only(&self) -> bool {
+        matches!(
+            self,
+            Commands::Group {
+                command: GroupCommands::List {}
+                    | GroupCommands::Create { .. }
+                    | GroupCommands::Delete { .. }
+            } | Commands::Scene {
+                command: SceneCommands::List {}
+                    | SceneCommands::Create { .. }
+                    | SceneCommands::Remove { .. }
+            }
+        )
+    }
+
+    /// Whether the command prints a listing that `--format` can shape.
+    pub fn supports_format(&self) -> bool {
+        matches!(
+            self,
+            Commands::List { .. }
+                | Commands::Info { .. }
+                | Commands::State { .. }
+                | Commands::Group {
+                    command: GroupCommands::List {}
+                }
+        )
+    }
+
+    /// Whether the command talks to the gateway beyond reading the device setup.
+    pub fn needs_gateway(&self) -> bool {
+        matches!(
+            self,
+            Commands::State { .. }
+                | Commands::Exec { .. }
+                | Commands::Open { .. }
+                | Commands::Close { .. }
+                | Commands::Stop { .. }
+                | Commands::Set { .. }
+                | Commands::My { .. }
+                | Commands::Jobs {}
+                | Commands::Cancel { .. }
+                | Commands::Watch { .. }
+                | Commands::Group {
+                    command: GroupCommands::Exec { .. }
+                }
+                | Commands::Scene {
+                    command: SceneCommands::Run { .. }
+                }
+        )
+    }
+}
+
+/// Device or group an intent-level command (`open`, `close`, `stop`, `set`) acts on.
+#[derive(Debug, Args)]
+pub struct IntentTarget {
+    /// ID or label of the device. See match-mode for label matching. Name of the group with --group
+    pub target: OsString,
+    /// The target is a group, each of its devices getting the command it takes
+    #[arg(short, long, action)]
+    pub group: bool,
+    /// Match mode for the device
+    #[arg(
+        long,
+        require_equals = true,
+        value_name = "MODE",
+        num_args = 0..=1,
+        default_value_t = MatchMode::Fuzzy,
+        default_missing_value = "fuzzy",
+        value_enum)]
+    pub match_mode: MatchMode,
+    /// Wait for the execution to complete or fail
+    #[arg(long, action)]
+    pub wait: bool,
+    /// Maximum number of seconds to wait for the execution (with --wait)
+    #[arg(long, value_name = "SECONDS", default_value_t = 60, requires = "wait")]
+    pub timeout: u64,
 }
 
 #[derive(Debug, Subcommand)]
@@ -101,6 +348,9 @@ pub enum GroupCommands {
         group: OsString,
         /// ID or label of the device. See match-mode for label matching
         device: OsString,
+        /// Profile of the gateway the device is on, when it is not the one of the group
+        #[arg(long, value_name = "PROFILE")]
+        from: Option<String>,
     },
     /// Remove a device from an exiting group
     #[command(name = "leave")]
@@ -109,6 +359,9 @@ pub enum GroupCommands {
         group: OsString,
         /// ID or label of the device. See match-mode for label matching
         device: OsString,
+        /// Profile of the gateway the device is on, when it is not the one of the group
+        #[arg(long, value_name = "PROFILE")]
+        from: Option<String>,
     },
     /// Execute a Tahoma action on a group of devices
     #[command(visible_alias("ex"))]
@@ -117,8 +370,124 @@ pub enum GroupCommands {
         group: OsString,
         /// Name of the command
         command: OsString,
-        /// Command arguments
+        /// Command arguments (untyped ones that read as numbers, e.g. `007`, are sent as numbers)
+        #[arg(num_args(0..))]
+        args: Vec<String>,
+        /// Wait for the execution to complete or fail
+        #[arg(long, action)]
+        wait: bool,
+        /// Maximum number of seconds to wait for the execution (with --wait)
+        #[arg(long, value_name = "SECONDS", default_value_t = 60, requires = "wait")]
+        timeout: u64,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SceneCommands {
+    /// List all scenes
+    #[command(visible_alias("ls"))]
+    List {},
+    /// Create a new, empty scene
+    Create {
+        /// Name of the scene
+        name: OsString,
+    },
+    /// Append a command for a device or a group to a scene
+    Add {
+        /// Name of the scene
+        scene: OsString,
+        /// ID or label of the device. See match-mode for label matching. Name of the group with --group
+        target: OsString,
+        /// Name of the command
+        command: OsString,
+        /// Command arguments (untyped ones that read as numbers, e.g. `007`, are sent as numbers)
         #[arg(num_args(0..))]
         args: Vec<String>,
+        /// The target is a group, whose devices are looked up when the scene runs
+        #[arg(short, long, action)]
+        group: bool,
+        /// Match mode for the device
+        #[arg(
+            long,
+            require_equals = true,
+            value_name = "MODE",
+            num_args = 0..=1,
+            default_value_t = MatchMode::Fuzzy,
+            default_missing_value = "fuzzy",
+            value_enum)]
+        match_mode: MatchMode,
+    },
+    /// Delete a scene, or only one of its steps
+    #[command(name = "rm")]
+    Remove {
+        /// Name of the scene
+        name: OsString,
+        /// Position of the step to remove, as printed by `scene show`
+        step: Option<usize>,
+    },
+    /// Print the steps of a scene
+    Show {
+        /// Name of the scene
+        name: OsString,
+    },
+    /// Run every step of a scene as a single execution
+    Run {
+        /// Name of the scene
+        name: OsString,
+        /// Wait for the execution to complete or fail
+        #[arg(long, action)]
+        wait: bool,
+        /// Maximum number of seconds to wait for the execution (with --wait)
+        #[arg(long, value_name = "SECONDS", default_value_t = 60, requires = "wait")]
+        timeout: u64,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommands {
+    /// Set up the gateway of the profile interactively, testing the connection before saving
+    ///
+    /// The address, port and token given with --host, --port, --token-file or their
+    /// environment variables are not asked for. Without address, the gateway is
+    /// looked up on the local network.
+    Init {
+        /// Save the settings without testing the connection
+        #[arg(long, action)]
+        no_check: bool,
+        /// Replace the settings of an already configured profile
+        #[arg(long, action)]
+        force: bool,
+    },
+    /// Print the settings of the profile, API token redacted
+    Show {
+        /// Tell where each value comes from (default, config file, environment, flag)
+        #[arg(long, action)]
+        origin: bool,
+    },
+    /// Change a setting of the profile, e.g. `port 8443` or `tls.insecure false`
+    Set {
+        /// Dotted path of the setting
+        key: String,
+        value: String,
+    },
+    /// Check the config file and the connection to the gateway of every profile
+    Validate {},
+    /// Print the path of the config file
+    Path {},
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AuthCommands {
+    /// Store the API token of the profile in the system keyring, instead of the config file
+    ///
+    /// The token is asked for, checked against the gateway, then the profile is
+    /// set to read it from the keyring (`api_token_keyring = true`) and its other
+    /// token settings are removed from the config file.
+    Login {
+        /// Store the token without checking it against the gateway
+        #[arg(long, action)]
+        no_check: bool,
     },
+    /// Check that the gateway accepts the API token of the profile
+    Check {},
 }
@@ -0,0 +1,470 @@
+//! Schema of the configuration file: the settings `mataho config set` accepts and
+//! the checks run by `mataho config validate`.
+//!
+//! Settings are layered: defaults, then the config file (top level, then the
+//! section of the profile), then `MATAHO_*` environment variables, then command
+//! line flags. [`Overrides`] holds the last two layers.
+
+use std::{collections::BTreeMap, env, fmt, path::PathBuf};
+
+use toml_edit::{DocumentMut, Item, Table, Value};
+
+use crate::{
+    api::{self, Backend},
+    error::{MatahoError, Result},
+    model::{Configuration, Profile},
+    secret::{self, TokenSource},
+};
+
+/// Printed instead of the API token.
+pub const REDACTED: &str = "<redacted>";
+
+/// Settings giving the API token, which override each other as a whole.
+pub const TOKEN_SETTINGS: &[&str] = &[
+    "api_token",
+    "api_token_file",
+    "api_token_command",
+    "api_token_keyring",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingKind {
+    Text,
+    Integer,
+    Port,
+    Boolean,
+    Backend,
+}
+
+/// A setting of the configuration file.
+#[derive(Debug)]
+pub struct Setting {
+    /// Dotted path of the setting, e.g. `tls.fingerprint`
+    pub key: &'static str,
+    pub kind: SettingKind,
+    /// Whether a profile can override it, otherwise it only exists at the top level
+    pub per_profile: bool,
+}
+
+pub const SETTINGS: &[Setting] = &[
+    Setting::new("hostname", SettingKind::Text),
+    Setting::new("port", SettingKind::Port),
+    Setting::new("api_token", SettingKind::Text),
+    Setting::new("api_token_file", SettingKind::Text),
+    Setting::new("api_token_command", SettingKind::Text),
+    Setting::new("api_token_keyring", SettingKind::Boolean),
+    Setting::new("cache_ttl", SettingKind::Integer),
+    Setting::new("timeout", SettingKind::Integer),
+    Setting::new("backend", SettingKind::Backend),
+    Setting::new("simulator_setup", SettingKind::Text),
+    Setting::new("record_file", SettingKind::Text),
+    Setting::new("tls.ca_file", SettingKind::Text),
+    Setting::new("tls.fingerprint", SettingKind::Text),
+    Setting::new("tls.insecure", SettingKind::Boolean),
+    Setting {
+        key: "default_profile",
+        kind: SettingKind::Text,
+        per_profile: false,
+    },
+];
+
+impl Setting {
+    const fn new(key: &'static str, kind: SettingKind) -> Setting {
+        Setting {
+            key,
+            kind,
+            per_profile: true,
+        }
+    }
+
+    /// Convert the value given on the command line to the type of the setting.
+    pub fn parse(&self, raw: &str) -> Result<Value> {
+        let invalid = |expected: &str| {
+            MatahoError::Config(format!(
+                "Invalid value `{}` for `{}`: {} expected",
+                raw, self.key, expected
+            ))
+        };
+
+        match self.kind {
+            SettingKind::Text => Ok(Value::from(raw)),
+            SettingKind::Integer => raw
+                .parse::<i64>()
+                .ok()
+                .filter(|value| *value >= 0)
+                .map(Value::from)
+                .ok_or_else(|| invalid("a positive integer")),
+            SettingKind::Port => raw
+                .parse::<u16>()
+                .ok()
+                .filter(|port| *port > 0)
+                .map(|port| Value::from(i64::from(port)))
+                .ok_or_else(|| invalid("a port number")),
+            SettingKind::Boolean => raw
+                .parse::<bool>()
+                .map(Value::from)
+                .map_err(|_| invalid("`true` or `false`")),
+            SettingKind::Backend => match raw {
+                "http" | "simulator" => Ok(Value::from(raw)),
+                _ => Err(invalid("`http` or `simulator`")),
+            },
+        }
+    }
+
+    /// Write the value in the table of a profile, creating the intermediate tables.
+    pub fn set(&self, settings: &mut Table, value: Value) -> Result<()> {
+        let mut table = settings;
+        let mut path: Vec<&str> = self.key.split('.').collect();
+        let name = path.pop().expect("setting keys are not empty");
+
+        for section in path {
+            table = table
+                .entry(section)
+                .or_insert_with(toml_edit::table)
+                .as_table_mut()
+                .ok_or_else(|| {
+                    MatahoError::Config(format!(
+                        "`{}` must be a section of the config file",
+                        section
+                    ))
+                })?;
+        }
+
+        table[name] = Item::Value(value);
+        Ok(())
+    }
+
+    /// Environment variable overriding the setting, e.g. `MATAHO_API_TOKEN` for `api_token`.
+    /// Only the settings of a profile outside the `tls` section have one.
+    pub fn env_var(&self) -> Option<String> {
+        if !self.per_profile || self.key.contains('.') {
+            return None;
+        }
+
+        Some(format!("MATAHO_{}", self.key.to_uppercase()))
+    }
+}
+
+/// Where the effective value of a setting comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Origin {
+    Default,
+    /// Top level of the config file
+    File,
+    /// `[profiles.<name>]` section of the config file
+    Section(String),
+    /// Environment variable
+    Env(String),
+    /// Command line flag
+    Flag(&'static str),
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::Default => write!(f, "default"),
+            Origin::File => write!(f, "config file"),
+            Origin::Section(profile) => write!(f, "config file, [profiles.{}]", profile),
+            Origin::Env(var) => write!(f, "env {}", var),
+            Origin::Flag(flag) => write!(f, "flag {}", flag),
+        }
+    }
+}
+
+/// Settings given outside the config file, which win over it.
+#[derive(Debug, Clone, Default)]
+pub struct Overrides {
+    pub settings: Profile,
+    /// Origin of each setting of `settings`, by dotted path
+    pub origins: BTreeMap<&'static str, Origin>,
+}
+
+impl Overrides {
+    /// Settings given by `MATAHO_*` environment variables, see [`Setting::env_var`].
+    /// Only one of the variables giving the API token can be set.
+    pub fn from_env() -> Result<Overrides> {
+        let mut overrides = Overrides::default();
+        let mut token_vars = Vec::new();
+
+        for setting in SETTINGS.iter() {
+            let var = match setting.env_var() {
+                Some(var) => var,
+                None => continue,
+            };
+
+            if let Ok(value) = env::var(&var) {
+                if TOKEN_SETTINGS.contains(&setting.key) {
+                    token_vars.push(var.clone());
+                }
+                overrides
+                    .set(setting.key, &value, Origin::Env(var.clone()))
+                    .map_err(|err| MatahoError::Config(format!("{}: {}", var, err)))?;
+            }
+        }
+
+        if token_vars.len() > 1 {
+            return Err(MatahoError::Config(format!(
+                "Several API token variables are set: {}, keep only one",
+                token_vars.join(", ")
+            )));
+        }
+
+        Ok(overrides)
+    }
+
+    /// Override the setting with a value given as text, replacing any previous override.
+    /// Giving the API token replaces all its previous sources.
+    pub fn set(&mut self, key: &str, raw: &str, origin: Origin) -> Result<()> {
+        let setting = find_setting(key)?;
+        let value = setting.parse(raw)?;
+        let settings = &mut self.settings;
+
+        if TOKEN_SETTINGS.contains(&setting.key) {
+            settings.api_token = None;
+            settings.api_token_file = None;
+            settings.api_token_command = None;
+            settings.api_token_keyring = None;
+            self.origins.retain(|key, _| !TOKEN_SETTINGS.contains(key));
+        }
+
+        match setting.key {
+            "hostname" => settings.hostname = Some(raw.to_string()),
+            "port" => settings.port = value.as_integer().map(|port| port as i32),
+            "api_token" => settings.api_token = Some(raw.to_string()),
+            "api_token_file" => settings.api_token_file = Some(PathBuf::from(raw)),
+            "api_token_command" => settings.api_token_command = Some(raw.to_string()),
+            "api_token_keyring" => settings.api_token_keyring = value.as_bool(),
+            "cache_ttl" => settings.cache_ttl = value.as_integer().map(|ttl| ttl as u64),
+            "timeout" => settings.timeout = value.as_integer().map(|timeout| timeout as u64),
+            "backend" if raw == "simulator" => settings.backend = Some(Backend::Simulator),
+            "backend" => settings.backend = Some(Backend::Http),
+            "simulator_setup" => settings.simulator_setup = Some(PathBuf::from(raw)),
+            "record_file" => settings.record_file = Some(PathBuf::from(raw)),
+            _ => {
+                return Err(MatahoError::Config(format!(
+                    "`{}` can only be set in the config file",
+                    setting.key
+                )))
+            }
+        }
+
+        self.origins.insert(setting.key, origin);
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.origins.is_empty()
+    }
+}
+
+/// Origin of the effective value of each setting of a profile, given the top
+/// level of the config file, the section of the profile and the overrides.
+/// The API token settings come from the same layer, see [`TOKEN_SETTINGS`].
+pub fn origins(
+    top_level: &Profile,
+    section: Option<(&str, &Profile)>,
+    overrides: &Overrides,
+) -> Vec<(&'static Setting, Origin)> {
+    SETTINGS
+        .iter()
+        .filter(|setting| setting.per_profile)
+        .map(|setting| {
+            let key = setting.key;
+            let layer_sets = |layer: &Profile| match TOKEN_SETTINGS.contains(&key) {
+                true => layer.sets_token(),
+                false => layer.is_set(key),
+            };
+            // The layer of the effective value may leave it unset when it gives
+            // another source of the API token
+            let origin = if layer_sets(&overrides.settings) {
+                overrides.origins.get(key).cloned()
+            } else if let Some((name, section)) = section.filter(|(_, s)| layer_sets(s)) {
+                section
+                    .is_set(key)
+                    .then(|| Origin::Section(name.to_string()))
+            } else {
+                top_level.is_set(key).then_some(Origin::File)
+            }
+            .unwrap_or(Origin::Default);
+
+            (setting, origin)
+        })
+        .collect()
+}
+
+/// Fail when a setting without default value is missing, unless the gateway is not used.
+pub fn require_gateway(config: &Configuration) -> Result<()> {
+    if config.backend != Backend::Http {
+        return Ok(());
+    }
+
+    let mut missing = Vec::new();
+    if config.hostname.is_empty() {
+        missing.push("`hostname` (MATAHO_HOSTNAME)");
+    }
+    if secret::token_source(config)?.is_none() {
+        missing.push("`api_token` (MATAHO_API_TOKEN), `api_token_file` or `api_token_command`");
+    }
+
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    let hint = missing.join(" and ");
+    Err(MatahoError::Config(format!(
+        "No gateway set up for profile `{}`: run `mataho config init`, or set {}",
+        config.profile, hint
+    )))
+}
+
+/// Find a setting by its dotted path.
+pub fn find_setting(key: &str) -> Result<&'static Setting> {
+    SETTINGS
+        .iter()
+        .find(|setting| setting.key == key)
+        .ok_or_else(|| {
+            let known = SETTINGS
+                .iter()
+                .map(|setting| setting.key)
+                .collect::<Vec<&str>>()
+                .join(", ");
+            MatahoError::Config(format!(
+                "Unknown setting `{}`, expected one of: {}",
+                key, known
+            ))
+        })
+}
+
+/// Keys of the config file that are not settings (typos, removed settings), as dotted paths.
+pub fn unknown_keys(document: &DocumentMut) -> Vec<String> {
+    let mut unknown = Vec::new();
+    collect_unknown_keys(document.as_table(), "", false, &mut unknown);
+
+    if let Some(profiles) = document.get("profiles").and_then(Item::as_table_like) {
+        for (name, profile) in profiles.iter() {
+            if let Some(profile) = profile.as_table() {
+                collect_unknown_keys(profile, &format!("profiles.{}.", name), true, &mut unknown);
+            }
+        }
+    }
+
+    unknown
+}
+
+fn collect_unknown_keys(table: &Table, prefix: &str, in_profile: bool, unknown: &mut Vec<String>) {
+    for (key, item) in table.iter() {
+        if !in_profile && key == "profiles" {
+            continue;
+        }
+
+        let known = |key: &str| {
+            SETTINGS
+                .iter()
+                .any(|setting| setting.key == key && (setting.per_profile || !in_profile))
+        };
+
+        match item.as_table() {
+            Some(section) if key == "tls" => {
+                for (name, _) in section.iter() {
+                    let key = format!("tls.{}", name);
+                    if !known(&key) {
+                        unknown.push(format!("{}{}", prefix, key));
+                    }
+                }
+            }
+            _ if known(key) => {}
+            _ => unknown.push(format!("{}{}", prefix, key)),
+        }
+    }
+}
+
+/// Problems of the resolved settings of a profile that do not need the gateway to be found.
+pub fn check_profile(config: &Configuration) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    // The simulator has no address and needs no token
+    if config.backend == Backend::Http {
+        if !config.hostname.starts_with("https://") && !config.hostname.starts_with("http://") {
+            problems.push(format!(
+                "`hostname` must start with `https://`: `{}`",
+                config.hostname
+            ));
+        }
+
+        if !(1..=65535).contains(&config.port) {
+            problems.push(format!("`port` is not a valid port: {}", config.port));
+        }
+
+        match secret::token_source(config) {
+            Ok(None) => problems.push(
+                "no API token: set `api_token`, `api_token_file` or `api_token_command`, or run `mataho auth login`"
+                    .to_string(),
+            ),
+            Ok(Some(TokenSource::File(path))) if !path.exists() => problems.push(format!(
+                "`api_token_file` does not exist: {}",
+                path.to_string_lossy()
+            )),
+            Ok(_) => {}
+            Err(err) => problems.push(err.to_string()),
+        }
+    }
+
+    if config.timeout == 0 {
+        problems.push("`timeout` must be at least 1 second".to_string());
+    }
+
+    if let Some(path) = &config.simulator_setup {
+        if config.backend == Backend::Simulator && !path.exists() {
+            problems.push(format!(
+                "`simulator_setup` does not exist: {}",
+                path.to_string_lossy()
+            ));
+        }
+    }
+
+    if let Some(path) = &config.tls.ca_file {
+        if !path.exists() {
+            problems.push(format!(
+                "`tls.ca_file` does not exist: {}",
+                path.to_string_lossy()
+            ));
+        }
+    }
+
+    if let Some(fingerprint) = &config.tls.fingerprint {
+        let digits = fingerprint
+            .chars()
+            .filter(|c| c.is_ascii_hexdigit())
+            .count();
+        if digits != 64 {
+            problems.push(format!(
+                "`tls.fingerprint` is not a SHA-256 fingerprint: `{}`",
+                fingerprint
+            ));
+        }
+    }
+
+    problems
+}
+
+/// Fetch the device setup with the settings of the profile and return the number of devices.
+pub fn check_connection(config: &Configuration) -> Result<usize> {
+    let api = api::from_configuration(config)?;
+    Ok(api.get_setup()?.devices.len())
+}
+
+/// Copy of the settings that can be printed: the API token is hidden.
+pub fn redacted(config: &Configuration) -> Configuration {
+    let mut config = config.clone();
+    if !config.api_token.is_empty() {
+        config.api_token = REDACTED.to_string();
+    }
+
+    for profile in config.profiles.values_mut() {
+        if profile.api_token.is_some() {
+            profile.api_token = Some(REDACTED.to_string());
+        }
+    }
+
+    config
+}
@@ -1,16 +1,30 @@
-use anyhow::{anyhow, Context, Result};
 use log::{debug, info};
-use serde::Deserialize;
-use serde_json::json;
+use reqwest::{
+    blocking::{RequestBuilder, Response},
+    StatusCode,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use std::time::Duration;
 
-use crate::Configuration;
+use crate::{
+    api::TahomaApi,
+    error::{MatahoError, Result},
+    secret::LazyToken,
+    tls::{self, TlsConfig},
+    Configuration,
+};
 
-use super::device::Device;
+use super::device::{Device, DeviceState};
+use super::events::Event;
+use super::execution::Execution;
 
 pub struct TahomaApiController {
     hostname: String,
     port: i32,
-    api_token: String,
+    api_token: LazyToken,
+    timeout: Duration,
+    tls: TlsConfig,
 }
 
 impl TahomaApiController {
@@ -18,125 +32,400 @@ impl TahomaApiController {
         TahomaApiController {
             hostname: configuration.hostname.clone(),
             port: configuration.port,
-            api_token: configuration.api_token.clone(),
+            api_token: LazyToken::new(configuration),
+            timeout: Duration::from_secs(configuration.timeout),
+            tls: configuration.tls.clone(),
         }
     }
 
-    fn get_client() -> reqwest::blocking::Client {
-        reqwest::blocking::ClientBuilder::new()
-            .danger_accept_invalid_certs(true)
+    fn get_client(&self) -> Result<reqwest::blocking::Client> {
+        let mut builder = reqwest::blocking::ClientBuilder::new().timeout(self.timeout);
+        if tls::uses_tls(&self.hostname) {
+            builder = builder.use_preconfigured_tls(tls::client_config(&self.tls)?);
+        }
+
+        builder
             .build()
-            .unwrap()
+            .map_err(|err| MatahoError::from_request("Failed to build the HTTP client", err))
     }
 
-    pub fn get_setup(&self) -> Result<TahomaSetupResponse> {
-        let client = Self::get_client();
-
-        let url = self.endpoint("/enduser-mobile-web/1/enduserAPI/setup");
-        info!("GET {}", url);
-
-        let res = client
-            .get(url)
-            .bearer_auth(&self.api_token)
+    /// Send the request and check its status, `context` prefixes the error message.
+    fn send(&self, request: RequestBuilder, context: &str) -> Result<Response> {
+        let res = request
+            .bearer_auth(self.api_token.get()?)
             .send()
-            .context("Failed to get setup. Check your configuration file.")?;
-
-        let res = res.json()?;
+            .map_err(|err| MatahoError::from_request(context, err))?;
         debug!("result: {:?}", res);
 
-        Ok(res)
+        res.error_for_status()
+            .map_err(|err| MatahoError::from_request(context, err))
+    }
+
+    fn send_json<T: DeserializeOwned>(&self, request: RequestBuilder, context: &str) -> Result<T> {
+        self.send(request, context)?
+            .json()
+            .map_err(|err| MatahoError::from_request(context, err))
     }
 
-    pub fn execute(&self, device: &Device, command: &str, params: &Vec<String>) -> Result<()> {
-        let client = Self::get_client();
-
-        let payload = json!({
-            "label": format!("Exec {} on {}", command, device.url()),
-            "actions":
-            [
-                {
-                    "commands": [
-                        {
-                            "name": command,
-                            "parameters": params
-                        }
-                    ],
-                    "deviceURL": device.url()
-                }
-            ]
-        });
+    fn apply(&self, request: &ExecRequest, context: &str) -> Result<String> {
+        request.validate()?;
+        let client = self.get_client()?;
 
         let url = self.endpoint("/enduser-mobile-web/1/enduserAPI/exec/apply");
-        info!("POST {} {}", url, serde_json::to_string(&payload)?);
+        info!("POST {} {}", url, serde_json::to_string(request)?);
 
         let res = client
             .post(url)
-            .bearer_auth(&self.api_token)
-            .json(&payload)
-            .send()?;
+            .bearer_auth(self.api_token.get()?)
+            .json(request)
+            .send()
+            .map_err(|err| MatahoError::from_request(context, err))?;
+        debug!("result: {:?}", res);
+
+        let res: ExecApplyResponse = res
+            .error_for_status()
+            .and_then(|res| res.json())
+            .map_err(|err| MatahoError::from_execution(context, err))?;
+        debug!("exec id: {}", res.exec_id);
+
+        Ok(res.exec_id)
+    }
 
+    fn cancel(&self, path: &str) -> Result<()> {
+        let client = self.get_client()?;
+
+        let url = self.endpoint(path);
+        info!("DELETE {}", url);
+
+        self.send(client.delete(url), "Failed to cancel execution")?;
+        Ok(())
+    }
+
+    fn endpoint(&self, path: &str) -> String {
+        format!("{}:{}/{}", self.hostname, self.port, path)
+    }
+}
+
+impl TahomaApi for TahomaApiController {
+    fn get_setup(&self) -> Result<TahomaSetupResponse> {
+        let client = self.get_client()?;
+
+        let url = self.endpoint("/enduser-mobile-web/1/enduserAPI/setup");
+        info!("GET {}", url);
+
+        let res = self.send_json(
+            client.get(url),
+            "Failed to get setup. Check your configuration file",
+        )?;
         debug!("result: {:?}", res);
 
-        match res.error_for_status() {
-            Ok(_res) => Ok(()),
-            Err(err) => Err(anyhow!("Failed to execute command: {}", err)),
-        }
+        Ok(res)
     }
 
-    pub fn execute_multiple(
+    fn execute(&self, device: &Device, command: &str, params: &[String]) -> Result<String> {
+        self.execute_multiple(vec![device], command, params)
+    }
+
+    fn execute_multiple(
         &self,
         devices: Vec<&Device>,
         command: &str,
-        params: &Vec<String>,
-    ) -> Result<()> {
-        let client = Self::get_client();
-
-        let mut all_actions = Vec::new();
-        for device in devices.iter() {
-            let action = json!({
-                "commands": [
-                    {
-                        "name": command,
-                        "parameters": params
-                    }
-                ],
-                "deviceURL": device.url()
-            });
-
-            all_actions.push(action);
-        }
+        params: &[String],
+    ) -> Result<String> {
+        let batch = ExecAction::same_command(&devices, command, params)?;
 
-        let payload = json!({
-            "label": format!("Exec {} on {} devices", command, devices.len()),
-            "actions": all_actions
-        });
+        self.execute_batch(&exec_label(command, &devices), &batch)
+    }
 
-        let url = self.endpoint("/enduser-mobile-web/1/enduserAPI/exec/apply");
-        info!("POST {} {}", url, serde_json::to_string(&payload)?);
+    fn execute_batch(&self, label: &str, batch: &[ExecAction]) -> Result<String> {
+        check_supported(batch)?;
+
+        self.apply(&ExecRequest::new(label, batch), "Failed to execute command")
+    }
+
+    fn get_device_states(&self, device: &Device) -> Result<Vec<DeviceState>> {
+        let client = self.get_client()?;
+
+        let url = self.endpoint(&format!(
+            "/enduser-mobile-web/1/enduserAPI/setup/devices/{}/states",
+            urlencoding::encode(device.url())
+        ));
+        info!("GET {}", url);
+
+        let res = self.send_json(client.get(url), "Failed to get device states")?;
+        debug!("result: {:?}", res);
+
+        Ok(res)
+    }
+
+    fn get_current_executions(&self) -> Result<Vec<Execution>> {
+        let client = self.get_client()?;
+
+        let url = self.endpoint("/enduser-mobile-web/1/enduserAPI/exec/current");
+        info!("GET {}", url);
+
+        let res = self.send_json(client.get(url), "Failed to get current executions")?;
+        debug!("result: {:?}", res);
+
+        Ok(res)
+    }
+
+    fn get_execution(&self, exec_id: &str) -> Result<Option<Execution>> {
+        let client = self.get_client()?;
+        let context = "Failed to get execution";
+
+        let url = self.endpoint(&format!(
+            "/enduser-mobile-web/1/enduserAPI/exec/current/{}",
+            urlencoding::encode(exec_id)
+        ));
+        info!("GET {}", url);
 
         let res = client
-            .post(url)
-            .bearer_auth(&self.api_token)
-            .json(&payload)
-            .send()?;
+            .get(url)
+            .bearer_auth(self.api_token.get()?)
+            .send()
+            .map_err(|err| MatahoError::from_request(context, err))?;
+        debug!("result: {:?}", res);
+
+        // Finished executions are unknown to the gateway, or answered with no body
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let body = res
+            .error_for_status()
+            .and_then(|res| res.text())
+            .map_err(|err| MatahoError::from_request(context, err))?;
 
+        match body.trim().is_empty() {
+            true => Ok(None),
+            false => Ok(serde_json::from_str(&body)?),
+        }
+    }
+
+    fn cancel_execution(&self, exec_id: &str) -> Result<()> {
+        self.cancel(&format!(
+            "/enduser-mobile-web/1/enduserAPI/exec/current/setup/{}",
+            urlencoding::encode(exec_id)
+        ))
+    }
+
+    fn cancel_all_executions(&self) -> Result<()> {
+        self.cancel("/enduser-mobile-web/1/enduserAPI/exec/current/setup")
+    }
+
+    fn register_event_listener(&self) -> Result<String> {
+        let client = self.get_client()?;
+
+        let url = self.endpoint("/enduser-mobile-web/1/enduserAPI/events/register");
+        info!("POST {}", url);
+
+        let res: EventListenerResponse =
+            self.send_json(client.post(url), "Failed to register event listener")?;
         debug!("result: {:?}", res);
 
-        match res.error_for_status() {
-            Ok(_res) => Ok(()),
-            Err(err) => Err(anyhow!(
-                "Failed to execute command on multiple devices: {}",
-                err
-            )),
+        Ok(res.id)
+    }
+
+    fn fetch_events(&self, listener_id: &str) -> Result<Vec<Event>> {
+        let client = self.get_client()?;
+
+        let url = self.endpoint(&format!(
+            "/enduser-mobile-web/1/enduserAPI/events/{}/fetch",
+            urlencoding::encode(listener_id)
+        ));
+        info!("POST {}", url);
+
+        let res = self.send_json(client.post(url), "Failed to fetch events")?;
+        debug!("result: {:?}", res);
+
+        Ok(res)
+    }
+
+    fn unregister_event_listener(&self, listener_id: &str) -> Result<()> {
+        let client = self.get_client()?;
+
+        let url = self.endpoint(&format!(
+            "/enduser-mobile-web/1/enduserAPI/events/{}/unregister",
+            urlencoding::encode(listener_id)
+        ));
+        info!("POST {}", url);
+
+        self.send(client.post(url), "Failed to unregister event listener")?;
+        Ok(())
+    }
+}
+
+/// Commands sent to one device by [`TahomaApi::execute_batch`], in order.
+#[derive(Debug, Clone)]
+pub struct ExecAction<'a> {
+    pub device: &'a Device,
+    pub commands: Vec<ExecCommand>,
+}
+
+impl<'a> ExecAction<'a> {
+    /// The same command for each device, as sent by `execute_multiple`, with the
+    /// arguments typed for each device (see [`ExecCommand::parse`]).
+    pub fn same_command(
+        devices: &[&'a Device],
+        command: &str,
+        args: &[String],
+    ) -> Result<Vec<ExecAction<'a>>> {
+        devices
+            .iter()
+            .map(|device| {
+                Ok(ExecAction {
+                    device,
+                    commands: vec![ExecCommand::parse(device, command, args)?],
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExecCommand {
+    pub name: String,
+    #[serde(rename = "parameters")]
+    pub params: Vec<Value>,
+}
+
+impl ExecCommand {
+    pub fn new(name: &str, params: Vec<Value>) -> ExecCommand {
+        ExecCommand {
+            name: name.to_string(),
+            params,
         }
     }
 
-    fn endpoint(&self, path: &str) -> String {
-        format!("{}:{}/{}", self.hostname, self.port, path)
+    /// Command of the device with the arguments given on the command line,
+    /// converted to the types of its signature and checked against it.
+    pub fn parse(device: &Device, name: &str, args: &[String]) -> Result<ExecCommand> {
+        let action = device
+            .find_action(name)
+            .ok_or_else(|| MatahoError::CommandNotSupported {
+                device: device.label().to_string(),
+                command: name.to_string(),
+            })?;
+
+        let signature = action.signature();
+        let params = signature
+            .convert(args)
+            .map_err(|reason| MatahoError::InvalidParameters {
+                device: device.label().to_string(),
+                command: name.to_string(),
+                reason,
+                expected: signature.to_string(),
+            })?;
+
+        Ok(ExecCommand::new(name, params))
     }
 }
 
-#[derive(Debug, Deserialize)]
+/// Body of `/exec/apply`: the commands of each device, run by the gateway as
+/// a single execution.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExecRequest {
+    pub label: String,
+    pub actions: Vec<ExecRequestAction>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExecRequestAction {
+    #[serde(rename = "deviceURL")]
+    pub device_url: String,
+    pub commands: Vec<ExecCommand>,
+}
+
+impl ExecRequest {
+    pub fn new(label: &str, batch: &[ExecAction]) -> ExecRequest {
+        ExecRequest {
+            label: label.to_string(),
+            actions: batch
+                .iter()
+                .map(|action| ExecRequestAction {
+                    device_url: action.device.url().to_string(),
+                    commands: action.commands.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Check the request before sending it: the gateway rejects executions without
+    /// actions or commands, and a device must get all its commands in one action.
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |reason: String| {
+            Err(MatahoError::InvalidExecution {
+                label: self.label.clone(),
+                reason,
+            })
+        };
+
+        if self.actions.is_empty() {
+            return invalid("no actions".to_string());
+        }
+
+        for (index, action) in self.actions.iter().enumerate() {
+            if action.commands.is_empty() {
+                return invalid(format!("no commands for `{}`", action.device_url));
+            }
+            if action
+                .commands
+                .iter()
+                .any(|command| command.name.is_empty())
+            {
+                return invalid(format!("unnamed command for `{}`", action.device_url));
+            }
+            if self.actions[..index]
+                .iter()
+                .any(|other| other.device_url == action.device_url)
+            {
+                return invalid(format!(
+                    "several actions for `{}`, give it all its commands in one action",
+                    action.device_url
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Label of the execution of a command on the given devices.
+pub(crate) fn exec_label(command: &str, devices: &[&Device]) -> String {
+    match devices {
+        [device] => format!("Exec {} on {}", command, device.url()),
+        devices => format!("Exec {} on {} devices", command, devices.len()),
+    }
+}
+
+/// Fail unless every device of the batch supports its commands.
+pub(crate) fn check_supported(batch: &[ExecAction]) -> Result<()> {
+    for action in batch.iter() {
+        for command in action.commands.iter() {
+            if !action.device.supports_action(&command.name) {
+                return Err(MatahoError::CommandNotSupported {
+                    device: action.device.label().to_string(),
+                    command: command.name.clone(),
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct TahomaSetupResponse {
     pub devices: Vec<Device>,
 }
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct EventListenerResponse {
+    pub(crate) id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ExecApplyResponse {
+    #[serde(rename = "execId")]
+    pub(crate) exec_id: String,
+}
@@ -1,9 +1,9 @@
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
-use crate::model::DeviceTypeFilter;
+use crate::{device_type::DeviceTypeRegistry, model::DeviceTypeFilter, params::Signature};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Device {
     label: String,
 
@@ -18,8 +18,7 @@ pub struct Device {
 
 impl Device {
     pub fn id(&self) -> &str {
-        let parts: Vec<&str> = self.url.split('/').collect();
-        parts.last().unwrap()
+        id_from_url(&self.url)
     }
 
     pub fn label(&self) -> &str {
@@ -30,13 +29,11 @@ impl Device {
         &self.controllable_name
     }
 
+    #[deprecated(note = "use `DeviceTypeRegistry::lookup`, which also knows the user types")]
     pub fn device_type(&self) -> &str {
-        match self.controllable_name.as_str() {
-            "io:GarageOpenerIOComponent" => "garage opener",
-            "io:SlidingDiscreteGateOpenerIOComponent" => "sliding gate",
-            "io:RollerShutterWithLowSpeedManagementIOComponent" => "roller shutter",
-            _ => "<unknown>",
-        }
+        DeviceTypeRegistry::builtin()
+            .lookup(&self.controllable_name)
+            .map_or("<unknown>", |device_type| device_type.name.as_str())
     }
 
     pub fn definition(&self) -> &DeviceDefinition {
@@ -48,21 +45,28 @@ impl Device {
     }
 
     pub fn supports_action(&self, action: &str) -> bool {
+        self.find_action(action).is_some()
+    }
+
+    pub fn find_action(&self, action: &str) -> Option<&DeviceAction> {
         self.definition()
             .actions()
             .iter()
-            .any(|dev_action| dev_action.name() == action)
+            .find(|dev_action| dev_action.name() == action)
     }
 
+    #[deprecated(note = "use `DeviceTypeRegistry::matches`, which also knows the user types")]
     pub fn has_type(&self, filter: DeviceTypeFilter) -> bool {
-        if filter == DeviceTypeFilter::All {
-            return true;
-        }
-
-        self.controllable_name == filter.as_str()
+        DeviceTypeRegistry::builtin().matches(self, &filter)
     }
 }
 
+/// Extract the device ID (last part of the URL) from a device URL.
+pub fn id_from_url(url: &str) -> &str {
+    let parts: Vec<&str> = url.split('/').collect();
+    parts.last().unwrap()
+}
+
 impl fmt::Display for Device {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
@@ -75,19 +79,42 @@ impl fmt::Display for Device {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DeviceDefinition {
     #[serde(rename = "commands")]
     actions: Vec<DeviceAction>,
+    /// States the device reports (older caches do not have them)
+    #[serde(default)]
+    states: Vec<StateDefinition>,
 }
 
 impl DeviceDefinition {
     pub fn actions(&self) -> &Vec<DeviceAction> {
         &self.actions
     }
+
+    pub fn states(&self) -> &Vec<StateDefinition> {
+        &self.states
+    }
+
+    pub fn has_state(&self, name: &str) -> bool {
+        self.states.iter().any(|state| state.name() == name)
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateDefinition {
+    #[serde(rename = "qualifiedName")]
+    name: String,
+}
+
+impl StateDefinition {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DeviceAction {
     #[serde(rename = "nparams")]
     params_count: i32,
@@ -109,14 +136,109 @@ impl DeviceAction {
     pub fn params_signature(&self) -> Option<&str> {
         self.params_signature.as_deref()
     }
+
+    /// Typed parameters of the command, see [`Signature::parse`].
+    pub fn signature(&self) -> Signature {
+        Signature::parse(&self.name, self.params_count, self.params_signature())
+    }
 }
 
 impl fmt::Display for DeviceAction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if let Some(params) = &self.params_signature {
-            return write!(f, "{}: [{}] ({})", self.name, params, self.params_count);
+        write!(f, "{}", self.signature())
+    }
+}
+
+/// Live state reported by the gateway for a device (e.g. `core:ClosureState`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "RawDeviceState")]
+pub struct DeviceState {
+    name: String,
+    value: StateValue,
+}
+
+impl DeviceState {
+    pub fn new(name: &str, value: StateValue) -> DeviceState {
+        DeviceState {
+            name: name.to_string(),
+            value,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &StateValue {
+        &self.value
+    }
+}
+
+impl fmt::Display for DeviceState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.name, self.value)
+    }
+}
+
+/// Value of a device state, typed according to the `type` field sent by the gateway.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum StateValue {
+    Integer(i64),
+    Float(f64),
+    Text(String),
+    Boolean(bool),
+    Json(serde_json::Value),
+}
+
+impl fmt::Display for StateValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateValue::Integer(val) => write!(f, "{}", val),
+            StateValue::Float(val) => write!(f, "{}", val),
+            StateValue::Text(val) => write!(f, "{}", val),
+            StateValue::Boolean(val) => write!(f, "{}", val),
+            StateValue::Json(val) => write!(f, "{}", val),
         }
+    }
+}
+
+#[derive(Deserialize)]
+struct RawDeviceState {
+    name: String,
+    #[serde(rename = "type")]
+    state_type: i32,
+    value: serde_json::Value,
+}
 
-        write!(f, "{}", self.name)
+impl From<RawDeviceState> for DeviceState {
+    fn from(raw: RawDeviceState) -> Self {
+        // See `DataType` in the Overkiz API: 1 = int, 2 = float, 3 = string, 6 = boolean.
+        // Events send every value as a string, so parse those back to their declared type.
+        let value = match (raw.state_type, raw.value) {
+            (1, serde_json::Value::String(val)) if val.parse::<i64>().is_ok() => {
+                StateValue::Integer(val.parse().unwrap())
+            }
+            (2, serde_json::Value::String(val)) if val.parse::<f64>().is_ok() => {
+                StateValue::Float(val.parse().unwrap())
+            }
+            (6, serde_json::Value::String(val)) if val.parse::<bool>().is_ok() => {
+                StateValue::Boolean(val.parse().unwrap())
+            }
+            (1, serde_json::Value::Number(val)) if val.is_i64() => {
+                StateValue::Integer(val.as_i64().unwrap())
+            }
+            (1 | 2, serde_json::Value::Number(val)) if val.as_f64().is_some() => {
+                StateValue::Float(val.as_f64().unwrap())
+            }
+            (3, serde_json::Value::String(val)) => StateValue::Text(val),
+            (6, serde_json::Value::Bool(val)) => StateValue::Boolean(val),
+            (_, val) => StateValue::Json(val),
+        };
+
+        DeviceState {
+            name: raw.name,
+            value,
+        }
     }
 }
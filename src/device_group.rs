@@ -1,8 +1,29 @@
-use anyhow::{anyhow, Result};
+use crate::error::{MatahoError, Result};
 use log::error;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+/// Separates the profile from the device ID in the members of a group that live on
+/// the gateway of another profile, e.g. `office:00000003`.
+pub const PROFILE_SEPARATOR: char = ':';
+
+/// Member of a group for the device with the given ID, on the gateway of `profile`
+/// when it is not the profile of the group.
+pub fn member_id(profile: Option<&str>, device_id: &str) -> String {
+    match profile {
+        Some(profile) => format!("{}{}{}", profile, PROFILE_SEPARATOR, device_id),
+        None => device_id.to_string(),
+    }
+}
+
+/// Profile (if any) and device ID of a member of a group, see [`member_id`].
+pub fn parse_member(member: &str) -> (Option<&str>, &str) {
+    match member.split_once(PROFILE_SEPARATOR) {
+        Some((profile, device_id)) => (Some(profile), device_id),
+        None => (None, member),
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DeviceGroup {
     id: String,
@@ -38,18 +59,26 @@ impl DeviceGroup {
     pub fn add_device(&mut self, device_id: &str) -> Result<()> {
         if self.has_device(device_id) {
             error!("Device `{}` already in group `{}`", device_id, self.name());
-            return Err(anyhow!("Device already in group"));
+            return Err(MatahoError::DeviceAlreadyInGroup {
+                device: device_id.to_string(),
+                group: self.name().to_string(),
+            });
         }
 
-        Ok(self.devices.push(device_id.to_string()))
+        self.devices.push(device_id.to_string());
+        Ok(())
     }
 
     pub fn remove_device(&mut self, device_id: &str) -> Result<()> {
         if !self.has_device(device_id) {
             error!("Device `{}` not in group `{}`", device_id, self.name());
-            return Err(anyhow!("Device not in group"));
+            return Err(MatahoError::DeviceNotInGroup {
+                device: device_id.to_string(),
+                group: self.name().to_string(),
+            });
         }
 
-        Ok(self.devices.retain(|id| id != device_id))
+        self.devices.retain(|id| id != device_id);
+        Ok(())
     }
 }
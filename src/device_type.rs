@@ -0,0 +1,221 @@
+//! What a device is (roller shutter, light, heater...), from its controllable name.
+//!
+//! The gateway only gives the controllable name of a device (e.g.
+//! `io:RollerShutterWithLowSpeedManagementIOComponent`). The registry maps them
+//! to a human type, a [`Category`] and [`Capability`]s with rules whose `*`
+//! matches any text, the first matching rule winning. The rules bundled with
+//! mataho (`data/device_types.toml`) come after the ones of the user, see
+//! [`DeviceTypeRegistry::with_overrides`].
+
+use std::{fmt, sync::OnceLock};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    device::Device,
+    error::{MatahoError, Result},
+    model::DeviceTypeFilter,
+};
+
+const BUILTIN_DEVICE_TYPES: &str = include_str!("../data/device_types.toml");
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Category {
+    /// Roller shutters, blinds, awnings, garage doors, windows
+    Cover,
+    Gate,
+    Light,
+    /// Heaters and thermostats
+    Climate,
+    Sensor,
+    /// Alarms and their sensors (contact, motion, smoke)
+    Security,
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.to_possible_value()
+            .expect("no values are skipped")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+/// What the device can do, whatever the commands it does it with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Capability {
+    OpenClose,
+    Stop,
+    /// Moved to a closure percentage
+    Position,
+    /// Has a favourite (`my`) position
+    Favourite,
+    /// Slats that can be tilted
+    Orientation,
+    /// Deployed to a percentage, like awnings
+    Deployment,
+    /// Opened just enough to let a pedestrian through
+    Pedestrian,
+    OnOff,
+    Intensity,
+    Color,
+    /// Keeps a target temperature
+    Temperature,
+    /// Reports measures (temperature, light, contact...)
+    Measure,
+    Alarm,
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match serde_json::to_value(self) {
+            Ok(serde_json::Value::String(name)) => write!(f, "{}", name),
+            _ => write!(f, "{:?}", self),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceType {
+    /// Human name, e.g. `roller shutter`
+    #[serde(rename = "type")]
+    pub name: String,
+    pub category: Category,
+    #[serde(default)]
+    pub capabilities: Vec<Capability>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TypeRule {
+    /// Controllable name, `*` matching any text
+    controllable: String,
+    #[serde(flatten)]
+    device_type: DeviceType,
+}
+
+/// Rules of a device types file.
+#[derive(Debug, Default, Deserialize)]
+struct TypeRules {
+    #[serde(default)]
+    types: Vec<TypeRule>,
+}
+
+/// Rules mapping controllable names to device types, in order.
+#[derive(Debug, Clone)]
+pub struct DeviceTypeRegistry {
+    rules: Vec<TypeRule>,
+}
+
+impl DeviceTypeRegistry {
+    /// Rules bundled with mataho.
+    pub fn builtin() -> &'static DeviceTypeRegistry {
+        static REGISTRY: OnceLock<DeviceTypeRegistry> = OnceLock::new();
+
+        REGISTRY.get_or_init(|| {
+            let rules: TypeRules = toml::from_str(BUILTIN_DEVICE_TYPES)
+                .expect("the built-in device types are valid TOML");
+            DeviceTypeRegistry { rules: rules.types }
+        })
+    }
+
+    /// Built-in rules, after the ones of a device types file (same format as
+    /// `data/device_types.toml`) which can add types or change the built-in ones.
+    pub fn with_overrides(content: &str) -> Result<DeviceTypeRegistry> {
+        let overrides: TypeRules = toml::from_str(content)
+            .map_err(|err| MatahoError::Config(format!("Invalid device types file: {}", err)))?;
+
+        let mut rules = overrides.types;
+        rules.extend(DeviceTypeRegistry::builtin().rules.iter().cloned());
+        Ok(DeviceTypeRegistry { rules })
+    }
+
+    /// Type of the devices with this controllable name, `None` when no rule matches.
+    pub fn lookup(&self, controllable_name: &str) -> Option<&DeviceType> {
+        self.rules
+            .iter()
+            .find(|rule| matches_pattern(&rule.controllable, controllable_name))
+            .map(|rule| &rule.device_type)
+    }
+
+    /// Human names of the known types, sorted.
+    pub fn type_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self
+            .rules
+            .iter()
+            .map(|rule| rule.device_type.name.as_str())
+            .collect();
+        names.sort();
+        names.dedup();
+
+        names
+    }
+
+    pub fn matches(&self, device: &Device, filter: &DeviceTypeFilter) -> bool {
+        let device_type = self.lookup(device.controllable_name());
+
+        match filter {
+            DeviceTypeFilter::All => true,
+            DeviceTypeFilter::Category(category) => {
+                device_type.is_some_and(|device_type| device_type.category == *category)
+            }
+            DeviceTypeFilter::Type(name) => {
+                device_type.is_some_and(|device_type| device_type.name == *name)
+            }
+            DeviceTypeFilter::Controllable(pattern) => {
+                matches_pattern(pattern, device.controllable_name())
+            }
+        }
+    }
+
+    /// Fail when the filter names a type no rule gives, which would list nothing.
+    pub fn check_filter(&self, filter: &DeviceTypeFilter) -> Result<()> {
+        match filter {
+            DeviceTypeFilter::Type(name) if !self.type_names().contains(&name.as_str()) => {
+                Err(MatahoError::Other(format!(
+                    "Unknown device type `{}`, expected `all`, a category ({}), a type ({}) or a controllable name",
+                    name,
+                    Category::value_variants()
+                        .iter()
+                        .map(Category::to_string)
+                        .collect::<Vec<String>>()
+                        .join(", "),
+                    self.type_names()
+                        .iter()
+                        .map(|name| name.replace(' ', "-"))
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Whether the name matches the pattern, whose `*` match any text.
+pub(crate) fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    // `split` always gives a first part
+    let first = parts.next().unwrap_or_default();
+    let mut rest = match name.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    match parts.split_last() {
+        // No `*`
+        None => rest.is_empty(),
+        Some((last, middle)) => {
+            for part in middle {
+                match rest.find(part) {
+                    Some(index) => rest = &rest[index + part.len()..],
+                    None => return false,
+                }
+            }
+            rest.ends_with(last)
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+use log::{debug, info, warn};
+use mdns_sd::{ResolvedService, ServiceDaemon, ServiceEvent};
+use serde::Serialize;
+
+use crate::error::{MatahoError, Result};
+
+/// DNS-SD service type announced by the gateways once the developer mode is enabled.
+pub const SERVICE_TYPE: &str = "_kizboxdev._tcp.local.";
+
+/// Standard mDNS port, see [`discover`].
+pub const MDNS_PORT: u16 = 5353;
+
+/// Environment variable replacing [`MDNS_PORT`], to test against a local responder.
+const MDNS_PORT_VAR: &str = "MATAHO_MDNS_PORT";
+
+/// A gateway found on the local network.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiscoveredGateway {
+    /// PIN of the gateway, e.g. `1234-5678-9012`
+    pub pin: String,
+    /// Host name, e.g. `gateway-1234-5678-9012.local`
+    pub hostname: String,
+    pub addresses: Vec<IpAddr>,
+    pub port: u16,
+    pub api_version: Option<String>,
+    pub firmware_version: Option<String>,
+}
+
+impl DiscoveredGateway {
+    /// Value of the `hostname` configuration field for this gateway, its certificate is issued for it.
+    pub fn url(&self) -> String {
+        format!("https://{}", self.hostname)
+    }
+
+    /// Same as [`DiscoveredGateway::url`] with the resolved address (IPv4 first)
+    /// instead of the `.local` name, for systems that do not resolve mDNS names.
+    pub fn address_url(&self) -> Option<String> {
+        let address = self
+            .addresses
+            .iter()
+            .find(|address| address.is_ipv4())
+            .or_else(|| self.addresses.first())?;
+
+        match address {
+            IpAddr::V4(address) => Some(format!("https://{}", address)),
+            IpAddr::V6(address) => Some(format!("https://[{}]", address)),
+        }
+    }
+
+    fn from_service(service: &ResolvedService) -> DiscoveredGateway {
+        let hostname = service.get_hostname().trim_end_matches('.').to_string();
+
+        // The instance name is `gateway-<PIN>` when the TXT record lacks the PIN
+        let pin = match service.get_property_val_str("gateway_pin") {
+            Some(pin) => pin.to_string(),
+            None => service
+                .get_fullname()
+                .trim_end_matches(SERVICE_TYPE)
+                .trim_end_matches('.')
+                .trim_start_matches("gateway-")
+                .to_string(),
+        };
+
+        let mut addresses: Vec<IpAddr> = service
+            .get_addresses()
+            .iter()
+            .map(|address| address.to_ip_addr())
+            .collect();
+        addresses.sort();
+
+        DiscoveredGateway {
+            pin,
+            hostname,
+            addresses,
+            port: service.get_port(),
+            api_version: service
+                .get_property_val_str("api_version")
+                .map(String::from),
+            firmware_version: service.get_property_val_str("fw_version").map(String::from),
+        }
+    }
+}
+
+/// Browse the local network for gateways during `duration`.
+pub fn discover(duration: Duration) -> Result<Vec<DiscoveredGateway>> {
+    let daemon = ServiceDaemon::new_with_port(mdns_port()).map_err(mdns_error)?;
+    info!("browse {} for {}s", SERVICE_TYPE, duration.as_secs());
+    let receiver = daemon.browse(SERVICE_TYPE).map_err(mdns_error)?;
+
+    // Keyed by full name, a gateway is resolved again on each of its addresses
+    let mut gateways = BTreeMap::new();
+    let deadline = Instant::now() + duration;
+
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        let event = match receiver.recv_timeout(remaining) {
+            Ok(event) => event,
+            Err(_) => break,
+        };
+        debug!("mdns event: {:?}", event);
+
+        if let ServiceEvent::ServiceResolved(service) = event {
+            let gateway = DiscoveredGateway::from_service(&service);
+            info!("found gateway `{}` at {:?}", gateway.pin, gateway.addresses);
+            gateways.insert(service.get_fullname().to_string(), gateway);
+        }
+    }
+
+    if let Err(err) = daemon.shutdown() {
+        warn!("failed to stop the mDNS daemon: {}", err);
+    }
+
+    let mut gateways: Vec<DiscoveredGateway> = gateways.into_values().collect();
+    gateways.sort_by(|left, right| left.pin.cmp(&right.pin));
+
+    Ok(gateways)
+}
+
+/// Port the queries are sent to, the standard [`MDNS_PORT`] unless testing.
+fn mdns_port() -> u16 {
+    match env::var(MDNS_PORT_VAR).map(|port| port.parse()) {
+        Ok(Ok(port)) => {
+            debug!("send mDNS queries to port {} ({})", port, MDNS_PORT_VAR);
+            port
+        }
+        Ok(Err(err)) => {
+            warn!("ignore {}: {}", MDNS_PORT_VAR, err);
+            MDNS_PORT
+        }
+        Err(_) => MDNS_PORT,
+    }
+}
+
+fn mdns_error(err: mdns_sd::Error) -> MatahoError {
+    MatahoError::Other(format!("mDNS discovery failed: {}", err))
+}
@@ -0,0 +1,495 @@
+use crate::{
+    output::Output,
+    view::{
+        CommandHelpView, DeviceInfoView, DeviceStatesView, DeviceView, ExecutionView, GatewayView,
+        GroupView, JobView, SceneView,
+    },
+};
+use anyhow::{anyhow, Result};
+use mataho::{
+    catalog,
+    config::{Origin, Setting},
+    device::{Device, DeviceState},
+    discovery::DiscoveredGateway,
+    events::{Event, ExecutionResult, ExecutionState},
+    execution::Execution,
+    fanout::GatewayOutcome,
+    model::DeviceTypeFilter,
+    scene::Scene,
+    Configuration, MatahoError, MatahoService,
+};
+use std::collections::HashMap;
+
+pub fn print_groups(output: &Output, service: &MatahoService) -> Result<()> {
+    let groups: Vec<GroupView> = service
+        .groups()
+        .iter()
+        .map(|group| GroupView::new(service, group))
+        .collect();
+
+    output.print(&groups, GroupView::rows(&groups), |_| {
+        if groups.is_empty() {
+            println!("No group");
+            return;
+        }
+
+        println!("{} groups:", groups.len());
+        for group in service.groups().iter() {
+            let device_labels: String = service
+                .group_device_labels(group)
+                .iter()
+                .map(|label| format!("`{}`", label))
+                .collect::<Vec<String>>()
+                .join(", ");
+
+            println!(
+                "- {}({}): {}",
+                group.name(),
+                group.devices().len(),
+                &device_labels
+            );
+        }
+    })
+}
+
+pub fn print_scenes(output: &Output, service: &MatahoService) -> Result<()> {
+    let scenes: Vec<SceneView> = service
+        .scenes()
+        .iter()
+        .map(|scene| SceneView::new(service, scene))
+        .collect();
+
+    output.print(&scenes, SceneView::rows(&scenes), |rows| {
+        if scenes.is_empty() {
+            println!("No scene");
+            return;
+        }
+
+        output.print_table(rows);
+    })
+}
+
+pub fn print_scene(output: &Output, service: &MatahoService, scene: &Scene) -> Result<()> {
+    let view = SceneView::new(service, scene);
+
+    output.print(&view, view.step_rows(), |rows| {
+        if view.steps.is_empty() {
+            println!("Scene `{}` has no steps", view.name);
+            return;
+        }
+
+        output.print_table(rows);
+    })
+}
+
+/// Devices of one or several gateways, with the profile of their gateway in
+/// the `Gateway` column (`ls --all-profiles`).
+pub fn print_devices(
+    output: &Output,
+    services: &[MatahoService],
+    filter: &DeviceTypeFilter,
+    long_listing: bool,
+    gateway_column: bool,
+    states: &HashMap<String, Vec<DeviceState>>,
+) -> Result<()> {
+    for service in services {
+        service.check_filter(filter)?;
+    }
+
+    let devices: Vec<DeviceView> = services
+        .iter()
+        .flat_map(|service| {
+            service
+                .filter_devices(filter)
+                .into_iter()
+                .map(move |device| {
+                    DeviceView::new(service, device)
+                        .with_states(states.get(device.url()).map(Vec::as_slice))
+                })
+        })
+        .collect();
+    let rows = DeviceView::rows(&devices, long_listing, gateway_column);
+
+    output.print(&devices, rows, |rows| output.print_table(rows))
+}
+
+pub fn print_profile_error(profile: &str, err: &anyhow::Error) {
+    eprintln!("Warning: skipping profile `{}`: {}", profile, err);
+}
+
+pub fn print_device_info(
+    output: &Output,
+    service: &MatahoService,
+    device: &Device,
+    states: Option<&[DeviceState]>,
+) -> Result<()> {
+    let info = DeviceInfoView::new(service, device, states);
+
+    output.print(&info, info.rows(), |_| {
+        println!("- label: {}", device.label());
+        println!("- url: {}", device.url());
+        println!("- id: {} (last part of URL)", device.id());
+        println!("- type: {}", info.device.device_type);
+        println!("- commands:");
+
+        for command in info.commands.iter() {
+            match &command.description {
+                Some(description) => println!("\t- {}: {}", command.usage, description),
+                None => println!("\t- {}", command.usage),
+            }
+        }
+    })
+}
+
+pub fn print_command_help(output: &Output, devices: &[Device], name: &str) -> Result<()> {
+    let help = CommandHelpView::new(devices, name).ok_or_else(|| {
+        let query = name.to_lowercase();
+        let mut similar: Vec<&str> = catalog::builtin()
+            .names()
+            .chain(
+                devices
+                    .iter()
+                    .flat_map(|device| device.definition().actions().iter())
+                    .map(|action| action.name()),
+            )
+            .filter(|known| known.to_lowercase().contains(&query))
+            .collect();
+        similar.sort();
+        similar.dedup();
+
+        match similar.is_empty() {
+            true => anyhow!("Unknown command or state `{}`", name),
+            false => anyhow!(
+                "Unknown command or state `{}`, did you mean {}?",
+                name,
+                similar
+                    .iter()
+                    .map(|known| format!("`{}`", known))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+        }
+    })?;
+
+    output.print(&help, help.rows(), |_| {
+        match help.kind.as_str() {
+            "state" => println!("{} (state): {}", help.name, help.usage),
+            _ => println!("{}", help.usage),
+        }
+        println!(
+            "{}",
+            help.description
+                .as_deref()
+                .unwrap_or("No description, this command is not in the catalog")
+        );
+
+        if !help.params.is_empty() {
+            println!();
+            println!("Parameters:");
+            for param in help.params.iter() {
+                let optional = if param.optional { ", optional" } else { "" };
+                match &param.description {
+                    Some(description) => println!(
+                        "- {}: {}{}. {}",
+                        param.name, param.param_type, optional, description
+                    ),
+                    None => println!("- {}: {}{}", param.name, param.param_type, optional),
+                }
+            }
+        }
+
+        if !help.devices.is_empty() {
+            println!();
+            println!("Devices: {}", help.devices.join(", "));
+        }
+    })
+}
+
+pub fn print_device_states(
+    output: &Output,
+    service: &MatahoService,
+    device: &Device,
+    states: &[DeviceState],
+) -> Result<()> {
+    let view = DeviceStatesView::new(service, device, states);
+
+    output.print(&view, view.rows(), |_| {
+        println!("- label: {}", device.label());
+        println!("- states:");
+
+        for state in states.iter() {
+            println!("\t- {}", state);
+        }
+    })
+}
+
+pub fn print_event(service: &MatahoService, event: &Event, json: bool) -> Result<()> {
+    if let Event::Other = event {
+        return Ok(());
+    }
+
+    if json {
+        println!("{}", serde_json::to_string(event)?);
+        return Ok(());
+    }
+
+    match event {
+        Event::DeviceStateChanged {
+            device_url,
+            device_states,
+        } => {
+            let states = device_states
+                .iter()
+                .map(|state| state.to_string())
+                .collect::<Vec<String>>()
+                .join(", ");
+
+            println!("{}: {}", service.device_label_from_url(device_url), states);
+        }
+        Event::DeviceAvailable { device_url } => {
+            println!("{}: available", service.device_label_from_url(device_url));
+        }
+        Event::DeviceUnavailable { device_url } => {
+            println!("{}: unavailable", service.device_label_from_url(device_url));
+        }
+        Event::ExecutionRegistered {
+            exec_id,
+            label,
+            actions,
+        } => {
+            println!(
+                "execution {} registered: {} ({} devices)",
+                exec_id,
+                label.as_deref().unwrap_or("<no label>"),
+                actions.len()
+            );
+        }
+        Event::ExecutionStateChanged {
+            exec_id,
+            new_state,
+            old_state,
+            failure_type,
+            failed_commands,
+        } => {
+            let old_state = old_state.map_or("?".to_string(), |state| state.to_string());
+            print!("execution {}: {} -> {}", exec_id, old_state, new_state);
+
+            if let Some(failure_type) = failure_type {
+                print!(" ({})", failure_type);
+            }
+
+            for command in failed_commands.iter().flatten() {
+                print!(
+                    ", `{}` failed: {}",
+                    service.device_label_from_url(command.device_url()),
+                    command.failure_type().unwrap_or("<no reason given>")
+                );
+            }
+            println!();
+        }
+        Event::Other => {}
+    }
+
+    Ok(())
+}
+
+pub fn print_executions(
+    output: &Output,
+    service: &MatahoService,
+    executions: &[Execution],
+) -> Result<()> {
+    let jobs: Vec<JobView> = executions
+        .iter()
+        .map(|execution| JobView::new(service, execution))
+        .collect();
+
+    output.print(&jobs, JobView::rows(&jobs), |rows| {
+        if jobs.is_empty() {
+            println!("No running execution");
+            return;
+        }
+
+        output.print_table(rows);
+    })
+}
+
+/// Execution sent to a single device, with its result when it was waited for.
+pub fn print_execution(
+    output: &Output,
+    service: &MatahoService,
+    execution: &ExecutionView,
+    result: Option<&ExecutionResult>,
+) -> Result<()> {
+    output.print(
+        execution,
+        ExecutionView::rows(std::slice::from_ref(execution)),
+        |_| {
+            if let Some(result) = result {
+                print_execution_result(service, result);
+            }
+        },
+    )
+}
+
+/// Executions sent to the devices of a group, one per gateway.
+pub fn print_group_executions(
+    output: &Output,
+    service: &MatahoService,
+    executions: &[ExecutionView],
+    result: Option<&ExecutionResult>,
+) -> Result<()> {
+    output.print(executions, ExecutionView::rows(executions), |_| {
+        if let Some(result) = result {
+            print_execution_result(service, result);
+        }
+    })
+}
+
+fn print_execution_result(service: &MatahoService, result: &ExecutionResult) {
+    if result.is_success() {
+        println!("Execution `{}` completed", result.exec_id());
+        return;
+    }
+    if result.state() == ExecutionState::Unknown {
+        println!("Execution `{}` ended, outcome unknown", result.exec_id());
+        return;
+    }
+
+    println!(
+        "Execution `{}` {}: {}",
+        result.exec_id(),
+        result.state(),
+        result.failure_type().unwrap_or("<no reason given>")
+    );
+
+    for command in result.failed_commands() {
+        println!(
+            "- `{}`: {}",
+            service.device_label_from_url(command.device_url()),
+            command.failure_type().unwrap_or("<no reason given>")
+        );
+    }
+}
+
+pub fn print_gateway_outcomes(
+    output: &Output,
+    outcomes: &[GatewayOutcome],
+    command: &str,
+) -> Result<()> {
+    let executions: Vec<ExecutionView> = outcomes
+        .iter()
+        .map(|outcome| ExecutionView::from_outcome(outcome, command))
+        .collect();
+
+    output.print(&executions, ExecutionView::rows(&executions), |_| {
+        for outcome in outcomes.iter() {
+            let status = match (&outcome.execution, &outcome.result) {
+                (Err(err), _) => format!("failed to execute: {}", err),
+                (Ok(exec_id), None) => format!("execution `{}` started", exec_id),
+                (Ok(exec_id), Some(Ok(result))) if result.is_success() => {
+                    format!("execution `{}` completed", exec_id)
+                }
+                (Ok(exec_id), Some(Ok(result))) if result.state() == ExecutionState::Unknown => {
+                    format!("execution `{}` ended, outcome unknown", exec_id)
+                }
+                (Ok(exec_id), Some(Ok(result))) => format!(
+                    "execution `{}` {}: {}",
+                    exec_id,
+                    result.state(),
+                    result.failure_type().unwrap_or("<no reason given>")
+                ),
+                (Ok(exec_id), Some(Err(err))) => format!("execution `{}`: {}", exec_id, err),
+            };
+
+            println!(
+                "- {} ({} devices): {}",
+                outcome.profile,
+                outcome.devices.len(),
+                status
+            );
+        }
+    })
+}
+
+pub fn print_insecure_warning() {
+    eprintln!("Warning: TLS certificate checks are disabled (`tls.insecure = true`), the API token is sent to whoever answers on the gateway address");
+}
+
+pub fn print_gateway_fingerprint(hostname: &str, fingerprint: &str, pinned: Option<&str>) {
+    println!("Certificate of `{}`:", hostname);
+    println!("- SHA-256 fingerprint: {}", fingerprint);
+
+    if let Some(pinned) = pinned {
+        eprintln!(
+            "Warning: it does not match the pinned certificate ({})",
+            pinned
+        );
+        eprintln!("Someone may be impersonating the gateway, only trust it if it was replaced");
+    }
+}
+
+pub fn print_gateways(output: &Output, gateways: &[DiscoveredGateway]) -> Result<()> {
+    let gateways: Vec<GatewayView> = gateways.iter().map(GatewayView::new).collect();
+
+    output.print(&gateways, GatewayView::rows(&gateways), |rows| {
+        if gateways.is_empty() {
+            println!("No gateway found, is the developer mode enabled?");
+            return;
+        }
+
+        output.print_table(rows);
+    })
+}
+
+/// Settings of a profile, as they would be written in the config file.
+pub fn print_config(config: &Configuration) -> Result<()> {
+    println!("# profile `{}`", config.profile);
+    print!("{}", toml::to_string(config)?);
+
+    Ok(())
+}
+
+/// Settings of a profile, each followed by where its value comes from.
+pub fn print_config_origins(config: &Configuration, origins: &[(&Setting, Origin)]) -> Result<()> {
+    let values = toml::Value::try_from(config)?;
+    println!("# profile `{}`", config.profile);
+
+    for (setting, origin) in origins.iter() {
+        let value = setting
+            .key
+            .split('.')
+            .try_fold(&values, |value, key| value.get(key));
+
+        // Unset optional settings (e.g. `record_file`) have no value
+        if let Some(value) = value {
+            println!("{} = {}  # {}", setting.key, value, origin);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn print_config_check(scope: &str, message: &str) {
+    println!("- {}: {}", scope, message);
+}
+
+/// Explain why the gateway refused the API token, after the error itself.
+pub fn print_token_problem(err: &MatahoError) {
+    match err {
+        MatahoError::Unauthorized { status: 403, .. } => eprintln!(
+            "The gateway knows the token but denies access with it: check that the developer mode is still enabled on the gateway, or generate a new token"
+        ),
+        MatahoError::Unauthorized { .. } => eprintln!(
+            "The token is wrong, has expired or was revoked: generate a new one in the developer mode of the TaHoma app, then run `mataho auth login`"
+        ),
+        _ => {}
+    }
+}
+
+pub fn print_plaintext_token_notice() {
+    println!("The token is written in plain text in the config file, `mataho auth login` moves it to the system keyring");
+}
+
+pub fn print_nothing_saved() {
+    eprintln!("Nothing was saved: fix the settings, or save them anyway with --no-check");
+}
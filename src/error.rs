@@ -0,0 +1,256 @@
+use thiserror::Error;
+
+pub type Result<T> = std::result::Result<T, MatahoError>;
+
+/// Everything that can go wrong when talking to the gateway or resolving devices and groups.
+///
+/// Each variant maps to a process exit code through [`MatahoError::exit_code`], so that
+/// scripts can tell failures apart.
+#[derive(Debug, Error)]
+pub enum MatahoError {
+    #[error("Failed to find a device that matches: `{0}`")]
+    DeviceNotFound(String),
+
+    #[error(
+        "Failed to find a single best match, there are several candidates: {}",
+        quote_all(.candidates)
+    )]
+    AmbiguousMatch {
+        query: String,
+        /// Labels of the devices that matched equally well
+        candidates: Vec<String>,
+    },
+
+    #[error("Device `{device}` does not support the `{command}` command")]
+    CommandNotSupported { device: String, command: String },
+
+    #[error("Invalid arguments for `{command}` of `{device}`: {reason}. Expected: {expected}")]
+    InvalidParameters {
+        device: String,
+        command: String,
+        reason: String,
+        /// Signature of the command, e.g. `setClosure <p1: integer 0-100>`
+        expected: String,
+    },
+
+    #[error(
+        "Given command is not supported by all devices in the group: `{command}` is not supported by {}",
+        quote_all(.devices)
+    )]
+    GroupCommandNotSupported {
+        group: String,
+        command: String,
+        /// Labels of the devices that do not support the command
+        devices: Vec<String>,
+    },
+
+    #[error("No such group: `{0}`")]
+    GroupNotFound(String),
+
+    #[error("There is already a group named `{0}`")]
+    GroupAlreadyExists(String),
+
+    #[error("Device `{device}` already in group `{group}`")]
+    DeviceAlreadyInGroup { device: String, group: String },
+
+    #[error("Device `{device}` not in group `{group}`")]
+    DeviceNotInGroup { device: String, group: String },
+
+    #[error("No such scene: `{0}`")]
+    SceneNotFound(String),
+
+    #[error("There is already a scene named `{0}`")]
+    SceneAlreadyExists(String),
+
+    #[error("Scene `{scene}` has no step {step}")]
+    SceneStepNotFound { scene: String, step: usize },
+
+    #[error("Scene `{0}` has no steps, add some with `mataho scene add`")]
+    EmptyScene(String),
+
+    #[error("Invalid execution `{label}`: {reason}")]
+    InvalidExecution { label: String, reason: String },
+
+    #[error("{context}: the gateway refused the API token ({status})")]
+    Unauthorized { context: String, status: u16 },
+
+    #[error("{context}: the gateway is unreachable ({message})")]
+    GatewayUnreachable { context: String, message: String },
+
+    #[error("{context}: the gateway certificate is not trusted ({message}), pin it with `mataho trust` or set `tls.ca_file`")]
+    UntrustedCertificate { context: String, message: String },
+
+    #[error("{context}: the gateway rejected the execution ({status}: {message})")]
+    ExecutionRejected {
+        context: String,
+        status: u16,
+        message: String,
+    },
+
+    #[error("{context}: unexpected answer from the gateway ({message})")]
+    Gateway {
+        context: String,
+        status: Option<u16>,
+        message: String,
+    },
+
+    #[error("Execution `{exec_id}` failed: {}", .failure_type.as_deref().unwrap_or("<no reason given>"))]
+    ExecutionFailed {
+        exec_id: String,
+        failure_type: Option<String>,
+    },
+
+    #[error("Execution `{exec_id}` ended while its events were missed, its outcome is unknown")]
+    ExecutionOutcomeUnknown { exec_id: String },
+
+    #[error("Timed out after {seconds}s waiting for execution `{exec_id}`")]
+    ExecutionTimeout { exec_id: String, seconds: u64 },
+
+    #[error("{0}")]
+    Config(String),
+
+    #[error("No such profile: `{0}`")]
+    ProfileNotFound(String),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    #[error("{0}")]
+    Other(String),
+}
+
+impl MatahoError {
+    /// Process exit code for this error. `2` is left to clap for usage errors.
+    ///
+    /// | Code | Meaning |
+    /// |------|---------|
+    /// | 1    | Other error (I/O, invalid files, etc.) |
+    /// | 3    | Device not found |
+    /// | 4    | Ambiguous device match |
+    /// | 5    | Command not supported by the device(s), or invalid arguments |
+    /// | 6    | Group or scene error (no such group or scene, already exists, membership) |
+    /// | 7    | Unauthorized API token |
+    /// | 8    | Gateway unreachable |
+    /// | 9    | Gateway rejected the execution or answered unexpectedly |
+    /// | 10   | Execution failed or timed out |
+    /// | 11   | Invalid configuration or unknown profile |
+    /// | 12   | Untrusted gateway certificate |
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            MatahoError::DeviceNotFound(_) => 3,
+            MatahoError::AmbiguousMatch { .. } => 4,
+            MatahoError::CommandNotSupported { .. }
+            | MatahoError::InvalidParameters { .. }
+            | MatahoError::GroupCommandNotSupported { .. } => 5,
+            MatahoError::GroupNotFound(_)
+            | MatahoError::GroupAlreadyExists(_)
+            | MatahoError::DeviceAlreadyInGroup { .. }
+            | MatahoError::DeviceNotInGroup { .. }
+            | MatahoError::SceneNotFound(_)
+            | MatahoError::SceneAlreadyExists(_)
+            | MatahoError::SceneStepNotFound { .. }
+            | MatahoError::EmptyScene(_) => 6,
+            MatahoError::Unauthorized { .. } => 7,
+            MatahoError::GatewayUnreachable { .. } => 8,
+            MatahoError::ExecutionRejected { .. } | MatahoError::Gateway { .. } => 9,
+            MatahoError::ExecutionFailed { .. }
+            | MatahoError::ExecutionOutcomeUnknown { .. }
+            | MatahoError::ExecutionTimeout { .. } => 10,
+            MatahoError::Config(_) | MatahoError::ProfileNotFound(_) => 11,
+            MatahoError::UntrustedCertificate { .. } => 12,
+            MatahoError::InvalidExecution { .. }
+            | MatahoError::Io(_)
+            | MatahoError::Json(_)
+            | MatahoError::Other(_) => 1,
+        }
+    }
+
+    /// Build the error matching a failed HTTP request to the gateway.
+    pub(crate) fn from_request(context: &str, err: reqwest::Error) -> MatahoError {
+        if let Some(tls_err) = find_tls_error(&err) {
+            return MatahoError::UntrustedCertificate {
+                context: context.to_string(),
+                message: tls_err.to_string(),
+            };
+        }
+
+        if let Some(status) = err.status() {
+            let status = status.as_u16();
+            if status == 401 || status == 403 {
+                return MatahoError::Unauthorized {
+                    context: context.to_string(),
+                    status,
+                };
+            }
+
+            return MatahoError::Gateway {
+                context: context.to_string(),
+                status: Some(status),
+                message: err.to_string(),
+            };
+        }
+
+        if err.is_connect() || err.is_timeout() || err.is_request() {
+            return MatahoError::GatewayUnreachable {
+                context: context.to_string(),
+                message: err.to_string(),
+            };
+        }
+
+        MatahoError::Gateway {
+            context: context.to_string(),
+            status: None,
+            message: err.to_string(),
+        }
+    }
+
+    /// Same as [`MatahoError::from_request`], but errors returned by the gateway
+    /// itself mean it rejected the execution.
+    pub(crate) fn from_execution(context: &str, err: reqwest::Error) -> MatahoError {
+        match MatahoError::from_request(context, err) {
+            MatahoError::Gateway {
+                context,
+                status: Some(status),
+                message,
+            } => MatahoError::ExecutionRejected {
+                context,
+                status,
+                message,
+            },
+            err => err,
+        }
+    }
+}
+
+/// TLS failure at the root of a request error, if any.
+fn find_tls_error(err: &reqwest::Error) -> Option<&rustls::Error> {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(err);
+
+    while let Some(err) = source {
+        if let Some(tls_err) = err.downcast_ref::<rustls::Error>() {
+            return Some(tls_err);
+        }
+
+        // `io::Error` skips the error it wraps when asked for its source
+        source = match err.downcast_ref::<std::io::Error>() {
+            Some(io_err) => match io_err.get_ref() {
+                Some(inner) => Some(inner as &(dyn std::error::Error + 'static)),
+                None => err.source(),
+            },
+            None => err.source(),
+        };
+    }
+
+    None
+}
+
+fn quote_all(values: &[String]) -> String {
+    values
+        .iter()
+        .map(|value| format!("`{}`", value))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
@@ -0,0 +1,337 @@
+use std::{
+    fmt, thread,
+    time::{Duration, Instant},
+};
+
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::TahomaApi,
+    device::DeviceState,
+    error::{MatahoError, Result},
+};
+
+/// The gateway drops listeners that have not been fetched for 10 minutes.
+const LISTENER_TTL: Duration = Duration::from_secs(8 * 60);
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Event emitted by the gateway and fetched through an [`EventListener`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "name")]
+pub enum Event {
+    #[serde(rename = "DeviceStateChangedEvent")]
+    DeviceStateChanged {
+        #[serde(rename = "deviceURL")]
+        device_url: String,
+        #[serde(rename = "deviceStates", default)]
+        device_states: Vec<DeviceState>,
+    },
+    #[serde(rename = "ExecutionRegisteredEvent")]
+    ExecutionRegistered {
+        #[serde(rename = "execId")]
+        exec_id: String,
+        label: Option<String>,
+        #[serde(default)]
+        actions: Vec<EventAction>,
+    },
+    #[serde(rename = "ExecutionStateChangedEvent")]
+    ExecutionStateChanged {
+        #[serde(rename = "execId")]
+        exec_id: String,
+        #[serde(rename = "newState")]
+        new_state: ExecutionState,
+        #[serde(rename = "oldState")]
+        old_state: Option<ExecutionState>,
+        #[serde(rename = "failureType")]
+        failure_type: Option<String>,
+        #[serde(rename = "failedCommands")]
+        failed_commands: Option<Vec<FailedCommand>>,
+    },
+    #[serde(rename = "DeviceAvailableEvent")]
+    DeviceAvailable {
+        #[serde(rename = "deviceURL")]
+        device_url: String,
+    },
+    #[serde(rename = "DeviceUnavailableEvent")]
+    DeviceUnavailable {
+        #[serde(rename = "deviceURL")]
+        device_url: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+impl Event {
+    /// URLs of the devices this event is about, if any.
+    pub fn device_urls(&self) -> Vec<&str> {
+        match self {
+            Event::DeviceStateChanged { device_url, .. }
+            | Event::DeviceAvailable { device_url }
+            | Event::DeviceUnavailable { device_url } => vec![device_url],
+            Event::ExecutionRegistered { actions, .. } => {
+                actions.iter().map(|action| action.device_url()).collect()
+            }
+            Event::ExecutionStateChanged {
+                failed_commands, ..
+            } => failed_commands
+                .iter()
+                .flatten()
+                .map(|command| command.device_url())
+                .collect(),
+            Event::Other => Vec::new(),
+        }
+    }
+
+    pub fn exec_id(&self) -> Option<&str> {
+        match self {
+            Event::ExecutionRegistered { exec_id, .. }
+            | Event::ExecutionStateChanged { exec_id, .. } => Some(exec_id),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ExecutionState {
+    Initialized,
+    NotTransmitted,
+    Transmitted,
+    InProgress,
+    Completed,
+    Failed,
+    #[serde(other)]
+    Unknown,
+}
+
+impl ExecutionState {
+    pub fn is_final(&self) -> bool {
+        matches!(self, ExecutionState::Completed | ExecutionState::Failed)
+    }
+}
+
+impl fmt::Display for ExecutionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match self {
+            ExecutionState::Initialized => "INITIALIZED",
+            ExecutionState::NotTransmitted => "NOT_TRANSMITTED",
+            ExecutionState::Transmitted => "TRANSMITTED",
+            ExecutionState::InProgress => "IN_PROGRESS",
+            ExecutionState::Completed => "COMPLETED",
+            ExecutionState::Failed => "FAILED",
+            ExecutionState::Unknown => "UNKNOWN",
+        };
+
+        write!(f, "{}", state)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventAction {
+    #[serde(rename = "deviceURL")]
+    device_url: String,
+}
+
+impl EventAction {
+    pub fn new(device_url: &str) -> EventAction {
+        EventAction {
+            device_url: device_url.to_string(),
+        }
+    }
+
+    pub fn device_url(&self) -> &str {
+        &self.device_url
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailedCommand {
+    #[serde(rename = "deviceURL")]
+    device_url: String,
+    /// Left out by the gateway for some failures
+    #[serde(rename = "failureType")]
+    failure_type: Option<String>,
+}
+
+impl FailedCommand {
+    pub fn device_url(&self) -> &str {
+        &self.device_url
+    }
+
+    pub fn failure_type(&self) -> Option<&str> {
+        self.failure_type.as_deref()
+    }
+}
+
+/// Final outcome of an execution, as reported by the gateway.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExecutionResult {
+    exec_id: String,
+    state: ExecutionState,
+    failure_type: Option<String>,
+    failed_commands: Vec<FailedCommand>,
+}
+
+impl ExecutionResult {
+    pub fn exec_id(&self) -> &str {
+        &self.exec_id
+    }
+
+    pub fn state(&self) -> ExecutionState {
+        self.state
+    }
+
+    pub fn failure_type(&self) -> Option<&str> {
+        self.failure_type.as_deref()
+    }
+
+    pub fn failed_commands(&self) -> &Vec<FailedCommand> {
+        &self.failed_commands
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.state == ExecutionState::Completed
+    }
+}
+
+/// Event listener registered on the gateway.
+///
+/// The listener is re-registered transparently when it is about to expire or
+/// when the gateway no longer knows about it.
+pub struct EventListener<'a> {
+    api: &'a dyn TahomaApi,
+    id: String,
+    last_fetch: Instant,
+    /// Set when the listener was registered again: the events sent in between are lost
+    missed_events: bool,
+}
+
+impl<'a> EventListener<'a> {
+    pub fn register(api: &'a dyn TahomaApi) -> Result<EventListener<'a>> {
+        let id = api.register_event_listener()?;
+        info!("registered event listener `{}`", id);
+
+        Ok(EventListener {
+            api,
+            id,
+            last_fetch: Instant::now(),
+            missed_events: false,
+        })
+    }
+
+    fn refresh(&mut self) -> Result<()> {
+        // The gateway may still know the old listener, which would then leak
+        if let Err(err) = self.api.unregister_event_listener(&self.id) {
+            debug!("failed to unregister event listener `{}`: {}", self.id, err);
+        }
+
+        self.id = self.api.register_event_listener()?;
+        self.last_fetch = Instant::now();
+        self.missed_events = true;
+        info!("refreshed event listener `{}`", self.id);
+
+        Ok(())
+    }
+
+    /// Fetch the events received since the previous call.
+    pub fn fetch(&mut self) -> Result<Vec<Event>> {
+        if self.last_fetch.elapsed() > LISTENER_TTL {
+            self.refresh()?;
+        }
+
+        let events = match self.api.fetch_events(&self.id) {
+            Ok(events) => events,
+            Err(err) => {
+                warn!("failed to fetch events, re-registering listener: {}", err);
+                self.refresh()?;
+                self.api.fetch_events(&self.id)?
+            }
+        };
+        self.last_fetch = Instant::now();
+
+        Ok(events)
+    }
+
+    /// Block until at least one event is available and return it.
+    pub fn poll(&mut self) -> Result<Vec<Event>> {
+        loop {
+            let events = self.fetch()?;
+            if !events.is_empty() {
+                return Ok(events);
+            }
+
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+impl EventListener<'_> {
+    /// Follow an execution until it is completed or failed.
+    ///
+    /// The listener must be registered before the execution is applied, otherwise
+    /// its state changes may be missed. When the listener had to be registered
+    /// again, an execution the gateway no longer runs ended in a state that is
+    /// not known: it is reported as [`ExecutionState::Unknown`].
+    pub fn wait_for_execution(
+        &mut self,
+        exec_id: &str,
+        timeout: Duration,
+    ) -> Result<ExecutionResult> {
+        let start = Instant::now();
+
+        while start.elapsed() < timeout {
+            for event in self.fetch()? {
+                if let Event::ExecutionStateChanged {
+                    exec_id: id,
+                    new_state,
+                    failure_type,
+                    failed_commands,
+                    ..
+                } = event
+                {
+                    debug!("execution `{}` is now {}", id, new_state);
+
+                    if id == exec_id && new_state.is_final() {
+                        return Ok(ExecutionResult {
+                            exec_id: id,
+                            state: new_state,
+                            failure_type,
+                            failed_commands: failed_commands.unwrap_or_default(),
+                        });
+                    }
+                }
+            }
+
+            // Its final state may have been sent while the listener was registered again
+            if std::mem::take(&mut self.missed_events) && self.api.get_execution(exec_id)?.is_none()
+            {
+                warn!(
+                    "execution `{}` finished while events were missed, its outcome is unknown",
+                    exec_id
+                );
+                return Ok(ExecutionResult {
+                    exec_id: exec_id.to_string(),
+                    state: ExecutionState::Unknown,
+                    failure_type: None,
+                    failed_commands: Vec::new(),
+                });
+            }
+
+            thread::sleep(POLL_INTERVAL);
+        }
+
+        Err(MatahoError::ExecutionTimeout {
+            exec_id: exec_id.to_string(),
+            seconds: timeout.as_secs(),
+        })
+    }
+}
+
+impl Drop for EventListener<'_> {
+    fn drop(&mut self) {
+        if let Err(err) = self.api.unregister_event_listener(&self.id) {
+            warn!("failed to unregister event listener `{}`: {}", self.id, err);
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+
+use crate::events::{EventAction, ExecutionState};
+
+/// Execution currently running on the gateway, as returned by `/exec/current`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Execution {
+    id: String,
+    owner: Option<String>,
+    #[serde(rename = "startTime")]
+    start_time: Option<u64>,
+    state: Option<ExecutionState>,
+    #[serde(rename = "actionGroup")]
+    action_group: ActionGroup,
+}
+
+impl Execution {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn label(&self) -> Option<&str> {
+        self.action_group.label.as_deref()
+    }
+
+    pub fn owner(&self) -> Option<&str> {
+        self.owner.as_deref()
+    }
+
+    /// Start time in milliseconds since the UNIX epoch.
+    pub fn start_time(&self) -> Option<u64> {
+        self.start_time
+    }
+
+    pub fn state(&self) -> Option<ExecutionState> {
+        self.state
+    }
+
+    pub fn device_urls(&self) -> Vec<&str> {
+        self.action_group
+            .actions
+            .iter()
+            .map(|action| action.device_url())
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ActionGroup {
+    label: Option<String>,
+    #[serde(default)]
+    actions: Vec<EventAction>,
+}
@@ -0,0 +1,200 @@
+use std::{thread, time::Duration};
+
+use log::{error, info};
+
+use crate::{
+    api::{self, TahomaApi},
+    controller::{exec_label, ExecAction},
+    device::Device,
+    error::{MatahoError, Result},
+    events::{EventListener, ExecutionResult},
+    intent::Intent,
+    model::Configuration,
+};
+
+/// Devices of one gateway to run a command on.
+#[derive(Debug)]
+pub struct GatewayTarget {
+    /// Settings of the profile of the gateway
+    pub config: Configuration,
+    pub devices: Vec<Device>,
+}
+
+/// What happened on one gateway, see [`execute_on_gateways`].
+#[derive(Debug)]
+pub struct GatewayOutcome {
+    pub profile: String,
+    /// Labels of the devices the command was sent to
+    pub devices: Vec<String>,
+    /// ID of the execution, or why it could not be applied
+    pub execution: Result<String>,
+    /// How the execution ended, when waited for
+    pub result: Option<Result<ExecutionResult>>,
+}
+
+impl GatewayOutcome {
+    pub fn is_success(&self) -> bool {
+        match (&self.execution, &self.result) {
+            (Err(_), _) => false,
+            (Ok(_), Some(Ok(result))) => result.is_success(),
+            (Ok(_), Some(Err(_))) => false,
+            (Ok(_), None) => true,
+        }
+    }
+}
+
+/// Run the same command on devices spread over several gateways: the devices of
+/// each gateway get their own `/exec/apply`, all sent concurrently.
+///
+/// Nothing is sent if a device does not support the command or its arguments.
+/// Otherwise, the failure of one gateway does not stop the others, see
+/// [`GatewayOutcome`].
+pub fn execute_on_gateways(
+    group: &str,
+    targets: &[GatewayTarget],
+    command: &str,
+    params: &[String],
+    wait: Option<Duration>,
+) -> Result<Vec<GatewayOutcome>> {
+    let unsupported: Vec<String> = targets
+        .iter()
+        .flat_map(|target| target.devices.iter())
+        .filter(|device| !device.supports_action(command))
+        .map(|device| device.label().to_string())
+        .collect();
+
+    if !unsupported.is_empty() {
+        return Err(MatahoError::GroupCommandNotSupported {
+            group: group.to_string(),
+            command: command.to_string(),
+            devices: unsupported,
+        });
+    }
+    for target in targets.iter() {
+        let devices: Vec<&Device> = target.devices.iter().collect();
+        ExecAction::same_command(&devices, command, params)?;
+    }
+
+    Ok(fan_out(targets, command, wait, |api, devices| {
+        api.execute_multiple(devices, command, params)
+    }))
+}
+
+/// Run the intent on devices spread over several gateways, each device getting
+/// the native command it takes, see [`execute_on_gateways`].
+pub fn execute_intent_on_gateways(
+    group: &str,
+    targets: &[GatewayTarget],
+    intent: Intent,
+    wait: Option<Duration>,
+) -> Result<Vec<GatewayOutcome>> {
+    let devices: Vec<&Device> = targets
+        .iter()
+        .flat_map(|target| target.devices.iter())
+        .collect();
+    intent
+        .actions(&devices)
+        .map_err(|unsupported| MatahoError::GroupCommandNotSupported {
+            group: group.to_string(),
+            command: intent.to_string(),
+            devices: unsupported,
+        })?;
+
+    let label = intent.to_string();
+    Ok(fan_out(targets, &label, wait, |api, devices| {
+        // Checked above for every device
+        let batch = intent.actions(&devices).unwrap_or_default();
+        api.execute_batch(&exec_label(&label, &devices), &batch)
+    }))
+}
+
+/// Send an execution to each gateway concurrently with `send`, which is given
+/// the devices of the gateway.
+fn fan_out<F>(
+    targets: &[GatewayTarget],
+    what: &str,
+    wait: Option<Duration>,
+    send: F,
+) -> Vec<GatewayOutcome>
+where
+    F: Fn(&dyn TahomaApi, Vec<&Device>) -> Result<String> + Sync,
+{
+    thread::scope(|scope| {
+        let handles: Vec<_> = targets
+            .iter()
+            .map(|target| scope.spawn(|| execute_on_gateway(target, what, wait, &send)))
+            .collect();
+
+        handles
+            .into_iter()
+            .zip(targets)
+            .map(|(handle, target)| {
+                handle.join().unwrap_or_else(|_| {
+                    error!("execution thread of `{}` panicked", target.config.profile);
+                    outcome(
+                        target,
+                        Err(MatahoError::Other("Execution thread panicked".to_string())),
+                        None,
+                    )
+                })
+            })
+            .collect()
+    })
+}
+
+fn execute_on_gateway<F>(
+    target: &GatewayTarget,
+    what: &str,
+    wait: Option<Duration>,
+    send: &F,
+) -> GatewayOutcome
+where
+    F: Fn(&dyn TahomaApi, Vec<&Device>) -> Result<String>,
+{
+    info!(
+        "execute `{}` on {} devices of `{}`",
+        what,
+        target.devices.len(),
+        target.config.profile
+    );
+
+    let api = match api::from_configuration(&target.config) {
+        Ok(api) => api,
+        Err(err) => return outcome(target, Err(err), None),
+    };
+
+    let mut listener = match wait.map(|_| EventListener::register(api.as_ref())) {
+        Some(Ok(listener)) => Some(listener),
+        Some(Err(err)) => return outcome(target, Err(err), None),
+        None => None,
+    };
+
+    let exec_id = match send(api.as_ref(), target.devices.iter().collect()) {
+        Ok(exec_id) => exec_id,
+        Err(err) => return outcome(target, Err(err), None),
+    };
+
+    let result = match (listener.as_mut(), wait) {
+        (Some(listener), Some(timeout)) => Some(listener.wait_for_execution(&exec_id, timeout)),
+        _ => None,
+    };
+
+    outcome(target, Ok(exec_id), result)
+}
+
+fn outcome(
+    target: &GatewayTarget,
+    execution: Result<String>,
+    result: Option<Result<ExecutionResult>>,
+) -> GatewayOutcome {
+    GatewayOutcome {
+        profile: target.config.profile.clone(),
+        devices: target
+            .devices
+            .iter()
+            .map(|device| device.label().to_string())
+            .collect(),
+        execution,
+        result,
+    }
+}
@@ -0,0 +1,169 @@
+//! Intent-level commands (`open`, `close`, `stop`, `set`, `my`), sent to each
+//! device as the native command it takes.
+//!
+//! Devices do not agree on their commands: a roller shutter is positioned with
+//! `setClosure`, an awning with `setDeployment` and opens with `deploy`... The
+//! candidates of the controllable name of the device come first, then the
+//! generic ones, and the first one the device supports is sent. A device having
+//! none of them is reported as unsupported: `my` is not a stop, an RTS device
+//! lacking `stop` would move to its favourite position instead.
+
+use std::fmt;
+
+use crate::{
+    controller::{ExecAction, ExecCommand},
+    device::Device,
+    device_type::matches_pattern,
+};
+
+/// What to do with a device, whatever its type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Intent {
+    Open,
+    Close,
+    Stop,
+    /// Move to the closure percentage (0 = open, 100 = closed), as `setClosure`
+    Set(u8),
+    /// Move to the favourite (`my`) position
+    My,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Open,
+    Close,
+    Stop,
+    Set,
+    My,
+}
+
+/// Native command for an intent.
+#[derive(Debug)]
+enum Native {
+    /// Command without arguments
+    Plain(&'static str),
+    /// Command taking the closure percentage
+    Closure(&'static str),
+    /// Command taking the opening percentage (0 = closed, 100 = open), such as
+    /// the deployment of an awning
+    Opening(&'static str),
+}
+
+/// Candidates for the intents, for the devices whose controllable name matches
+/// (`*` matching any text). The generic ones come last.
+const NATIVE_COMMANDS: &[(&str, Kind, &[Native])] = &[
+    (
+        "io:HorizontalAwningIOComponent",
+        Kind::Open,
+        &[Native::Plain("deploy")],
+    ),
+    (
+        "io:HorizontalAwningIOComponent",
+        Kind::Close,
+        &[Native::Plain("undeploy")],
+    ),
+    (
+        "*",
+        Kind::Open,
+        &[Native::Plain("open"), Native::Plain("up")],
+    ),
+    (
+        "*",
+        Kind::Close,
+        &[Native::Plain("close"), Native::Plain("down")],
+    ),
+    ("*", Kind::Stop, &[Native::Plain("stop")]),
+    (
+        "*",
+        Kind::Set,
+        &[
+            Native::Closure("setClosure"),
+            Native::Closure("setPosition"),
+            Native::Opening("setDeployment"),
+        ],
+    ),
+    ("*", Kind::My, &[Native::Plain("my")]),
+];
+
+impl Intent {
+    fn kind(&self) -> Kind {
+        match self {
+            Intent::Open => Kind::Open,
+            Intent::Close => Kind::Close,
+            Intent::Stop => Kind::Stop,
+            Intent::Set(_) => Kind::Set,
+            Intent::My => Kind::My,
+        }
+    }
+
+    /// Native command of the device for the intent, `None` when it has none.
+    ///
+    /// A device that cannot be positioned is still opened by `set 0` and closed
+    /// by `set 100`.
+    pub fn command_for(&self, device: &Device) -> Option<ExecCommand> {
+        let command = NATIVE_COMMANDS
+            .iter()
+            .filter(|(pattern, kind, _)| {
+                *kind == self.kind() && matches_pattern(pattern, device.controllable_name())
+            })
+            .flat_map(|(_, _, natives)| natives.iter())
+            .find_map(|native| self.native_command(device, native));
+
+        match (command, self) {
+            (Some(command), _) => Some(command),
+            (None, Intent::Set(0)) => Intent::Open.command_for(device),
+            (None, Intent::Set(100)) => Intent::Close.command_for(device),
+            (None, _) => None,
+        }
+    }
+
+    fn native_command(&self, device: &Device, native: &Native) -> Option<ExecCommand> {
+        let (name, args) = match (native, self) {
+            (Native::Plain(name), _) => (name, Vec::new()),
+            (Native::Closure(name), Intent::Set(closure)) => (name, vec![closure.to_string()]),
+            (Native::Opening(name), Intent::Set(closure)) if *closure <= 100 => {
+                (name, vec![(100 - closure).to_string()])
+            }
+            _ => return None,
+        };
+
+        // Not supported, or with other parameters than expected
+        ExecCommand::parse(device, name, &args).ok()
+    }
+
+    /// Native command of each device, or the labels of the devices having none.
+    pub fn actions<'a>(
+        &self,
+        devices: &[&'a Device],
+    ) -> std::result::Result<Vec<ExecAction<'a>>, Vec<String>> {
+        let mut actions = Vec::new();
+        let mut unsupported = Vec::new();
+
+        for device in devices.iter() {
+            match self.command_for(device) {
+                Some(command) => actions.push(ExecAction {
+                    device,
+                    commands: vec![command],
+                }),
+                None => unsupported.push(device.label().to_string()),
+            }
+        }
+
+        match unsupported.is_empty() {
+            true => Ok(actions),
+            false => Err(unsupported),
+        }
+    }
+}
+
+impl fmt::Display for Intent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Intent::Open => write!(f, "open"),
+            Intent::Close => write!(f, "close"),
+            Intent::Stop => write!(f, "stop"),
+            Intent::Set(closure) => write!(f, "set {}", closure),
+            Intent::My => write!(f, "my"),
+        }
+    }
+}
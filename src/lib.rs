@@ -0,0 +1,58 @@
+//! Interact with a local Tahoma box.
+//!
+//! [`TahomaApiController`] talks to the gateway local API, while [`MatahoService`]
+//! resolves devices (by ID or fuzzy label matching) and manages groups of devices
+//! on top of the setup returned by the gateway.
+//!
+//! ```no_run
+//! use mataho::{model::MatchMode, Configuration, MatahoService, TahomaApi, TahomaApiController};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let config: Configuration = toml::from_str(&std::fs::read_to_string("config.toml")?)?;
+//! let controller = TahomaApiController::new(&config);
+//! let service = MatahoService::new(controller.get_setup()?);
+//!
+//! let device = service.find_device("coko", MatchMode::Fuzzy)?;
+//! service.execute_on_device(&controller, device, "close", &Vec::new())?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod api;
+#[cfg(feature = "async")]
+pub mod async_controller;
+pub mod cache;
+pub mod catalog;
+pub mod config;
+pub mod controller;
+pub mod device;
+pub mod device_group;
+pub mod device_type;
+pub mod discovery;
+pub mod error;
+pub mod events;
+pub mod execution;
+pub mod fanout;
+pub mod intent;
+pub mod model;
+pub mod params;
+pub mod recording;
+pub mod scene;
+pub mod secret;
+pub mod service;
+pub mod simulator;
+pub mod tls;
+
+pub use api::TahomaApi;
+#[cfg(feature = "async")]
+pub use async_controller::AsyncTahomaApiController;
+pub use controller::{TahomaApiController, TahomaSetupResponse};
+pub use device::{Device, DeviceAction, DeviceDefinition, DeviceState, StateValue};
+pub use device_group::DeviceGroup;
+pub use error::{MatahoError, Result};
+pub use model::Configuration;
+pub use recording::RecordingApi;
+pub use scene::Scene;
+pub use service::MatahoService;
+pub use simulator::SimulatedTahomaApi;
+pub use tls::TlsConfig;
@@ -1,108 +1,1111 @@
 use anyhow::{anyhow, Result};
 use clap::Parser;
-use cli::{Cli, Commands, GroupCommands};
-use log::info;
-use model::{Configuration, MatchMode};
-use service::MatahoService;
-use std::{fs, path::PathBuf};
+use cli::{
+    AuthCommands, Cli, Commands, ConfigCommands, GroupCommands, IntentTarget, SceneCommands,
+};
+use log::{debug, info, warn};
+use mataho::{
+    api::{self as backend, Backend},
+    cache::SetupCache,
+    config::{self, Origin, Overrides},
+    device_group, discovery,
+    events::{EventListener, ExecutionResult, ExecutionState},
+    fanout::{self, GatewayOutcome, GatewayTarget},
+    intent::Intent,
+    model::{Configuration, DeviceTypeFilter, MatchMode, Profile, DEFAULT_PROFILE},
+    scene::{SceneStep, SceneTarget},
+    secret, tls, Device, MatahoError, MatahoService, TahomaApi, TahomaSetupResponse,
+};
+use output::{Output, OutputFormat};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs,
+    io::{self, Write},
+    path::Path,
+    process::ExitCode,
+    time::Duration,
+};
+use template::Template;
+use view::ExecutionView;
 
 mod cli;
-mod controller;
-mod device;
-mod device_group;
-mod model;
-mod service;
+mod display;
+mod output;
+mod template;
+mod view;
 
-use controller::TahomaApiController;
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {:?}", err);
 
-fn main() -> Result<()> {
+            let code = match err.downcast_ref::<MatahoError>() {
+                Some(err) => err.exit_code(),
+                None => 1,
+            };
+            ExitCode::from(code)
+        }
+    }
+}
+
+fn run() -> Result<()> {
     let args = Cli::parse();
     env_logger::Builder::new()
         .filter_level(args.verbose.log_level_filter())
         .init();
+    let output = output(&args)?;
 
     info!("getting config file");
     let config_file_path = MatahoService::config_file_path()?;
-    if !config_file_path.exists() {
-        MatahoService::create_config_file()?;
-        info!("config file created");
+
+    if let Commands::Config { command } = &args.command {
+        return process_config_command(command, &args, &config_file_path);
     }
 
+    if let Commands::Discover {
+        timeout,
+        save,
+        by_address,
+    } = &args.command
+    {
+        info!("cmd::discover");
+
+        let profile = selected_profile(&args, Some(&load_config(&config_file_path)?));
+        return discover_gateways(&output, &profile, *timeout, save.as_deref(), *by_address);
+    }
+
+    info!("loading config");
+    let settings = ResolvedSettings::load(&args, &config_file_path)?;
+    info!("use profile `{}`", settings.selected);
+    let config = settings.profile(&settings.selected)?;
+
+    if let Commands::Auth { command } = &args.command {
+        return process_auth_command(command, &config, &settings.overrides);
+    }
+
+    if let Commands::HelpCommand { name } = &args.command {
+        info!("cmd::help-command: {}", name);
+        return help_command(&output, &config, name);
+    }
+
+    config::require_gateway(&config)?;
+    let policy = SetupPolicy::new(&args, &output);
+
     info!("getting groups file");
-    let groups_file_path = MatahoService::groups_file_path()?;
+    let groups_file_path = MatahoService::groups_file_path(&config.profile)?;
     if !groups_file_path.exists() {
-        MatahoService::create_groups_file()?;
+        MatahoService::create_groups_file(&config.profile)?;
         info!("groups file created");
     }
 
-    info!("loading config");
-    let config = load_config(config_file_path)?;
+    if config.tls.insecure && config.backend == Backend::Http {
+        display::print_insecure_warning();
+    }
+
+    if let Commands::Trust { yes } = args.command {
+        info!("cmd::trust");
+        return trust_gateway(&config, yes);
+    }
 
-    info!("init Tahoma api controller");
-    let controller = TahomaApiController::new(&config);
+    if let Commands::List {
+        filter,
+        long_listing,
+        all_profiles: true,
+    } = args.command
+    {
+        info!("cmd::list: {} (all profiles)", filter);
+        return list_all_profiles(&output, policy, &settings, &filter, long_listing);
+    }
+
+    info!("init Tahoma api backend: {:?}", config.backend);
+    let api = backend::from_configuration(&config)?;
+
+    info!("load device setup");
+    let setup = load_setup(policy, &config, api.as_ref())?;
 
     info!("init Mataho service");
-    let mut mataho_service = MatahoService::new(controller.get_setup()?);
+    let mut mataho_service = MatahoService::with_profile(setup, &config.profile);
 
     info!("process cli args");
-    process_args(args, &controller, &mut mataho_service)?;
+    process_args(
+        args,
+        &output,
+        policy,
+        &settings,
+        api.as_ref(),
+        &mut mataho_service,
+    )?;
 
     Ok(())
 }
 
-fn load_config(path: PathBuf) -> Result<Configuration> {
-    let content = fs::read_to_string(path)?;
+/// Describe a command or a state from the catalog and the cached device setup,
+/// without contacting the gateway (which may not even be configured yet).
+fn help_command(output: &Output, config: &Configuration, name: &str) -> Result<()> {
+    let cache_path = MatahoService::setup_cache_file_path(&config.profile)?;
+    let setup = SetupCache::load_for(&cache_path, config)
+        .unwrap_or_else(|err| {
+            warn!("ignoring unreadable setup cache: {}", err);
+            None
+        })
+        .map(SetupCache::into_setup)
+        .unwrap_or_default();
+
+    display::print_command_help(output, &setup.devices, name)
+}
+
+/// Settings of the config file, the default ones when there is no config file.
+fn load_config(path: &Path) -> Result<Configuration> {
+    let content = read_config_file(path)?;
 
     info!("parsing config toml");
-    let config: Configuration = toml::from_str(&content)?;
+    let config: Configuration = toml::from_str(&content)
+        .map_err(|err| MatahoError::Config(format!("Invalid config file: {}", err)))?;
     Ok(config)
 }
 
+/// Top-level settings actually written in the config file, to tell them from defaults.
+fn load_top_level_settings(path: &Path) -> Result<Profile> {
+    let content = read_config_file(path)?;
+
+    let settings: Profile = toml::from_str(&content)
+        .map_err(|err| MatahoError::Config(format!("Invalid config file: {}", err)))?;
+    Ok(settings)
+}
+
+fn read_config_file(path: &Path) -> Result<String> {
+    if !path.exists() {
+        info!("no config file, only use the environment and the flags");
+        return Ok(String::new());
+    }
+
+    Ok(fs::read_to_string(path)?)
+}
+
+/// Settings of the config file, with the environment and the flags applied to
+/// the selected profile.
+struct ResolvedSettings {
+    file: Configuration,
+    /// Profile given on the command line, or the default one of the config file
+    selected: String,
+    overrides: Overrides,
+}
+
+impl ResolvedSettings {
+    fn load(args: &Cli, path: &Path) -> Result<ResolvedSettings> {
+        let file = load_config(path)?;
+        let selected = selected_profile(args, Some(&file));
+
+        Ok(ResolvedSettings {
+            file,
+            selected,
+            overrides: load_overrides(args)?,
+        })
+    }
+
+    /// Settings of the profile. The environment and the flags only apply to the
+    /// selected one.
+    fn profile(&self, profile: &str) -> std::result::Result<Configuration, MatahoError> {
+        let config = self.file.profile(profile)?;
+
+        match profile == self.selected {
+            true => Ok(config.with_overrides(&self.overrides.settings)),
+            false => Ok(config),
+        }
+    }
+
+    fn profile_names(&self) -> Vec<String> {
+        self.file.profile_names()
+    }
+}
+
+/// Settings given by the environment and the command line flags, the flags win.
+fn load_overrides(args: &Cli) -> Result<Overrides> {
+    let mut overrides = Overrides::from_env()?;
+
+    if let Some(host) = &args.host {
+        overrides.set("hostname", host, Origin::Flag("--host"))?;
+    }
+
+    if let Some(port) = args.port {
+        overrides.set("port", &port.to_string(), Origin::Flag("--port"))?;
+    }
+
+    if let Some(path) = &args.token_file {
+        overrides.set(
+            "api_token_file",
+            &path.to_string_lossy(),
+            Origin::Flag("--token-file"),
+        )?;
+    }
+
+    debug!("overrides: {:?}", overrides.origins);
+    Ok(overrides)
+}
+
+/// How to print results, `--format` being only supported by listings.
+fn output(args: &Cli) -> Result<Output> {
+    let template = match &args.format {
+        Some(format) if args.command.supports_format() => Some(Template::parse(format)?),
+        Some(_) => {
+            return Err(anyhow!(
+                "`--format` is only supported by `ls`, `info`, `state` and `group ls`"
+            ))
+        }
+        None => None,
+    };
+
+    Ok(Output::new(args.output, args.no_headers).with_template(template))
+}
+
+/// Profile given on the command line, or the default one of the config file.
+fn selected_profile(args: &Cli, settings: Option<&Configuration>) -> String {
+    match (&args.profile, settings) {
+        (Some(profile), _) => profile.clone(),
+        (None, Some(settings)) => settings.default_profile_name().to_string(),
+        (None, None) => DEFAULT_PROFILE.to_string(),
+    }
+}
+
+fn discover_gateways(
+    output: &Output,
+    profile: &str,
+    timeout: u64,
+    save: Option<&str>,
+    by_address: bool,
+) -> Result<()> {
+    let gateways = discovery::discover(Duration::from_secs(timeout))?;
+    display::print_gateways(output, &gateways)?;
+
+    let pin = match save {
+        Some(pin) => pin,
+        None => return Ok(()),
+    };
+
+    let gateway = match (pin, gateways.as_slice()) {
+        ("", [gateway]) => gateway,
+        ("", []) => return Err(anyhow!("No gateway to save")),
+        ("", _) => {
+            return Err(anyhow!(
+                "Several gateways found, pass the PIN of the one to save: --save <PIN>"
+            ))
+        }
+        (pin, gateways) => gateways
+            .iter()
+            .find(|gateway| gateway.pin == pin)
+            .ok_or_else(|| anyhow!("No gateway found with PIN `{}`", pin))?,
+    };
+
+    let hostname = if by_address {
+        gateway
+            .address_url()
+            .ok_or_else(|| anyhow!("No address resolved for gateway `{}`", gateway.pin))?
+    } else {
+        gateway.url()
+    };
+
+    MatahoService::save_gateway(profile, &hostname, gateway.port)?;
+    output.message(&format!(
+        "Saved gateway `{}` in profile `{}`: {}:{}",
+        gateway.pin, profile, hostname, gateway.port
+    ));
+
+    Ok(())
+}
+
+fn trust_gateway(config: &Configuration, yes: bool) -> Result<()> {
+    let fingerprint = tls::fetch_fingerprint(config)?;
+
+    let pinned = config.tls.fingerprint.as_deref();
+    if pinned.is_some_and(|pinned| tls::same_fingerprint(pinned, &fingerprint)) {
+        println!(
+            "The certificate of `{}` is already trusted",
+            config.hostname
+        );
+        return Ok(());
+    }
+
+    display::print_gateway_fingerprint(&config.hostname, &fingerprint, pinned);
+
+    if !yes && !confirm("Trust this certificate?")? {
+        println!("Certificate not trusted");
+        return Ok(());
+    }
+
+    MatahoService::save_tls_fingerprint(&config.profile, &fingerprint)?;
+    println!("Certificate pinned in profile `{}`", config.profile);
+
+    Ok(())
+}
+
+fn process_config_command(command: &ConfigCommands, args: &Cli, path: &Path) -> Result<()> {
+    match command {
+        ConfigCommands::Init { no_check, force } => {
+            info!("cmd::config::init");
+
+            let settings = if path.exists() {
+                Some(load_config(path)?)
+            } else {
+                None
+            };
+            let profile = selected_profile(args, settings.as_ref());
+            let overrides = load_overrides(args)?;
+
+            init_profile(settings.as_ref(), &profile, &overrides, *no_check, *force)
+        }
+        ConfigCommands::Show { origin } => {
+            info!("cmd::config::show");
+
+            let settings = ResolvedSettings::load(args, path)?;
+            let config = config::redacted(&settings.profile(&settings.selected)?);
+
+            if !origin {
+                return display::print_config(&config);
+            }
+
+            let top_level = load_top_level_settings(path)?;
+            let section = settings
+                .file
+                .profiles
+                .get(&settings.selected)
+                .map(|section| (settings.selected.as_str(), section));
+            let origins = config::origins(&top_level, section, &settings.overrides);
+
+            display::print_config_origins(&config, &origins)
+        }
+        ConfigCommands::Set { key, value } => {
+            info!("cmd::config::set: {}", key);
+
+            let setting = config::find_setting(key)?;
+            let value = setting.parse(value)?;
+            let profile = if path.exists() {
+                selected_profile(args, Some(&load_config(path)?))
+            } else {
+                selected_profile(args, None)
+            };
+
+            MatahoService::save_settings(&profile, &[(setting, value)])?;
+            println!("Set `{}` in profile `{}`", key, profile);
+            Ok(())
+        }
+        ConfigCommands::Validate {} => {
+            info!("cmd::config::validate");
+            validate_config(path, args)
+        }
+        ConfigCommands::Path {} => {
+            info!("cmd::config::path");
+
+            println!("{}", path.to_string_lossy());
+            Ok(())
+        }
+    }
+}
+
+/// Set up the gateway of the profile. The address, port and token of `overrides`
+/// are used as they are, the missing ones are asked for.
+fn init_profile(
+    settings: Option<&Configuration>,
+    profile: &str,
+    overrides: &Overrides,
+    no_check: bool,
+    force: bool,
+) -> Result<()> {
+    let current = match settings {
+        Some(settings) => match settings.profile(profile) {
+            Ok(config) => Some(config),
+            Err(MatahoError::ProfileNotFound(_)) => None,
+            Err(err) => return Err(err.into()),
+        },
+        None if profile != DEFAULT_PROFILE => {
+            return Err(MatahoError::Config(format!(
+                "Set up the `{}` profile before profile `{}`: mataho config init",
+                DEFAULT_PROFILE, profile
+            ))
+            .into())
+        }
+        None => None,
+    };
+
+    let configured = current
+        .as_ref()
+        .is_some_and(|config| config::check_profile(config).is_empty());
+    if configured && !force {
+        return Err(MatahoError::Config(format!(
+            "Profile `{}` is already set up, use --force to replace its gateway",
+            profile
+        ))
+        .into());
+    }
+
+    // A new profile inherits the top-level settings (timeout, TLS, etc.)
+    let mut config = current
+        .or_else(|| settings.and_then(|settings| settings.profile(DEFAULT_PROFILE).ok()))
+        .unwrap_or_default();
+    config.profile = profile.to_string();
+    let given = &overrides.settings;
+
+    let found = match &given.hostname {
+        Some(_) => Vec::new(),
+        None => {
+            println!("Looking for gateways on the local network...");
+            discovery::discover(Duration::from_secs(3)).unwrap_or_else(|err| {
+                warn!("discovery failed: {}", err);
+                Vec::new()
+            })
+        }
+    };
+    if !found.is_empty() {
+        display::print_gateways(&Output::new(OutputFormat::Table, false), &found)?;
+    }
+    let found = match found.as_slice() {
+        [gateway] => Some(gateway),
+        _ => None,
+    };
+
+    let hostname = match given.hostname.clone() {
+        Some(hostname) => hostname,
+        None => ask(
+            "Gateway address (e.g. https://gateway-1234-5678-9012.local)",
+            found.map(|gateway| gateway.url()).as_deref(),
+        )?,
+    };
+    config.hostname = if hostname.contains("://") {
+        hostname
+    } else {
+        format!("https://{}", hostname)
+    };
+
+    config.port = match given.port.or(found.map(|gateway| i32::from(gateway.port))) {
+        Some(port) => port,
+        None => ask("Port", Some("8443"))?
+            .parse::<u16>()
+            .map(i32::from)
+            .map_err(|err| MatahoError::Config(format!("Invalid port: {}", err)))?,
+    };
+
+    // A token source given by the environment or --token-file is saved as it is
+    let token = if given.sets_token() {
+        Profile {
+            api_token: given.api_token.clone(),
+            api_token_file: given.api_token_file.clone(),
+            api_token_command: given.api_token_command.clone(),
+            api_token_keyring: given.api_token_keyring,
+            ..Profile::default()
+        }
+    } else {
+        Profile {
+            api_token: Some(ask_token()?),
+            ..Profile::default()
+        }
+    };
+    config = config.with_overrides(&token);
+
+    let mut values = vec![
+        ("hostname", toml_edit::Value::from(config.hostname.as_str())),
+        ("port", toml_edit::Value::from(i64::from(config.port))),
+    ];
+    if let Some(api_token) = &token.api_token {
+        values.push(("api_token", toml_edit::Value::from(api_token.as_str())));
+    }
+    if let Some(path) = &token.api_token_file {
+        values.push((
+            "api_token_file",
+            toml_edit::Value::from(path.to_string_lossy().as_ref()),
+        ));
+    }
+    if let Some(command) = &token.api_token_command {
+        values.push((
+            "api_token_command",
+            toml_edit::Value::from(command.as_str()),
+        ));
+    }
+    if let Some(keyring) = token.api_token_keyring {
+        values.push(("api_token_keyring", toml_edit::Value::from(keyring)));
+    }
+
+    if !no_check {
+        let devices = match config::check_connection(&config) {
+            Err(MatahoError::UntrustedCertificate { .. }) => {
+                let fingerprint = tls::fetch_fingerprint(&config)?;
+                display::print_gateway_fingerprint(
+                    &config.hostname,
+                    &fingerprint,
+                    config.tls.fingerprint.as_deref(),
+                );
+
+                if !confirm("Trust this certificate?")? {
+                    display::print_nothing_saved();
+                    return Err(anyhow!("Certificate not trusted"));
+                }
+
+                values.push((
+                    "tls.fingerprint",
+                    toml_edit::Value::from(fingerprint.as_str()),
+                ));
+                config.tls.fingerprint = Some(fingerprint);
+                config::check_connection(&config)
+            }
+            result => result,
+        };
+
+        match devices {
+            Ok(devices) => println!(
+                "Connected to {}:{} ({} devices)",
+                config.hostname, config.port, devices
+            ),
+            Err(err) => {
+                display::print_nothing_saved();
+                return Err(err.into());
+            }
+        }
+    }
+
+    let values = values
+        .into_iter()
+        .map(|(key, value)| Ok((config::find_setting(key)?, value)))
+        .collect::<mataho::Result<Vec<_>>>()?;
+    MatahoService::save_settings(profile, &values)?;
+    println!(
+        "Saved profile `{}` in {}",
+        profile,
+        MatahoService::config_file_path()?.to_string_lossy()
+    );
+
+    Ok(())
+}
+
+fn process_auth_command(
+    command: &AuthCommands,
+    config: &Configuration,
+    overrides: &Overrides,
+) -> Result<()> {
+    if config.backend != Backend::Http {
+        return Err(MatahoError::Config(format!(
+            "Profile `{}` uses the simulator, which needs no API token",
+            config.profile
+        ))
+        .into());
+    }
+
+    match command {
+        AuthCommands::Login { no_check } => {
+            info!("cmd::auth::login");
+            login(config, *no_check)
+        }
+        AuthCommands::Check {} => {
+            info!("cmd::auth::check");
+            check_token(config, overrides)
+        }
+    }
+}
+
+/// Ask for the API token, check it and store it in the system keyring.
+fn login(config: &Configuration, no_check: bool) -> Result<()> {
+    let token = ask_token()?;
+    let given = config.with_overrides(&Profile {
+        api_token: Some(token.clone()),
+        ..Profile::default()
+    });
+
+    if !no_check {
+        config::require_gateway(&given)?;
+        match config::check_connection(&given) {
+            Ok(devices) => println!(
+                "Connected to {}:{} ({} devices)",
+                given.hostname, given.port, devices
+            ),
+            Err(err) => {
+                display::print_token_problem(&err);
+                display::print_nothing_saved();
+                return Err(err.into());
+            }
+        }
+    }
+
+    secret::store_in_keyring(&config.profile, &token)?;
+    let keyring = config::find_setting("api_token_keyring")?;
+    MatahoService::save_settings(&config.profile, &[(keyring, toml_edit::Value::from(true))])?;
+    println!(
+        "API token of profile `{}` stored in the system keyring",
+        config.profile
+    );
+
+    Ok(())
+}
+
+/// Tell where the API token comes from and whether the gateway accepts it.
+fn check_token(config: &Configuration, overrides: &Overrides) -> Result<()> {
+    config::require_gateway(config)?;
+    let source = secret::token_source(config)?.expect("checked by require_gateway");
+    let origin = overrides
+        .origins
+        .iter()
+        .find(|(key, _)| config::TOKEN_SETTINGS.contains(key))
+        .map(|(_, origin)| origin.clone())
+        .unwrap_or(Origin::File);
+    println!(
+        "API token of profile `{}`: {} ({})",
+        config.profile, source, origin
+    );
+
+    match config::check_connection(config) {
+        Ok(devices) => {
+            println!(
+                "The gateway {}:{} accepts the token ({} devices)",
+                config.hostname, config.port, devices
+            );
+            if source == secret::TokenSource::Inline && origin == Origin::File {
+                display::print_plaintext_token_notice();
+            }
+
+            Ok(())
+        }
+        Err(err) => {
+            display::print_token_problem(&err);
+            Err(err.into())
+        }
+    }
+}
+
+fn validate_config(path: &Path, args: &Cli) -> Result<()> {
+    if path.exists() {
+        println!("Config file: {}", path.to_string_lossy());
+    } else {
+        println!(
+            "Config file: {} (missing, only the environment and the flags are used)",
+            path.to_string_lossy()
+        );
+    }
+
+    let content = read_config_file(path)?;
+    let document: toml_edit::DocumentMut = content
+        .parse()
+        .map_err(|err| MatahoError::Config(format!("Invalid config file: {}", err)))?;
+    let settings = ResolvedSettings::load(args, path)?;
+
+    let mut problems = 0;
+    let mut first_error = None;
+
+    for key in config::unknown_keys(&document) {
+        display::print_config_check("config file", &format!("unknown setting `{}`", key));
+        problems += 1;
+    }
+
+    if let Err(err) = settings.file.profile(settings.file.default_profile_name()) {
+        display::print_config_check("default_profile", &err.to_string());
+        problems += 1;
+    }
+
+    for profile in settings.profile_names() {
+        let scope = format!("profile `{}`", profile);
+
+        let config = match settings.profile(&profile) {
+            Ok(config) => config,
+            Err(err) => {
+                display::print_config_check(&scope, &err.to_string());
+                problems += 1;
+                continue;
+            }
+        };
+
+        let profile_problems = config::check_profile(&config);
+        for problem in profile_problems.iter() {
+            display::print_config_check(&scope, problem);
+        }
+        problems += profile_problems.len();
+
+        if args.offline || !profile_problems.is_empty() {
+            continue;
+        }
+
+        match config::check_connection(&config) {
+            Ok(devices) if config.backend == Backend::Simulator => display::print_config_check(
+                &scope,
+                &format!("ok, simulated gateway ({} devices)", devices),
+            ),
+            Ok(devices) => display::print_config_check(
+                &scope,
+                &format!(
+                    "ok, connected to {}:{} ({} devices)",
+                    config.hostname, config.port, devices
+                ),
+            ),
+            Err(err) => {
+                display::print_config_check(&scope, &err.to_string());
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+
+    if problems > 0 {
+        return Err(
+            MatahoError::Config(format!("{} problems found in the config file", problems)).into(),
+        );
+    }
+
+    match first_error {
+        Some(err) => Err(err.into()),
+        None => {
+            println!("The config file is valid");
+            Ok(())
+        }
+    }
+}
+
+/// Ask a question on the terminal, an empty answer picks the default one if any.
+fn ask_token() -> Result<String> {
+    ask(
+        "API token (generated by the developer mode of the TaHoma app)",
+        None,
+    )
+}
+
+fn ask(question: &str, default: Option<&str>) -> Result<String> {
+    match default {
+        Some(default) => print!("{} [{}]: ", question, default),
+        None => print!("{}: ", question),
+    }
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+
+    match (answer.trim(), default) {
+        ("", Some(default)) => Ok(default.to_string()),
+        ("", None) => Err(anyhow!("No answer given to: {}", question)),
+        (answer, _) => Ok(answer.to_string()),
+    }
+}
+
+fn confirm(question: &str) -> Result<bool> {
+    print!("{} [y/N] ", question);
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// How the device setup is loaded, given the global flags and the command.
+#[derive(Debug, Clone, Copy)]
+struct SetupPolicy {
+    refresh: bool,
+    offline: bool,
+    local_only: bool,
+    needs_gateway: bool,
+}
+
+impl SetupPolicy {
+    fn new(args: &Cli, output: &Output) -> SetupPolicy {
+        SetupPolicy {
+            refresh: args.refresh,
+            offline: args.offline,
+            local_only: args.command.is_local_only(),
+            // Device states of `--format` templates are read from the gateway
+            needs_gateway: args.command.needs_gateway() || output.uses_states(),
+        }
+    }
+}
+
+fn load_setup(
+    policy: SetupPolicy,
+    config: &Configuration,
+    api: &dyn TahomaApi,
+) -> Result<TahomaSetupResponse> {
+    let cache_path = MatahoService::setup_cache_file_path(&config.profile)?;
+    let local_only = policy.local_only;
+
+    if policy.offline && policy.needs_gateway {
+        return Err(anyhow!(
+            "This command needs the gateway, it cannot run with --offline"
+        ));
+    }
+
+    let cache = if policy.refresh && !local_only {
+        None
+    } else {
+        SetupCache::load_for(&cache_path, config).unwrap_or_else(|err| {
+            warn!("ignoring unreadable setup cache: {}", err);
+            None
+        })
+    };
+
+    if local_only || policy.offline {
+        return match cache {
+            Some(cache) => Ok(cache.into_setup()),
+            None if local_only => Ok(TahomaSetupResponse::default()),
+            None => Err(anyhow!(
+                "No cached device setup, run a command without --offline first"
+            )),
+        };
+    }
+
+    if let Some(cache) = cache {
+        if cache.is_fresh(Duration::from_secs(config.cache_ttl)) {
+            info!("use cached setup ({}s old)", cache.age().as_secs());
+            return Ok(cache.into_setup());
+        }
+    }
+
+    let cache = SetupCache::new(api.get_setup()?).with_gateway(config);
+    if let Err(err) = cache.save(&cache_path) {
+        warn!("failed to write setup cache: {}", err);
+    }
+
+    Ok(cache.into_setup())
+}
+
+/// Service of another profile than the selected one, for commands spanning several gateways.
+fn load_profile(
+    policy: SetupPolicy,
+    settings: &ResolvedSettings,
+    profile: &str,
+) -> Result<MatahoService> {
+    info!("load profile `{}`", profile);
+    let config = settings.profile(profile)?;
+    let api = backend::from_configuration(&config)?;
+    let setup = load_setup(policy, &config, api.as_ref())?;
+
+    Ok(MatahoService::with_profile(setup, profile))
+}
+
+fn list_all_profiles(
+    output: &Output,
+    policy: SetupPolicy,
+    settings: &ResolvedSettings,
+    filter: &DeviceTypeFilter,
+    long_listing: bool,
+) -> Result<()> {
+    if output.uses_states() {
+        return Err(anyhow!(
+            "Device states of `--format` are not available with --all-profiles"
+        ));
+    }
+
+    let mut services = Vec::new();
+    let mut first_error = None;
+
+    for profile in settings.profile_names() {
+        match load_profile(policy, settings, &profile) {
+            Ok(service) => services.push(service),
+            Err(err) => {
+                display::print_profile_error(&profile, &err);
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+
+    display::print_devices(
+        output,
+        &services,
+        filter,
+        long_listing,
+        true,
+        &HashMap::new(),
+    )?;
+
+    match first_error {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
 fn process_args(
     args: Cli,
-    controller: &TahomaApiController,
+    output: &Output,
+    policy: SetupPolicy,
+    settings: &ResolvedSettings,
+    api: &dyn TahomaApi,
     mataho_service: &mut MatahoService,
 ) -> Result<()> {
     match args.command {
         Commands::List {
             filter,
             long_listing,
+            ..
         } => {
             info!("cmd::list: {}", filter);
 
-            Ok(mataho_service.print_devices(filter, long_listing))
+            let mut states = HashMap::new();
+            if output.uses_states() {
+                for device in mataho_service.filter_devices(&filter) {
+                    states.insert(device.url().to_string(), api.get_device_states(device)?);
+                }
+            }
+
+            display::print_devices(
+                output,
+                std::slice::from_ref(mataho_service),
+                &filter,
+                long_listing,
+                false,
+                &states,
+            )
         }
         Commands::Info { device, match_mode } => {
             let device = device.to_string_lossy();
             info!("cmd::info: {}", device);
 
             let device = mataho_service.find_device(&device, match_mode)?;
-            Ok(mataho_service.print_device_info(&device))
+            let states = match output.uses_states() {
+                true => Some(api.get_device_states(device)?),
+                false => None,
+            };
+            display::print_device_info(output, mataho_service, device, states.as_deref())
+        }
+        Commands::State { device, match_mode } => {
+            let device = device.to_string_lossy();
+            info!("cmd::state: {}", device);
+
+            let device = mataho_service.find_device(&device, match_mode)?;
+            let states = api.get_device_states(device)?;
+            display::print_device_states(output, mataho_service, device, &states)
         }
         Commands::Exec {
             command,
             device,
             match_mode,
             args,
+            wait,
+            timeout,
         } => {
             let device = device.to_string_lossy();
             let command = command.to_string_lossy();
             info!("cmd::exec: {} {}", device, command);
 
-            execute_on_device(
-                &controller,
-                &mataho_service,
-                &device,
-                match_mode,
-                &command,
-                &args,
-            )
+            let invocation = Invocation {
+                action: Action::Command {
+                    name: &command,
+                    args: &args,
+                },
+                wait: wait.then(|| Duration::from_secs(timeout)),
+            };
+            execute_on_device(output, api, mataho_service, &device, match_mode, invocation)
+        }
+        Commands::Open { target } => execute_intent(
+            output,
+            policy,
+            settings,
+            api,
+            mataho_service,
+            target,
+            Intent::Open,
+        ),
+        Commands::Close { target } => execute_intent(
+            output,
+            policy,
+            settings,
+            api,
+            mataho_service,
+            target,
+            Intent::Close,
+        ),
+        Commands::Stop { target } => execute_intent(
+            output,
+            policy,
+            settings,
+            api,
+            mataho_service,
+            target,
+            Intent::Stop,
+        ),
+        Commands::Set { target, percent } => execute_intent(
+            output,
+            policy,
+            settings,
+            api,
+            mataho_service,
+            target,
+            Intent::Set(percent),
+        ),
+        Commands::My {
+            device,
+            match_mode,
+            wait,
+            timeout,
+        } => {
+            let device = device.to_string_lossy();
+            info!("cmd::my: {}", device);
+
+            let invocation = Invocation {
+                action: Action::Intent(Intent::My),
+                wait: wait.then(|| Duration::from_secs(timeout)),
+            };
+            execute_on_device(output, api, mataho_service, &device, match_mode, invocation)
+        }
+        Commands::Jobs {} => {
+            info!("cmd::jobs");
+
+            let executions = api.get_current_executions()?;
+            display::print_executions(output, mataho_service, &executions)
+        }
+        Commands::Cancel { exec_id } => {
+            let exec_id = exec_id.to_string_lossy();
+            info!("cmd::cancel: {}", exec_id);
+
+            if exec_id == "all" {
+                api.cancel_all_executions()?;
+                output.message("Cancelled all running executions");
+            } else {
+                api.cancel_execution(&exec_id)?;
+                output.message(&format!("Cancelled execution `{}`", exec_id));
+            }
+            Ok(())
+        }
+        Commands::Watch {
+            device,
+            group,
+            json,
+        } => {
+            info!("cmd::watch: device={:?} group={:?}", device, group);
+
+            let device_urls = if let Some(device) = device {
+                let device =
+                    mataho_service.find_device(&device.to_string_lossy(), MatchMode::Fuzzy)?;
+                Some(HashSet::from([device.url().to_string()]))
+            } else if let Some(group) = group {
+                let group = group.to_string_lossy();
+                let group = mataho_service
+                    .find_group_by_name(&group)
+                    .ok_or_else(|| MatahoError::GroupNotFound(group.to_string()))?;
+
+                let urls = mataho_service
+                    .get_group_devices(group)
+                    .iter()
+                    .map(|device| device.url().to_string())
+                    .collect();
+                Some(urls)
+            } else {
+                None
+            };
+
+            let json = match output.format {
+                OutputFormat::Table => json,
+                OutputFormat::Json => true,
+                format => {
+                    return Err(anyhow!(
+                        "`watch` prints one JSON object per event with --output json, `{}` is not supported",
+                        format
+                    ))
+                }
+            };
+            watch_events(api, mataho_service, device_urls, json)
+        }
+        // Handled before loading the setup, they do not need it
+        Commands::Discover { .. }
+        | Commands::Trust { .. }
+        | Commands::Config { .. }
+        | Commands::Auth { .. }
+        | Commands::HelpCommand { .. } => {
+            unreachable!("processed before the setup is loaded")
         }
         Commands::Group { command } => match command {
             GroupCommands::List {} => {
                 info!("cmd::group::list");
 
-                Ok(mataho_service.print_groups())
+                display::print_groups(output, mataho_service)
             }
             GroupCommands::Create { name } => {
                 let name = name.to_string_lossy();
@@ -116,84 +1119,543 @@ fn process_args(
 
                 Ok(mataho_service.delete_group(&name)?)
             }
-            GroupCommands::AddToGroup { group, device } => {
+            GroupCommands::AddToGroup {
+                group,
+                device,
+                from,
+            } => {
                 let group = group.to_string_lossy();
                 let device = device.to_string_lossy();
-                info!("cmd::group::join: add {} to {}", device, group);
+                info!(
+                    "cmd::group::join: add {} to {} (from {:?})",
+                    device, group, from
+                );
+
+                match from.filter(|profile| profile != mataho_service.profile()) {
+                    Some(profile) => {
+                        let remote = load_profile(policy, settings, &profile)?;
+                        let device = remote.find_device(&device, MatchMode::Fuzzy)?;
+                        let member = device_group::member_id(Some(&profile), device.id());
 
-                Ok(mataho_service.add_to_group(&group, &device)?)
+                        Ok(mataho_service.add_member_to_group(&group, &member)?)
+                    }
+                    None => Ok(mataho_service.add_to_group(&group, &device)?),
+                }
             }
-            GroupCommands::RemoveFromGroup { group, device } => {
+            GroupCommands::RemoveFromGroup {
+                group,
+                device,
+                from,
+            } => {
                 let group = group.to_string_lossy();
                 let device = device.to_string_lossy();
-                info!("cmd::group::leave: remove {} from {}", device, group);
+                info!(
+                    "cmd::group::leave: remove {} from {} (from {:?})",
+                    device, group, from
+                );
+
+                match from.filter(|profile| profile != mataho_service.profile()) {
+                    Some(profile) => {
+                        let remote = load_profile(policy, settings, &profile)?;
+                        let device = remote.find_device(&device, MatchMode::Fuzzy)?;
+                        let member = device_group::member_id(Some(&profile), device.id());
 
-                Ok(mataho_service.remove_from_group(&group, &device)?)
+                        Ok(mataho_service.remove_member_from_group(&group, &member)?)
+                    }
+                    None => Ok(mataho_service.remove_from_group(&group, &device)?),
+                }
             }
             GroupCommands::Exec {
                 group,
                 command,
                 args,
+                wait,
+                timeout,
             } => {
                 let group = group.to_string_lossy();
                 let command = command.to_string_lossy();
                 info!("cmd::group::exec: {} {}", group, command);
 
-                execute_on_group(controller, mataho_service, &group, &command, &args)
+                let invocation = Invocation {
+                    action: Action::Command {
+                        name: &command,
+                        args: &args,
+                    },
+                    wait: wait.then(|| Duration::from_secs(timeout)),
+                };
+                execute_on_any_group(
+                    output,
+                    policy,
+                    settings,
+                    api,
+                    mataho_service,
+                    &group,
+                    invocation,
+                )
             }
         },
+        Commands::Scene { command } => process_scene_command(command, output, api, mataho_service),
     }
 }
 
-fn execute_on_group(
-    controller: &TahomaApiController,
+fn process_scene_command(
+    command: SceneCommands,
+    output: &Output,
+    api: &dyn TahomaApi,
+    mataho_service: &mut MatahoService,
+) -> Result<()> {
+    match command {
+        SceneCommands::List {} => {
+            info!("cmd::scene::list");
+
+            display::print_scenes(output, mataho_service)
+        }
+        SceneCommands::Create { name } => {
+            let name = name.to_string_lossy();
+            info!("cmd::scene::create: {}", name);
+
+            Ok(mataho_service.create_scene(&name)?)
+        }
+        SceneCommands::Add {
+            scene,
+            target,
+            command,
+            args,
+            group,
+            match_mode,
+        } => {
+            let scene = scene.to_string_lossy();
+            let target = target.to_string_lossy();
+            let command = command.to_string_lossy();
+            info!("cmd::scene::add: {} {} {}", scene, target, command);
+
+            let target = match group {
+                true => SceneTarget::Group(target.to_string()),
+                false => {
+                    let device = mataho_service.find_device(&target, match_mode)?;
+                    SceneTarget::Device(device.id().to_string())
+                }
+            };
+
+            let step = SceneStep::new(target, &command, &args);
+            Ok(mataho_service.add_scene_step(&scene, step)?)
+        }
+        SceneCommands::Remove { name, step } => {
+            let name = name.to_string_lossy();
+            info!("cmd::scene::rm: {} {:?}", name, step);
+
+            match step {
+                Some(step) => Ok(mataho_service.remove_scene_step(&name, step)?),
+                None => Ok(mataho_service.delete_scene(&name)?),
+            }
+        }
+        SceneCommands::Show { name } => {
+            let name = name.to_string_lossy();
+            info!("cmd::scene::show: {}", name);
+
+            let scene = mataho_service
+                .find_scene_by_name(&name)
+                .ok_or_else(|| MatahoError::SceneNotFound(name.to_string()))?;
+            display::print_scene(output, mataho_service, scene)
+        }
+        SceneCommands::Run {
+            name,
+            wait,
+            timeout,
+        } => {
+            let name = name.to_string_lossy();
+            info!("cmd::scene::run: {}", name);
+
+            run_scene(
+                output,
+                api,
+                mataho_service,
+                &name,
+                wait.then(|| Duration::from_secs(timeout)),
+            )
+        }
+    }
+}
+
+/// Send every step of the scene to the gateway as a single execution.
+fn run_scene(
+    output: &Output,
+    api: &dyn TahomaApi,
     mataho_service: &MatahoService,
-    group: &str,
-    command: &str,
-    args: &Vec<String>,
+    name: &str,
+    wait: Option<Duration>,
 ) -> Result<()> {
-    if let Some(group) = mataho_service.find_group_by_name(group) {
-        let devices = mataho_service.get_group_devices(group);
+    let scene = mataho_service
+        .find_scene_by_name(name)
+        .ok_or_else(|| MatahoError::SceneNotFound(name.to_string()))?;
+    let devices: Vec<String> = mataho_service
+        .scene_actions(scene)?
+        .iter()
+        .map(|action| action.device.label().to_string())
+        .collect();
 
-        if !devices.iter().all(|device| device.supports_action(command)) {
-            return Err(anyhow!(
-                "Given command is not supported by all devices in the group"
-            ));
+    let mut listener = match wait {
+        Some(_) => Some(EventListener::register(api)?),
+        None => None,
+    };
+    let exec_id = mataho_service.execute_scene(api, scene)?;
+
+    output.message(&format!(
+        "Running scene `{}` ({} steps on {} devices)...",
+        scene.name(),
+        scene.steps().len(),
+        devices.len()
+    ));
+
+    let result = match (listener.as_mut(), wait) {
+        (Some(listener), Some(timeout)) => Some(wait_for_execution(listener, &exec_id, timeout)?),
+        _ => None,
+    };
+    let execution = ExecutionView::new(
+        mataho_service,
+        &exec_id,
+        &format!("scene {}", scene.name()),
+        devices,
+        result.as_ref(),
+    );
+    display::print_execution(output, mataho_service, &execution, result.as_ref())?;
+
+    check_execution_result(&exec_id, result.as_ref())
+}
+
+/// What `exec`, `group exec` or an intent-level command sends, and how long to
+/// wait for its outcome (`--wait`).
+#[derive(Debug, Clone, Copy)]
+struct Invocation<'a> {
+    action: Action<'a>,
+    wait: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Action<'a> {
+    /// Native command with its arguments
+    Command { name: &'a str, args: &'a [String] },
+    /// Sent to each device as the native command it takes
+    Intent(Intent),
+}
+
+impl fmt::Display for Action<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Command { name, .. } => write!(f, "{}", name),
+            Action::Intent(intent) => write!(f, "{}", intent),
         }
+    }
+}
+
+/// Run an intent-level command (`open`, `close`, `stop`, `set`, `my`) on a
+/// device or a group.
+fn execute_intent(
+    output: &Output,
+    policy: SetupPolicy,
+    settings: &ResolvedSettings,
+    api: &dyn TahomaApi,
+    mataho_service: &MatahoService,
+    target: IntentTarget,
+    intent: Intent,
+) -> Result<()> {
+    let name = target.target.to_string_lossy();
+    info!("cmd::{}: {}", intent, name);
 
-        controller.execute_multiple(devices, command, &args)?;
+    let invocation = Invocation {
+        action: Action::Intent(intent),
+        wait: target.wait.then(|| Duration::from_secs(target.timeout)),
+    };
 
-        println!(
+    match target.group {
+        true => execute_on_any_group(
+            output,
+            policy,
+            settings,
+            api,
+            mataho_service,
+            &name,
+            invocation,
+        ),
+        false => execute_on_device(
+            output,
+            api,
+            mataho_service,
+            &name,
+            target.match_mode,
+            invocation,
+        ),
+    }
+}
+
+/// Execute on a group, with one execution per gateway when its devices are on
+/// the gateways of several profiles.
+fn execute_on_any_group(
+    output: &Output,
+    policy: SetupPolicy,
+    settings: &ResolvedSettings,
+    api: &dyn TahomaApi,
+    mataho_service: &MatahoService,
+    group: &str,
+    invocation: Invocation,
+) -> Result<()> {
+    let remote = mataho_service
+        .find_group_by_name(group)
+        .is_some_and(|group| mataho_service.is_group_remote(group));
+
+    if remote {
+        execute_on_remote_group(output, policy, settings, mataho_service, group, invocation)
+    } else {
+        execute_on_group(output, api, mataho_service, group, invocation)
+    }
+}
+
+fn execute_on_group(
+    output: &Output,
+    api: &dyn TahomaApi,
+    mataho_service: &MatahoService,
+    group: &str,
+    invocation: Invocation,
+) -> Result<()> {
+    let Invocation { action, wait } = invocation;
+    if let Some(group) = mataho_service.find_group_by_name(group) {
+        let mut listener = match wait {
+            Some(_) => Some(EventListener::register(api)?),
+            None => None,
+        };
+        let exec_id = match action {
+            Action::Command { name, args } => {
+                mataho_service.execute_on_group(api, group, name, args)?
+            }
+            Action::Intent(intent) => mataho_service.execute_intent_on_group(api, group, intent)?,
+        };
+
+        output.message(&format!(
             "Executing `{}` on group `{} ({} devices)`...",
-            command,
+            action,
             group.name(),
             group.devices().len()
+        ));
+
+        let result = match (listener.as_mut(), wait) {
+            (Some(listener), Some(timeout)) => {
+                Some(wait_for_execution(listener, &exec_id, timeout)?)
+            }
+            _ => None,
+        };
+        let devices = mataho_service
+            .get_group_devices(group)
+            .iter()
+            .map(|device| device.label().to_string())
+            .collect();
+        let execution = ExecutionView::new(
+            mataho_service,
+            &exec_id,
+            &action.to_string(),
+            devices,
+            result.as_ref(),
         );
-        return Ok(());
+        display::print_group_executions(output, mataho_service, &[execution], result.as_ref())?;
+
+        return check_execution_result(&exec_id, result.as_ref());
+    }
+
+    Err(MatahoError::GroupNotFound(group.to_string()).into())
+}
+
+/// Execute the command on a group whose devices are on the gateways of several
+/// profiles, with one execution per gateway.
+fn execute_on_remote_group(
+    output: &Output,
+    policy: SetupPolicy,
+    settings: &ResolvedSettings,
+    mataho_service: &MatahoService,
+    group: &str,
+    invocation: Invocation,
+) -> Result<()> {
+    let Invocation { action, wait } = invocation;
+    let group = mataho_service
+        .find_group_by_name(group)
+        .ok_or_else(|| MatahoError::GroupNotFound(group.to_string()))?;
+
+    let mut targets = Vec::new();
+    // Gateways whose profile could not be loaded, the others still get the command
+    let mut unreachable = Vec::new();
+    for (profile, device_ids) in mataho_service.group_members_by_profile(group) {
+        let loaded = settings
+            .profile(&profile)
+            .map_err(anyhow::Error::from)
+            .and_then(|config| {
+                let remote = match profile == mataho_service.profile() {
+                    true => None,
+                    false => Some(load_profile(policy, settings, &profile)?),
+                };
+                Ok((config, remote))
+            });
+        let (config, remote) = match loaded {
+            Ok(loaded) => loaded,
+            Err(err) => {
+                unreachable.push(GatewayOutcome {
+                    profile,
+                    devices: device_ids,
+                    execution: Err(err
+                        .downcast::<MatahoError>()
+                        .unwrap_or_else(|err| MatahoError::Other(err.to_string()))),
+                    result: None,
+                });
+                continue;
+            }
+        };
+        let service = remote.as_ref().unwrap_or(mataho_service);
+
+        let devices: Vec<Device> = device_ids
+            .iter()
+            .filter_map(|device_id| match service.find_device_by_id(device_id) {
+                Some(device) => Some(device.clone()),
+                None => {
+                    warn!("device `{}` of profile `{}` is unknown", device_id, profile);
+                    None
+                }
+            })
+            .collect();
+
+        if !devices.is_empty() {
+            targets.push(GatewayTarget { config, devices });
+        }
+    }
+
+    output.message(&format!(
+        "Executing `{}` on group `{} ({} devices)` across {} gateways...",
+        action,
+        group.name(),
+        group.devices().len(),
+        targets.len() + unreachable.len()
+    ));
+
+    let mut outcomes = match action {
+        Action::Command { name, args } => {
+            fanout::execute_on_gateways(group.name(), &targets, name, args, wait)?
+        }
+        Action::Intent(intent) => {
+            fanout::execute_intent_on_gateways(group.name(), &targets, intent, wait)?
+        }
+    };
+    outcomes.extend(unreachable);
+    display::print_gateway_outcomes(output, &outcomes, &action.to_string())?;
+
+    // Report the first failure, once every gateway was dealt with
+    for outcome in outcomes {
+        match (outcome.execution, outcome.result) {
+            (Err(err), _) | (_, Some(Err(err))) => return Err(err.into()),
+            (Ok(exec_id), Some(Ok(result))) if !result.is_success() => {
+                return check_execution_result(&exec_id, Some(&result))
+            }
+            _ => {}
+        }
     }
 
-    Err(anyhow!("No such group: `{}`", group))
+    Ok(())
 }
 
 fn execute_on_device(
-    controller: &TahomaApiController,
+    output: &Output,
+    api: &dyn TahomaApi,
     mataho_service: &MatahoService,
     device_identifier: &str,
     match_mode: MatchMode,
-    command: &str,
-    args: &Vec<String>,
+    invocation: Invocation,
 ) -> Result<()> {
-    let device = mataho_service.find_device(&device_identifier, match_mode)?;
-    if !device.supports_action(command) {
-        return Err(anyhow!(
-            "Device `{}` does not support the `{}` command",
-            device_identifier,
-            command
-        ));
+    let Invocation { action, wait } = invocation;
+    let device = mataho_service.find_device(device_identifier, match_mode)?;
+
+    let mut listener = match wait {
+        Some(_) => Some(EventListener::register(api)?),
+        None => None,
+    };
+    let exec_id = match action {
+        Action::Command { name, args } => {
+            mataho_service.execute_on_device(api, device, name, args)?
+        }
+        Action::Intent(intent) => mataho_service.execute_intent_on_device(api, device, intent)?,
+    };
+
+    output.message(&format!(
+        "Executing `{}` on `{}`...",
+        action,
+        device.label()
+    ));
+
+    let result = match (listener.as_mut(), wait) {
+        (Some(listener), Some(timeout)) => Some(wait_for_execution(listener, &exec_id, timeout)?),
+        _ => None,
+    };
+    let execution = ExecutionView::new(
+        mataho_service,
+        &exec_id,
+        &action.to_string(),
+        vec![device.label().to_string()],
+        result.as_ref(),
+    );
+    display::print_execution(output, mataho_service, &execution, result.as_ref())?;
+
+    check_execution_result(&exec_id, result.as_ref())
+}
+
+fn wait_for_execution(
+    listener: &mut EventListener,
+    exec_id: &str,
+    timeout: Duration,
+) -> Result<ExecutionResult> {
+    info!("wait for execution `{}`", exec_id);
+    Ok(listener.wait_for_execution(exec_id, timeout)?)
+}
+
+/// Fail when the execution was waited for and did not complete.
+fn check_execution_result(exec_id: &str, result: Option<&ExecutionResult>) -> Result<()> {
+    match result {
+        Some(result) if result.state() == ExecutionState::Unknown => {
+            Err(MatahoError::ExecutionOutcomeUnknown {
+                exec_id: exec_id.to_string(),
+            }
+            .into())
+        }
+        Some(result) if !result.is_success() => Err(MatahoError::ExecutionFailed {
+            exec_id: exec_id.to_string(),
+            failure_type: result.failure_type().map(|failure| failure.to_string()),
+        }
+        .into()),
+        _ => Ok(()),
     }
+}
+
+fn watch_events(
+    api: &dyn TahomaApi,
+    mataho_service: &MatahoService,
+    device_urls: Option<HashSet<String>>,
+    json: bool,
+) -> Result<()> {
+    let mut listener = EventListener::register(api)?;
+    // Executions touching the watched devices, so that their later state changes are kept too
+    let mut exec_ids: HashSet<String> = HashSet::new();
 
-    controller.execute(&device, command, args)?;
+    loop {
+        for event in listener.poll()? {
+            if let Some(device_urls) = &device_urls {
+                let about_device = event
+                    .device_urls()
+                    .iter()
+                    .any(|url| device_urls.contains(*url));
 
-    println!("Executing `{}` on `{}`...", command, device.label());
-    Ok(())
+                if about_device {
+                    if let Some(exec_id) = event.exec_id() {
+                        exec_ids.insert(exec_id.to_string());
+                    }
+                } else if !event.exec_id().is_some_and(|id| exec_ids.contains(id)) {
+                    continue;
+                }
+            }
+
+            display::print_event(mataho_service, &event, json)?;
+        }
+    }
 }
@@ -1,76 +1,237 @@
-use anyhow::{anyhow, Result};
 use clap::ValueEnum;
-use log::error;
 use serde::{Deserialize, Serialize};
-use std::fmt;
-use uuid::Uuid;
+use std::{collections::BTreeMap, fmt, path::PathBuf, str::FromStr};
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::{
+    api::Backend,
+    device_type::Category,
+    error::{MatahoError, Result},
+    tls::TlsConfig,
+};
+
+/// Name of the profile made of the top-level settings of the configuration.
+pub const DEFAULT_PROFILE: &str = "default";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Configuration {
+    /// Address of the gateway, empty until set
+    #[serde(default)]
     pub hostname: String,
+    #[serde(default = "default_port")]
     pub port: i32,
     // pod: String,
+    /// Empty until set, or when the token comes from one of the sources below
+    #[serde(default)]
     pub api_token: String,
+    /// File holding the API token, instead of `api_token`
+    pub api_token_file: Option<PathBuf>,
+    /// Command printing the API token (e.g. `pass show tahoma`), instead of `api_token`
+    pub api_token_command: Option<String>,
+    /// The API token is in the system keyring, see `mataho auth login`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub api_token_keyring: bool,
+    /// Number of seconds during which the cached device setup is used instead of the gateway
+    #[serde(default = "default_cache_ttl")]
+    pub cache_ttl: u64,
+    /// Number of seconds after which a request to the gateway is abandoned
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+    /// Where commands are sent: `http` (the gateway) or `simulator`
+    #[serde(default)]
+    pub backend: Backend,
+    /// `/setup` JSON response describing the devices of the simulator
+    pub simulator_setup: Option<PathBuf>,
+    /// Append every execution and cancellation to this JSON lines file
+    pub record_file: Option<PathBuf>,
+    /// How the certificate of the gateway is checked
+    #[serde(default)]
+    pub tls: TlsConfig,
+    /// Profile used when none is given on the command line (`default` when not set)
+    pub default_profile: Option<String>,
+    /// Other gateways, each overriding some of the top-level settings
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, Profile>,
+    /// Name of the profile these settings come from, see [`Configuration::profile`]
+    #[serde(skip, default = "default_profile_name")]
+    pub profile: String,
 }
 
-impl Configuration {
-    pub fn new() -> Configuration {
-        Configuration {
-            hostname: "https://127.0.0.1".to_string(),
-            port: 8443,
-            api_token: "REPLACE_WITH_TOKEN".to_string(),
+/// Settings of a named profile (`[profiles.<name>]`), unset ones are taken from the top level.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Profile {
+    pub hostname: Option<String>,
+    pub port: Option<i32>,
+    pub api_token: Option<String>,
+    pub api_token_file: Option<PathBuf>,
+    pub api_token_command: Option<String>,
+    pub api_token_keyring: Option<bool>,
+    pub cache_ttl: Option<u64>,
+    pub timeout: Option<u64>,
+    pub backend: Option<Backend>,
+    pub simulator_setup: Option<PathBuf>,
+    pub record_file: Option<PathBuf>,
+    pub tls: Option<TlsConfig>,
+}
+
+impl Profile {
+    /// Whether the setting with this dotted path (see [`crate::config::SETTINGS`]) is set.
+    /// The `tls` section is set or not as a whole.
+    pub fn is_set(&self, key: &str) -> bool {
+        match key {
+            "hostname" => self.hostname.is_some(),
+            "port" => self.port.is_some(),
+            "api_token" => self.api_token.is_some(),
+            "api_token_file" => self.api_token_file.is_some(),
+            "api_token_command" => self.api_token_command.is_some(),
+            "api_token_keyring" => self.api_token_keyring.is_some(),
+            "cache_ttl" => self.cache_ttl.is_some(),
+            "timeout" => self.timeout.is_some(),
+            "backend" => self.backend.is_some(),
+            "simulator_setup" => self.simulator_setup.is_some(),
+            "record_file" => self.record_file.is_some(),
+            _ if key.starts_with("tls.") => self.tls.is_some(),
+            _ => false,
         }
     }
+
+    /// Whether one of the `api_token*` settings is set. They override each
+    /// other as a whole: a profile with `api_token_file` ignores the `api_token`
+    /// of the top level.
+    pub fn sets_token(&self) -> bool {
+        self.api_token.is_some()
+            || self.api_token_file.is_some()
+            || self.api_token_command.is_some()
+            || self.api_token_keyring.is_some()
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct DeviceGroup {
-    id: String,
-    name: String,
-    devices: Vec<String>,
+fn default_profile_name() -> String {
+    DEFAULT_PROFILE.to_string()
 }
 
-impl DeviceGroup {
-    pub fn new(name: &str) -> DeviceGroup {
-        DeviceGroup {
-            id: Uuid::new_v4().to_string(),
-            name: name.to_string(),
-            devices: Vec::new(),
-        }
-    }
+fn default_port() -> i32 {
+    8443
+}
 
-    pub fn id(&self) -> &str {
-        &self.id
-    }
+fn default_cache_ttl() -> u64 {
+    3600
+}
 
-    pub fn name(&self) -> &str {
-        &self.name
+fn default_timeout() -> u64 {
+    30
+}
+
+impl Configuration {
+    pub fn new() -> Configuration {
+        Configuration {
+            hostname: String::new(),
+            port: default_port(),
+            api_token: String::new(),
+            api_token_file: None,
+            api_token_command: None,
+            api_token_keyring: false,
+            cache_ttl: default_cache_ttl(),
+            timeout: default_timeout(),
+            backend: Backend::Http,
+            simulator_setup: None,
+            record_file: None,
+            tls: TlsConfig::default(),
+            default_profile: None,
+            profiles: BTreeMap::new(),
+            profile: default_profile_name(),
+        }
     }
 
-    pub fn devices(&self) -> &Vec<String> {
-        &self.devices
+    /// Name of the profile to use when none is given on the command line.
+    pub fn default_profile_name(&self) -> &str {
+        self.default_profile.as_deref().unwrap_or(DEFAULT_PROFILE)
     }
 
-    pub fn has_device(&self, device_id: &str) -> bool {
-        self.devices.iter().any(|id| id == device_id)
+    /// Names of all the profiles, starting with the default one.
+    pub fn profile_names(&self) -> Vec<String> {
+        let mut names = vec![DEFAULT_PROFILE.to_string()];
+        names.extend(
+            self.profiles
+                .keys()
+                .filter(|name| *name != DEFAULT_PROFILE)
+                .cloned(),
+        );
+
+        names
     }
 
-    pub fn add_device(&mut self, device_id: &str) -> Result<()> {
-        if self.has_device(device_id) {
-            error!("Device `{}` already in group `{}`", device_id, self.name());
-            return Err(anyhow!("Device already in group"));
+    /// Settings of the given profile: the top-level ones, overridden by those of
+    /// `[profiles.<name>]`. The `default` profile is the top level alone, unless
+    /// there is a `[profiles.default]` section.
+    pub fn profile(&self, name: &str) -> Result<Configuration> {
+        let valid_name = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid_name {
+            return Err(MatahoError::Config(format!(
+                "Invalid profile name `{}`: only letters, digits, `-` and `_` are allowed",
+                name
+            )));
         }
 
-        Ok(self.devices.push(device_id.to_string()))
+        let overrides = match self.profiles.get(name) {
+            Some(profile) => profile.clone(),
+            None if name == DEFAULT_PROFILE => Profile::default(),
+            None => return Err(MatahoError::ProfileNotFound(name.to_string())),
+        };
+
+        let mut config = self.with_overrides(&overrides);
+        config.profile = name.to_string();
+
+        Ok(config)
     }
 
-    pub fn remove_device(&mut self, device_id: &str) -> Result<()> {
-        if !self.has_device(device_id) {
-            error!("Device `{}` not in group `{}`", device_id, self.name());
-            return Err(anyhow!("Device not in group"));
+    /// Same settings, except those set in `overrides` (e.g. by the environment).
+    /// Profiles are dropped: the result is the settings of a single profile.
+    pub fn with_overrides(&self, overrides: &Profile) -> Configuration {
+        let overrides = overrides.clone();
+        let token = if overrides.sets_token() {
+            (
+                overrides.api_token.unwrap_or_default(),
+                overrides.api_token_file,
+                overrides.api_token_command,
+                overrides.api_token_keyring.unwrap_or(false),
+            )
+        } else {
+            (
+                self.api_token.clone(),
+                self.api_token_file.clone(),
+                self.api_token_command.clone(),
+                self.api_token_keyring,
+            )
+        };
+
+        Configuration {
+            hostname: overrides.hostname.unwrap_or_else(|| self.hostname.clone()),
+            port: overrides.port.unwrap_or(self.port),
+            api_token: token.0,
+            api_token_file: token.1,
+            api_token_command: token.2,
+            api_token_keyring: token.3,
+            cache_ttl: overrides.cache_ttl.unwrap_or(self.cache_ttl),
+            timeout: overrides.timeout.unwrap_or(self.timeout),
+            backend: overrides.backend.unwrap_or(self.backend),
+            simulator_setup: overrides
+                .simulator_setup
+                .or_else(|| self.simulator_setup.clone()),
+            record_file: overrides.record_file.or_else(|| self.record_file.clone()),
+            tls: overrides.tls.unwrap_or_else(|| self.tls.clone()),
+            default_profile: None,
+            profiles: BTreeMap::new(),
+            profile: self.profile.clone(),
         }
+    }
+}
 
-        Ok(self.devices.retain(|id| id != device_id))
+impl Default for Configuration {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -80,30 +241,51 @@ pub enum MatchMode {
     Fuzzy,
 }
 
-#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+/// Devices listed by `ls --filter`.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum DeviceTypeFilter {
     All,
-    GarageDoor,
-    Gate,
-    RollerShutter,
+    Category(Category),
+    /// Human type, e.g. `roller shutter`
+    Type(String),
+    /// Controllable name, `*` matching any text
+    Controllable(String),
 }
 
-impl DeviceTypeFilter {
-    pub fn as_str(&self) -> &str {
-        match self {
-            DeviceTypeFilter::All => "",
-            DeviceTypeFilter::GarageDoor => "io:GarageOpenerIOComponent",
-            DeviceTypeFilter::Gate => "io:SlidingDiscreteGateOpenerIOComponent",
-            DeviceTypeFilter::RollerShutter => "io:RollerShutterWithLowSpeedManagementIOComponent",
+/// Filters of the first releases, whose type names have changed since.
+const LEGACY_FILTERS: &[(&str, &str)] = &[("garage-door", "garage opener")];
+
+impl FromStr for DeviceTypeFilter {
+    type Err = String;
+
+    /// `all`, a category, a controllable name (they have a `:`) or a type, whose
+    /// spaces can be written `-` (e.g. `roller-shutter`).
+    fn from_str(value: &str) -> std::result::Result<DeviceTypeFilter, String> {
+        if value.is_empty() || value.eq_ignore_ascii_case("all") {
+            return Ok(DeviceTypeFilter::All);
+        }
+        if let Ok(category) = Category::from_str(value, true) {
+            return Ok(DeviceTypeFilter::Category(category));
         }
+        if value.contains(':') {
+            return Ok(DeviceTypeFilter::Controllable(value.to_string()));
+        }
+
+        let name = match LEGACY_FILTERS.iter().find(|(legacy, _)| *legacy == value) {
+            Some((_, name)) => name.to_string(),
+            None => value.to_lowercase().replace('-', " "),
+        };
+        Ok(DeviceTypeFilter::Type(name))
     }
 }
 
 impl fmt::Display for DeviceTypeFilter {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.to_possible_value()
-            .expect("no values are skipped")
-            .get_name()
-            .fmt(f)
+        match self {
+            DeviceTypeFilter::All => write!(f, "all"),
+            DeviceTypeFilter::Category(category) => write!(f, "{}", category),
+            DeviceTypeFilter::Type(name) => write!(f, "{}", name.replace(' ', "-")),
+            DeviceTypeFilter::Controllable(pattern) => write!(f, "{}", pattern),
+        }
     }
 }
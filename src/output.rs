@@ -0,0 +1,154 @@
+use std::{fmt, io};
+
+use anyhow::Result;
+use clap::ValueEnum;
+use prettytable::{Row, Table};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::template::Template;
+
+/// How command results are printed, see `--output`.
+#[derive(ValueEnum, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Tables and text meant to be read
+    #[default]
+    Table,
+    Json,
+    Yaml,
+    Csv,
+    Tsv,
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.to_possible_value()
+            .expect("no values are skipped")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+/// Cells of a result, for the `table`, `csv` and `tsv` formats.
+#[derive(Debug, Default)]
+pub struct Rows {
+    pub headers: Vec<&'static str>,
+    pub rows: Vec<Vec<String>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Output {
+    pub format: OutputFormat,
+    /// Print the header row of tables, CSV and TSV
+    pub headers: bool,
+    /// Template of `--format`, printed for each item instead of the format
+    pub template: Option<Template>,
+}
+
+impl Output {
+    pub fn new(format: OutputFormat, no_headers: bool) -> Output {
+        Output {
+            format,
+            headers: !no_headers,
+            template: None,
+        }
+    }
+
+    pub fn with_template(self, template: Option<Template>) -> Output {
+        Output { template, ..self }
+    }
+
+    pub fn is_table(&self) -> bool {
+        self.format == OutputFormat::Table && self.template.is_none()
+    }
+
+    /// Whether the `--format` template needs device states.
+    pub fn uses_states(&self) -> bool {
+        self.template
+            .as_ref()
+            .is_some_and(|template| template.uses_states())
+    }
+
+    /// Print a result: its view model in JSON and YAML, its rows in CSV and TSV,
+    /// one line per item with a template, and whatever `table` prints otherwise.
+    pub fn print<T: Serialize + ?Sized>(
+        &self,
+        view: &T,
+        rows: Rows,
+        table: impl FnOnce(&Rows),
+    ) -> Result<()> {
+        if let Some(template) = &self.template {
+            // Render every line first, not to print a part of the result on error
+            let lines = match serde_json::to_value(view)? {
+                Value::Array(items) => items
+                    .iter()
+                    .map(|item| template.render(item))
+                    .collect::<Result<Vec<String>>>()?,
+                item => vec![template.render(&item)?],
+            };
+            for line in lines.iter() {
+                println!("{}", line);
+            }
+
+            return Ok(());
+        }
+
+        match self.format {
+            OutputFormat::Table => table(&rows),
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(view)?),
+            OutputFormat::Yaml => print!("{}", serde_yaml::to_string(view)?),
+            OutputFormat::Csv => self.write_separated(&rows, b',')?,
+            OutputFormat::Tsv => self.write_separated(&rows, b'\t')?,
+        }
+
+        Ok(())
+    }
+
+    /// Print the rows as a table, the way most results are printed by default.
+    pub fn print_table(&self, rows: &Rows) {
+        let mut table = Table::new();
+        if self.headers {
+            table.add_row(Row::from(rows.headers.iter()));
+        }
+        for row in rows.rows.iter() {
+            table.add_row(Row::from(row.iter()));
+        }
+
+        table.printstd();
+    }
+
+    /// Print a progress or status message, on stderr unless the output is meant
+    /// to be read, so that only the result is piped.
+    pub fn message(&self, message: &str) {
+        if self.is_table() {
+            println!("{}", message);
+        } else {
+            eprintln!("{}", message);
+        }
+    }
+
+    fn write_separated(&self, rows: &Rows, delimiter: u8) -> Result<()> {
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(delimiter)
+            .quote_style(match delimiter {
+                b'\t' => csv::QuoteStyle::Never,
+                _ => csv::QuoteStyle::Necessary,
+            })
+            .from_writer(io::stdout());
+
+        if self.headers {
+            writer.write_record(&rows.headers)?;
+        }
+        for row in rows.rows.iter() {
+            // TSV has no quoting: tabs and line breaks of values would add cells
+            let row = row.iter().map(|cell| match delimiter {
+                b'\t' => cell.replace(['\t', '\n', '\r'], " "),
+                _ => cell.clone(),
+            });
+            writer.write_record(row)?;
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+}
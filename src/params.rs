@@ -0,0 +1,294 @@
+//! Typed parameters of device commands.
+//!
+//! The gateway describes the parameters of a command with `nparams` and `paramsSig`
+//! (e.g. `p1,*p2`, a `*` marking an optional parameter), which say nothing of their
+//! types. Types and ranges come from a `name:type` hint in the signature when the
+//! gateway gives one, or from the [`catalog`] of known commands.
+
+use std::fmt;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::catalog::{self, CommandDoc};
+
+/// Type of a command parameter, and the values it accepts.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ParamType {
+    Integer {
+        min: Option<i64>,
+        max: Option<i64>,
+    },
+    Float {
+        min: Option<f64>,
+        max: Option<f64>,
+    },
+    Text,
+    Boolean,
+    /// One of the given words
+    Enum {
+        values: Vec<String>,
+    },
+    /// Unknown type: what reads as a number (`007`, `1e3`) is sent as a number,
+    /// anything else as text
+    Any,
+}
+
+impl ParamType {
+    fn from_hint(hint: &str) -> ParamType {
+        match hint.to_lowercase().as_str() {
+            "int" | "integer" | "long" => ParamType::Integer {
+                min: None,
+                max: None,
+            },
+            "float" | "double" | "number" => ParamType::Float {
+                min: None,
+                max: None,
+            },
+            "string" | "text" => ParamType::Text,
+            "bool" | "boolean" => ParamType::Boolean,
+            _ => ParamType::Any,
+        }
+    }
+
+    /// JSON value of the argument, or why it does not fit.
+    fn convert(&self, arg: &str) -> Result<Value, String> {
+        match self {
+            ParamType::Integer { min, max } => {
+                let value: i64 = arg
+                    .parse()
+                    .map_err(|_| format!("`{}` is not an integer", arg))?;
+                if min.is_some_and(|min| value < min) || max.is_some_and(|max| value > max) {
+                    return Err(format!("{} is out of range ({})", value, self));
+                }
+                Ok(Value::from(value))
+            }
+            ParamType::Float { min, max } => {
+                // `NaN` and `inf` parse as floats but mean nothing to the gateway
+                let value: f64 = arg
+                    .parse()
+                    .ok()
+                    .filter(|value: &f64| value.is_finite())
+                    .ok_or_else(|| format!("`{}` is not a number", arg))?;
+                if min.is_some_and(|min| value < min) || max.is_some_and(|max| value > max) {
+                    return Err(format!("{} is out of range ({})", value, self));
+                }
+                Ok(Value::from(value))
+            }
+            ParamType::Text => Ok(Value::from(arg)),
+            ParamType::Boolean => match arg {
+                "true" => Ok(Value::Bool(true)),
+                "false" => Ok(Value::Bool(false)),
+                _ => Err(format!("`{}` is not `true` or `false`", arg)),
+            },
+            ParamType::Enum { values } => match values.iter().find(|word| *word == arg) {
+                Some(word) => Ok(Value::from(word.as_str())),
+                None => Err(format!("`{}` is not one of {}", arg, self)),
+            },
+            ParamType::Any => {
+                if let Ok(value) = arg.parse::<i64>() {
+                    return Ok(Value::from(value));
+                }
+                match arg.parse::<f64>() {
+                    Ok(value) if value.is_finite() => Ok(Value::from(value)),
+                    _ => Ok(Value::from(arg)),
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for ParamType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParamType::Integer { min, max } => {
+                write!(f, "integer")?;
+                write_range(f, min.as_ref(), max.as_ref())
+            }
+            ParamType::Float { min, max } => {
+                write!(f, "number")?;
+                write_range(f, min.as_ref(), max.as_ref())
+            }
+            ParamType::Text => write!(f, "text"),
+            ParamType::Boolean => write!(f, "true|false"),
+            ParamType::Enum { values } => write!(f, "{}", values.join("|")),
+            ParamType::Any => write!(f, "value"),
+        }
+    }
+}
+
+fn write_range<T: fmt::Display>(
+    f: &mut fmt::Formatter<'_>,
+    min: Option<&T>,
+    max: Option<&T>,
+) -> fmt::Result {
+    match (min, max) {
+        (Some(min), Some(max)) => write!(f, " {}-{}", min, max),
+        (Some(min), None) => write!(f, " >= {}", min),
+        (None, Some(max)) => write!(f, " <= {}", max),
+        (None, None) => Ok(()),
+    }
+}
+
+/// One parameter of a command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub name: String,
+    pub optional: bool,
+    pub param_type: ParamType,
+    /// Unit of the value, e.g. `%` or `s`
+    pub unit: Option<String>,
+    pub description: Option<String>,
+}
+
+impl Param {
+    /// Type of the parameter followed by its unit, e.g. `integer 0-100 %`.
+    pub fn type_description(&self) -> String {
+        match &self.unit {
+            Some(unit) => format!("{} {}", self.param_type, unit),
+            None => self.param_type.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.optional {
+            true => write!(f, "[{}: {}]", self.name, self.type_description()),
+            false => write!(f, "<{}: {}>", self.name, self.type_description()),
+        }
+    }
+}
+
+/// Parameters of a command, in order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    pub command: String,
+    pub params: Vec<Param>,
+}
+
+impl Signature {
+    /// Signature of the command from what the gateway gives: `nparams` and
+    /// `paramsSig`, whose parameters are `[*]name[:type]`. The gateway decides how
+    /// many parameters there are, the catalog documents them.
+    pub fn parse(command: &str, params_count: i32, params_signature: Option<&str>) -> Signature {
+        let mut params: Vec<Param> = params_signature
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|param| !param.is_empty())
+            .map(|param| {
+                let (optional, param) = match param.strip_prefix('*') {
+                    Some(param) => (true, param),
+                    None => (false, param),
+                };
+                let (name, param_type) = match param.split_once(':') {
+                    Some((name, hint)) => (name, ParamType::from_hint(hint)),
+                    None => (param, ParamType::Any),
+                };
+
+                Param {
+                    name: name.to_string(),
+                    optional,
+                    param_type,
+                    unit: None,
+                    description: None,
+                }
+            })
+            .collect();
+
+        // Some gateways give `nparams` without `paramsSig`
+        for index in params.len()..params_count.max(0) as usize {
+            params.push(Param {
+                name: format!("p{}", index + 1),
+                optional: false,
+                param_type: ParamType::Any,
+                unit: None,
+                description: None,
+            });
+        }
+
+        if let Some(doc) = catalog::builtin().command(command) {
+            for (param, known) in params.iter_mut().zip(doc.params.iter()) {
+                if is_generic_name(&param.name) {
+                    param.name = known.name.clone();
+                }
+                if param.param_type == ParamType::Any {
+                    param.param_type = known.param_type.clone();
+                }
+                param.unit = known.unit.clone();
+                param.description = known.description.clone();
+            }
+        }
+
+        Signature {
+            command: command.to_string(),
+            params,
+        }
+    }
+
+    /// Signature of a command as documented by the catalog, whatever the device.
+    pub fn from_doc(command: &str, doc: &CommandDoc) -> Signature {
+        Signature {
+            command: command.to_string(),
+            params: doc
+                .params
+                .iter()
+                .map(|known| Param {
+                    name: known.name.clone(),
+                    optional: false,
+                    param_type: known.param_type.clone(),
+                    unit: known.unit.clone(),
+                    description: known.description.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// JSON values of the arguments, checked against the signature. The error says
+    /// what is wrong, [`Signature`] displays what is expected.
+    pub fn convert(&self, args: &[String]) -> Result<Vec<Value>, String> {
+        let required = self.params.iter().filter(|param| !param.optional).count();
+        if args.len() < required || args.len() > self.params.len() {
+            let expected = match (required, self.params.len()) {
+                (0, 0) => "none".to_string(),
+                (required, total) if required == total => total.to_string(),
+                (required, total) => format!("{} to {}", required, total),
+            };
+            return Err(format!(
+                "wrong number of arguments ({} given, {} expected)",
+                args.len(),
+                expected
+            ));
+        }
+
+        self.params
+            .iter()
+            .zip(args.iter())
+            .map(|(param, arg)| {
+                param
+                    .param_type
+                    .convert(arg)
+                    .map_err(|err| format!("{}: {}", param.name, err))
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.command)?;
+        for param in self.params.iter() {
+            write!(f, " {}", param)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether the gateway named the parameter after its position (`p1`, `p2`...).
+fn is_generic_name(name: &str) -> bool {
+    name.strip_prefix('p')
+        .is_some_and(|index| !index.is_empty() && index.chars().all(|c| c.is_ascii_digit()))
+}
@@ -0,0 +1,127 @@
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::error::Result;
+use log::info;
+use serde_json::{json, Value};
+
+use crate::{
+    api::TahomaApi,
+    controller::{exec_label, ExecAction, ExecRequest, TahomaSetupResponse},
+    device::{Device, DeviceState},
+    events::Event,
+    execution::Execution,
+};
+
+/// Backend that forwards every call to another backend and appends the calls
+/// that act on the house (executions, cancellations) to a JSON lines file.
+pub struct RecordingApi<A: TahomaApi> {
+    inner: A,
+    path: PathBuf,
+}
+
+impl<A: TahomaApi> RecordingApi<A> {
+    pub fn new(inner: A, path: &Path) -> RecordingApi<A> {
+        RecordingApi {
+            inner,
+            path: path.to_path_buf(),
+        }
+    }
+
+    fn record(&self, call: &str, payload: Value) -> Result<()> {
+        info!("record `{}` to `{}`", call, self.path.to_string_lossy());
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let line = json!({
+            "timestamp": timestamp,
+            "call": call,
+            "payload": payload,
+        });
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", line)?;
+
+        Ok(())
+    }
+}
+
+impl<A: TahomaApi> TahomaApi for RecordingApi<A> {
+    fn get_setup(&self) -> Result<TahomaSetupResponse> {
+        self.inner.get_setup()
+    }
+
+    fn execute(&self, device: &Device, command: &str, params: &[String]) -> Result<String> {
+        let batch = ExecAction::same_command(&[device], command, params)?;
+        self.record(
+            "execute",
+            serde_json::to_value(ExecRequest::new(&exec_label(command, &[device]), &batch))?,
+        )?;
+        self.inner.execute(device, command, params)
+    }
+
+    fn execute_multiple(
+        &self,
+        devices: Vec<&Device>,
+        command: &str,
+        params: &[String],
+    ) -> Result<String> {
+        let batch = ExecAction::same_command(&devices, command, params)?;
+        self.record(
+            "execute_multiple",
+            serde_json::to_value(ExecRequest::new(&exec_label(command, &devices), &batch))?,
+        )?;
+        self.inner.execute_multiple(devices, command, params)
+    }
+
+    fn execute_batch(&self, label: &str, batch: &[ExecAction]) -> Result<String> {
+        self.record(
+            "execute_batch",
+            serde_json::to_value(ExecRequest::new(label, batch))?,
+        )?;
+        self.inner.execute_batch(label, batch)
+    }
+
+    fn get_device_states(&self, device: &Device) -> Result<Vec<DeviceState>> {
+        self.inner.get_device_states(device)
+    }
+
+    fn get_current_executions(&self) -> Result<Vec<Execution>> {
+        self.inner.get_current_executions()
+    }
+
+    fn get_execution(&self, exec_id: &str) -> Result<Option<Execution>> {
+        self.inner.get_execution(exec_id)
+    }
+
+    fn cancel_execution(&self, exec_id: &str) -> Result<()> {
+        self.record("cancel_execution", json!({ "execId": exec_id }))?;
+        self.inner.cancel_execution(exec_id)
+    }
+
+    fn cancel_all_executions(&self) -> Result<()> {
+        self.record("cancel_all_executions", Value::Null)?;
+        self.inner.cancel_all_executions()
+    }
+
+    fn register_event_listener(&self) -> Result<String> {
+        self.inner.register_event_listener()
+    }
+
+    fn fetch_events(&self, listener_id: &str) -> Result<Vec<Event>> {
+        self.inner.fetch_events(listener_id)
+    }
+
+    fn unregister_event_listener(&self, listener_id: &str) -> Result<()> {
+        self.inner.unregister_event_listener(listener_id)
+    }
+}
@@ -0,0 +1,101 @@
+use std::fmt;
+
+use crate::error::{MatahoError, Result};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// What a step of a scene acts on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SceneTarget {
+    /// ID of a device of the gateway of the profile
+    Device(String),
+    /// Name of a group of the profile, whose devices are resolved when the scene runs
+    Group(String),
+}
+
+impl fmt::Display for SceneTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneTarget::Device(id) => write!(f, "device `{}`", id),
+            SceneTarget::Group(name) => write!(f, "group `{}`", name),
+        }
+    }
+}
+
+/// A command sent to a device or to every device of a group.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SceneStep {
+    target: SceneTarget,
+    command: String,
+    #[serde(default)]
+    params: Vec<String>,
+}
+
+impl SceneStep {
+    pub fn new(target: SceneTarget, command: &str, params: &[String]) -> SceneStep {
+        SceneStep {
+            target,
+            command: command.to_string(),
+            params: params.to_vec(),
+        }
+    }
+
+    pub fn target(&self) -> &SceneTarget {
+        &self.target
+    }
+
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    pub fn params(&self) -> &[String] {
+        &self.params
+    }
+}
+
+/// Named list of steps, all sent to the gateway as a single execution.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Scene {
+    id: String,
+    name: String,
+    steps: Vec<SceneStep>,
+}
+
+impl Scene {
+    pub fn new(name: &str) -> Scene {
+        Scene {
+            id: Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            steps: Vec::new(),
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn steps(&self) -> &Vec<SceneStep> {
+        &self.steps
+    }
+
+    pub fn add_step(&mut self, step: SceneStep) {
+        self.steps.push(step);
+    }
+
+    /// Remove the step at the given position, starting at 1 as `scene show` prints them.
+    pub fn remove_step(&mut self, position: usize) -> Result<SceneStep> {
+        if position == 0 || position > self.steps.len() {
+            return Err(MatahoError::SceneStepNotFound {
+                scene: self.name().to_string(),
+                step: position,
+            });
+        }
+
+        Ok(self.steps.remove(position - 1))
+    }
+}
@@ -0,0 +1,206 @@
+//! Where the API token comes from: the config file itself (`api_token`), a file
+//! (`api_token_file`), the output of a command (`api_token_command`) or the
+//! system keyring (`api_token_keyring`, filled by `mataho auth login`).
+
+use std::{fmt, fs, path::Path, process::Command, sync::OnceLock};
+
+use crate::{
+    error::{MatahoError, Result},
+    model::Configuration,
+};
+
+/// Service name of the keyring entries, the user name being the profile.
+pub const KEYRING_SERVICE: &str = "mataho";
+
+/// Setting the API token of a profile is read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenSource<'a> {
+    /// `api_token`
+    Inline,
+    /// `api_token_file`
+    File(&'a Path),
+    /// `api_token_command`
+    Command(&'a str),
+    /// `api_token_keyring`
+    Keyring,
+}
+
+impl fmt::Display for TokenSource<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenSource::Inline => write!(f, "`api_token`"),
+            TokenSource::File(path) => write!(f, "file `{}`", path.to_string_lossy()),
+            TokenSource::Command(command) => write!(f, "command `{}`", command),
+            TokenSource::Keyring => write!(f, "system keyring"),
+        }
+    }
+}
+
+/// Where the API token of the profile is read from, `None` when no source is set.
+/// Fails when several are set, since it would be unclear which one is used.
+pub fn token_source(config: &Configuration) -> Result<Option<TokenSource<'_>>> {
+    let mut sources = Vec::new();
+    if !config.api_token.is_empty() {
+        sources.push(TokenSource::Inline);
+    }
+    if let Some(path) = &config.api_token_file {
+        sources.push(TokenSource::File(path));
+    }
+    if let Some(command) = &config.api_token_command {
+        sources.push(TokenSource::Command(command));
+    }
+    if config.api_token_keyring {
+        sources.push(TokenSource::Keyring);
+    }
+
+    if sources.len() > 1 {
+        let sources = sources
+            .iter()
+            .map(|source| source.to_string())
+            .collect::<Vec<String>>()
+            .join(", ");
+        return Err(MatahoError::Config(format!(
+            "Several API token sources for profile `{}`: {}, keep only one",
+            config.profile, sources
+        )));
+    }
+
+    Ok(sources.pop())
+}
+
+/// Read the API token of the profile from its source.
+pub fn resolve_token(config: &Configuration) -> Result<String> {
+    let token = match token_source(config)? {
+        None => {
+            return Err(MatahoError::Config(format!(
+                "No API token for profile `{}`: run `mataho auth login`, or set `api_token`, `api_token_file` or `api_token_command`",
+                config.profile
+            )))
+        }
+        Some(TokenSource::Inline) => return Ok(config.api_token.clone()),
+        Some(TokenSource::File(path)) => fs::read_to_string(path).map_err(|err| {
+            MatahoError::Config(format!(
+                "Failed to read the API token from `{}`: {}",
+                path.to_string_lossy(),
+                err
+            ))
+        })?,
+        Some(TokenSource::Command(command)) => run_token_command(command)?,
+        Some(TokenSource::Keyring) => read_from_keyring(&config.profile)?,
+    };
+
+    let token = token.trim();
+    if token.is_empty() {
+        let source = token_source(config)?.expect("a source was found above");
+        return Err(MatahoError::Config(format!(
+            "The API token read from {} is empty",
+            source
+        )));
+    }
+
+    Ok(token.to_string())
+}
+
+/// API token of a profile, read from its source at the first request only: the
+/// commands that never reach the gateway do not run `api_token_command` nor
+/// open the keyring.
+#[derive(Debug)]
+pub struct LazyToken {
+    config: Configuration,
+    token: OnceLock<String>,
+}
+
+impl LazyToken {
+    pub fn new(config: &Configuration) -> LazyToken {
+        LazyToken {
+            config: config.clone(),
+            token: OnceLock::new(),
+        }
+    }
+
+    /// The token, read from its source the first time.
+    pub fn get(&self) -> Result<&str> {
+        if let Some(token) = self.token.get() {
+            return Ok(token);
+        }
+
+        let token = resolve_token(&self.config)?;
+        Ok(self.token.get_or_init(|| token))
+    }
+}
+
+fn run_token_command(command: &str) -> Result<String> {
+    let failed = |message: String| {
+        MatahoError::Config(format!(
+            "Failed to get the API token from `{}`: {}",
+            command, message
+        ))
+    };
+
+    let output = if cfg!(windows) {
+        Command::new("cmd").args(["/C", command]).output()
+    } else {
+        Command::new("sh").args(["-c", command]).output()
+    }
+    .map_err(|err| failed(err.to_string()))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let message = match stderr.trim() {
+            "" => output.status.to_string(),
+            stderr => format!("{} ({})", output.status, stderr),
+        };
+        return Err(failed(message));
+    }
+
+    String::from_utf8(output.stdout).map_err(|_| failed("the output is not UTF-8".to_string()))
+}
+
+#[cfg(feature = "keyring")]
+fn keyring_entry(profile: &str) -> Result<keyring::Entry> {
+    keyring::Entry::new(KEYRING_SERVICE, profile).map_err(keyring_error)
+}
+
+#[cfg(feature = "keyring")]
+fn keyring_error(err: keyring::Error) -> MatahoError {
+    MatahoError::Config(format!("System keyring: {}", err))
+}
+
+#[cfg(feature = "keyring")]
+fn read_from_keyring(profile: &str) -> Result<String> {
+    keyring_entry(profile)?
+        .get_password()
+        .map_err(|err| match err {
+            keyring::Error::NoEntry => MatahoError::Config(format!(
+                "No API token in the system keyring for profile `{}`, run `mataho auth login`",
+                profile
+            )),
+            err => keyring_error(err),
+        })
+}
+
+/// Store the API token of the profile in the system keyring, replacing the previous one.
+#[cfg(feature = "keyring")]
+pub fn store_in_keyring(profile: &str, token: &str) -> Result<()> {
+    keyring_entry(profile)?
+        .set_password(token)
+        .map_err(keyring_error)
+}
+
+#[cfg(not(feature = "keyring"))]
+fn read_from_keyring(_profile: &str) -> Result<String> {
+    Err(no_keyring())
+}
+
+/// Store the API token of the profile in the system keyring, replacing the previous one.
+#[cfg(not(feature = "keyring"))]
+pub fn store_in_keyring(_profile: &str, _token: &str) -> Result<()> {
+    Err(no_keyring())
+}
+
+#[cfg(not(feature = "keyring"))]
+fn no_keyring() -> MatahoError {
+    MatahoError::Config(
+        "mataho was built without the system keyring (`keyring` feature)".to_string(),
+    )
+}
@@ -1,25 +1,41 @@
-use std::{env, fs, path::PathBuf};
+use std::{collections::BTreeMap, env, fs, path::PathBuf};
 
-use anyhow::{anyhow, Result};
 use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
-use log::{debug, error, info};
-use prettytable::{row, Table};
+use log::{debug, error, info, warn};
+use toml_edit::{DocumentMut, Item, Table};
 use xdg::BaseDirectories;
 
 use crate::{
-    controller::TahomaSetupResponse,
+    api::TahomaApi,
+    config::{self, Setting},
+    controller::{exec_label, ExecAction, ExecCommand, TahomaSetupResponse},
     device::Device,
-    model::{Configuration, DeviceGroup, DeviceTypeFilter, MatchMode},
+    device_group::{self, DeviceGroup},
+    device_type::{DeviceType, DeviceTypeRegistry},
+    error::{MatahoError, Result},
+    intent::Intent,
+    model::{Configuration, DeviceTypeFilter, MatchMode, DEFAULT_PROFILE},
+    scene::{Scene, SceneStep, SceneTarget},
 };
 
 pub struct MatahoService {
     devices: Vec<Device>,
     groups: Vec<DeviceGroup>,
+    scenes: Vec<Scene>,
+    /// Built-in device types, with the ones of the user
+    registry: DeviceTypeRegistry,
+    /// Profile whose groups are used
+    profile: String,
 }
 
 impl MatahoService {
     pub fn new(response: TahomaSetupResponse) -> MatahoService {
-        let groups = match MatahoService::read_groups_from_file() {
+        MatahoService::with_profile(response, DEFAULT_PROFILE)
+    }
+
+    /// Same as [`MatahoService::new`], with the groups and scenes of the given profile.
+    pub fn with_profile(response: TahomaSetupResponse, profile: &str) -> MatahoService {
+        let groups = match MatahoService::read_groups_from_file(profile) {
             Ok(val) => val,
             Err(err) => {
                 error!("Error: {}", err);
@@ -27,18 +43,42 @@ impl MatahoService {
             }
         };
 
+        let scenes = match MatahoService::read_scenes_from_file(profile) {
+            Ok(val) => val,
+            Err(err) => {
+                error!("Error: {}", err);
+                Vec::new()
+            }
+        };
+
+        let registry = match MatahoService::read_device_types_from_file() {
+            Ok(val) => val,
+            Err(err) => {
+                error!("Error: {}", err);
+                DeviceTypeRegistry::builtin().clone()
+            }
+        };
+
         MatahoService {
             devices: response.devices,
             groups,
+            scenes,
+            registry,
+            profile: profile.to_string(),
         }
     }
 
+    /// Build the service from the setup returned by the given backend.
+    pub fn from_api(api: &dyn TahomaApi) -> Result<MatahoService> {
+        Ok(MatahoService::new(api.get_setup()?))
+    }
+
     pub fn get_config_dir() -> Result<PathBuf> {
         let app_name = "mataho";
         let mataho_config_var = "MATAHO_CONFIG";
 
-        if env::var(mataho_config_var).is_ok() {
-            let val = PathBuf::from(mataho_config_var);
+        if let Ok(val) = env::var(mataho_config_var) {
+            let val = PathBuf::from(val);
             info!(
                 "get config from env: {} = {}",
                 mataho_config_var,
@@ -64,21 +104,46 @@ impl MatahoService {
         }
 
         error!("No suitable place for config dir");
-        Err(anyhow!("Failed to find config dir"))
+        Err(MatahoError::Config("Failed to find config dir".to_string()))
     }
 
     pub fn config_file_path() -> Result<PathBuf> {
         Ok(Self::get_config_dir()?.join("config.toml"))
     }
 
-    pub fn groups_file_path() -> Result<PathBuf> {
-        Ok(Self::get_config_dir()?.join("groups.json"))
+    /// Device types of the user, shared by every profile.
+    pub fn device_types_file_path() -> Result<PathBuf> {
+        Ok(Self::get_config_dir()?.join("device_types.toml"))
     }
 
-    fn read_groups_from_file() -> Result<Vec<DeviceGroup>> {
-        info!("read groups from file");
+    /// Where the groups, scenes and setup cache of the profile are stored: the config dir
+    /// for the default profile, `profiles/<name>` in the config dir for the others.
+    pub fn profile_dir(profile: &str) -> Result<PathBuf> {
+        let config_dir = Self::get_config_dir()?;
 
-        let path = Self::groups_file_path()?;
+        if profile == DEFAULT_PROFILE {
+            return Ok(config_dir);
+        }
+
+        Ok(config_dir.join("profiles").join(profile))
+    }
+
+    pub fn groups_file_path(profile: &str) -> Result<PathBuf> {
+        Ok(Self::profile_dir(profile)?.join("groups.json"))
+    }
+
+    pub fn scenes_file_path(profile: &str) -> Result<PathBuf> {
+        Ok(Self::profile_dir(profile)?.join("scenes.json"))
+    }
+
+    pub fn setup_cache_file_path(profile: &str) -> Result<PathBuf> {
+        Ok(Self::profile_dir(profile)?.join("setup_cache.json"))
+    }
+
+    fn read_groups_from_file(profile: &str) -> Result<Vec<DeviceGroup>> {
+        info!("read groups of profile `{}` from file", profile);
+
+        let path = Self::groups_file_path(profile)?;
         let json = fs::read_to_string(path)?;
         let groups: Vec<DeviceGroup> = serde_json::from_str(&json)?;
         debug!("deserialize groups: `{} -> {:?}`", json, groups);
@@ -86,25 +151,152 @@ impl MatahoService {
         Ok(groups)
     }
 
-    pub fn create_config_file() -> Result<()> {
+    /// Built-in device types, after the ones of the user if they wrote any.
+    fn read_device_types_from_file() -> Result<DeviceTypeRegistry> {
+        let path = Self::device_types_file_path()?;
+        if !path.exists() {
+            return Ok(DeviceTypeRegistry::builtin().clone());
+        }
+
+        info!("read device types from file: `{}`", path.to_string_lossy());
+        DeviceTypeRegistry::with_overrides(&fs::read_to_string(path)?)
+    }
+
+    /// Scenes of the profile, none until the first one is created.
+    fn read_scenes_from_file(profile: &str) -> Result<Vec<Scene>> {
+        info!("read scenes of profile `{}` from file", profile);
+
+        let path = Self::scenes_file_path(profile)?;
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let json = fs::read_to_string(path)?;
+        let scenes: Vec<Scene> = serde_json::from_str(&json)?;
+        debug!("deserialize scenes: `{} -> {:?}`", json, scenes);
+
+        Ok(scenes)
+    }
+
+    /// Pin the certificate of the gateway in the `tls` section of the profile.
+    pub fn save_tls_fingerprint(profile: &str, fingerprint: &str) -> Result<()> {
+        Self::edit_config_file(|document| {
+            let settings = Self::profile_settings(document, profile)?;
+            if !settings.contains_key("tls") {
+                settings.insert("tls", toml_edit::table());
+            }
+            settings["tls"]["fingerprint"] = toml_edit::value(fingerprint);
+            Ok(())
+        })
+    }
+
+    /// Point the profile at another gateway.
+    pub fn save_gateway(profile: &str, hostname: &str, port: u16) -> Result<()> {
+        Self::edit_config_file(|document| {
+            let settings = Self::profile_settings(document, profile)?;
+            settings["hostname"] = toml_edit::value(hostname);
+            settings["port"] = toml_edit::value(i64::from(port));
+            Ok(())
+        })
+    }
+
+    /// Write settings into the profile, creating the config file if needed. Giving
+    /// the API token removes its other sources from the profile (see
+    /// [`config::TOKEN_SETTINGS`]). Nothing is written if the resulting file would
+    /// not be a valid configuration.
+    pub fn save_settings(profile: &str, values: &[(&Setting, toml_edit::Value)]) -> Result<()> {
+        Self::edit_config_file(|document| {
+            let settings = Self::profile_settings(document, profile)?;
+            let gives_token = values
+                .iter()
+                .any(|(setting, _)| config::TOKEN_SETTINGS.contains(&setting.key));
+            if gives_token {
+                for key in config::TOKEN_SETTINGS {
+                    settings.remove(key);
+                }
+            }
+
+            for (setting, value) in values {
+                if !setting.per_profile && profile != DEFAULT_PROFILE {
+                    return Err(MatahoError::Config(format!(
+                        "`{}` can only be set at the top level of the config file (`default` profile)",
+                        setting.key
+                    )));
+                }
+                setting.set(settings, value.clone())?;
+            }
+
+            let config: Configuration = toml::from_str(&document.to_string()).map_err(|err| {
+                MatahoError::Config(format!("The config file would be invalid: {}", err))
+            })?;
+            config.profile(profile)?;
+
+            Ok(())
+        })
+    }
+
+    /// Table holding the settings of the profile: the top level for the default
+    /// profile (unless it has its own section), `[profiles.<name>]` otherwise.
+    fn profile_settings<'a>(document: &'a mut DocumentMut, profile: &str) -> Result<&'a mut Table> {
+        let has_section = document
+            .get("profiles")
+            .and_then(|profiles| profiles.get(profile))
+            .is_some();
+
+        if profile == DEFAULT_PROFILE && !has_section {
+            return Ok(document.as_table_mut());
+        }
+
+        let not_a_section = || {
+            MatahoError::Config(format!(
+                "Cannot edit profile `{}`: `[profiles.{}]` must be a section of the config file",
+                profile, profile
+            ))
+        };
+
+        document
+            .entry("profiles")
+            .or_insert_with(|| {
+                let mut profiles = Table::new();
+                profiles.set_implicit(true);
+                Item::Table(profiles)
+            })
+            .as_table_mut()
+            .ok_or_else(not_a_section)?
+            .entry(profile)
+            .or_insert_with(toml_edit::table)
+            .as_table_mut()
+            .ok_or_else(not_a_section)
+    }
+
+    /// Change some values of the config file, keeping the rest of it (comments, order) as it is.
+    fn edit_config_file(edit: impl FnOnce(&mut DocumentMut) -> Result<()>) -> Result<()> {
         let file_path = Self::config_file_path()?;
-        info!("create config file: `{}`", file_path.to_string_lossy());
+        info!("edit config file: `{}`", file_path.to_string_lossy());
+
+        let content = if file_path.exists() {
+            fs::read_to_string(&file_path)?
+        } else {
+            info!("config file does not exist yet");
+            String::new()
+        };
+        let mut document: DocumentMut = content
+            .parse()
+            .map_err(|err| MatahoError::Config(format!("Invalid config file: {}", err)))?;
+
+        edit(&mut document)?;
+        debug!("updated config: {}", document);
 
         if let Some(config_dir) = file_path.parent() {
             fs::create_dir_all(config_dir)?;
         }
-
-        let default_config = Configuration::new();
-        let json = serde_json::to_string(&default_config)?;
-        debug!("default config serialized JSON: {}", json);
-
-        fs::write(&file_path, json)?;
+        fs::write(&file_path, document.to_string())?;
 
         Ok(())
     }
 
-    pub fn create_groups_file() -> Result<()> {
-        let file_path = Self::groups_file_path()?;
+    pub fn create_groups_file(profile: &str) -> Result<()> {
+        let file_path = Self::groups_file_path(profile)?;
         info!("create groups file: `{}`", file_path.to_string_lossy());
 
         if let Some(config_dir) = file_path.parent() {
@@ -120,69 +312,69 @@ impl MatahoService {
         Ok(())
     }
 
-    fn write_groups_to_file(groups: &Vec<DeviceGroup>) -> Result<()> {
-        let file_path = Self::groups_file_path()?;
+    fn write_groups_to_file(&self) -> Result<()> {
+        let file_path = Self::groups_file_path(&self.profile)?;
         info!("write groups to file: `{}`", file_path.to_string_lossy());
 
-        let json = serde_json::to_string(groups)?;
-        debug!("serialize groups: `{:?} -> {}`", groups, json);
+        let json = serde_json::to_string(&self.groups)?;
+        debug!("serialize groups: `{:?} -> {}`", self.groups, json);
 
         fs::write(file_path, json)?;
 
         Ok(())
     }
 
-    pub fn print_groups(&self) {
-        if self.groups.len() == 0 {
-            println!("No group");
-            return;
-        }
+    fn write_scenes_to_file(&self) -> Result<()> {
+        let file_path = Self::scenes_file_path(&self.profile)?;
+        info!("write scenes to file: `{}`", file_path.to_string_lossy());
 
-        println!("{} groups:", self.groups.len());
-        for group in self.groups.iter() {
-            let device_labels: String = group
-                .devices()
-                .iter()
-                .map(|device_id| {
-                    format!("`{}`", self.find_device_by_id(device_id).unwrap().label())
-                })
-                .collect::<Vec<String>>()
-                .join(", ");
-
-            println!(
-                "- {}({}): {}",
-                group.name(),
-                group.devices().len(),
-                &device_labels
-            );
+        if let Some(profile_dir) = file_path.parent() {
+            fs::create_dir_all(profile_dir)?;
         }
+
+        let json = serde_json::to_string(&self.scenes)?;
+        debug!("serialize scenes: `{:?} -> {}`", self.scenes, json);
+
+        fs::write(file_path, json)?;
+
+        Ok(())
     }
 
-    pub fn print_devices(&self, filter: DeviceTypeFilter, long_listing: bool) {
-        let mut table = Table::new();
+    pub fn devices(&self) -> &Vec<Device> {
+        &self.devices
+    }
 
-        if long_listing {
-            table.add_row(row!["ID", "Label", "Controllable type", "URL"]);
-        } else {
-            table.add_row(row!["ID", "Label", "Type"]);
-        }
+    pub fn filter_devices(&self, filter: &DeviceTypeFilter) -> Vec<&Device> {
+        self.devices
+            .iter()
+            .filter(|device| self.registry.matches(device, filter))
+            .collect()
+    }
 
-        for device in &self.devices {
-            if filter == DeviceTypeFilter::All || device.has_type(filter) {
-                if long_listing {
-                    table.add_row(row![
-                        device.id(),
-                        device.label(),
-                        device.controllable_name(),
-                        device.url()
-                    ]);
-                } else {
-                    table.add_row(row![device.id(), device.label(), device.device_type()]);
-                }
-            }
-        }
+    /// Type of the device, `None` when no rule knows its controllable name.
+    pub fn device_type(&self, device: &Device) -> Option<&DeviceType> {
+        self.registry.lookup(device.controllable_name())
+    }
 
-        table.printstd();
+    /// Fail when the filter names a type that no device can have.
+    pub fn check_filter(&self, filter: &DeviceTypeFilter) -> Result<()> {
+        self.registry.check_filter(filter)
+    }
+
+    pub fn groups(&self) -> &Vec<DeviceGroup> {
+        &self.groups
+    }
+
+    /// Labels of the devices in the group, or their IDs when they are unknown.
+    pub fn group_device_labels<'a>(&'a self, group: &'a DeviceGroup) -> Vec<&'a str> {
+        group
+            .devices()
+            .iter()
+            .map(|device_id| match self.find_device_by_id(device_id) {
+                Some(device) => device.label(),
+                None => device_id,
+            })
+            .collect()
     }
 
     pub fn find_group_by_name(&self, name: &str) -> Option<&DeviceGroup> {
@@ -193,25 +385,74 @@ impl MatahoService {
         self.groups.iter_mut().find(|group| group.name() == name)
     }
 
+    /// Devices of the group that are on the gateway of this profile.
     pub fn get_group_devices(&self, group: &DeviceGroup) -> Vec<&Device> {
         let mut res: Vec<&Device> = Vec::new();
 
-        for device_id in group.devices() {
-            let device = self.find_device_by_id(device_id).unwrap();
-            res.push(device);
+        for member in group.devices() {
+            let device_id = match self.local_device_id(member) {
+                Some(device_id) => device_id,
+                None => {
+                    debug!("device `{}` of group `{}` is remote", member, group.name());
+                    continue;
+                }
+            };
+
+            match self.find_device_by_id(device_id) {
+                Some(device) => res.push(device),
+                None => warn!(
+                    "device `{}` of group `{}` is unknown",
+                    device_id,
+                    group.name()
+                ),
+            }
         }
 
         res
     }
 
+    /// IDs of the devices of the group, by profile of the gateway they are on.
+    pub fn group_members_by_profile(&self, group: &DeviceGroup) -> BTreeMap<String, Vec<String>> {
+        let mut res: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+        for member in group.devices() {
+            let (profile, device_id) = device_group::parse_member(member);
+            let profile = profile.unwrap_or(&self.profile);
+
+            res.entry(profile.to_string())
+                .or_default()
+                .push(device_id.to_string());
+        }
+
+        res
+    }
+
+    /// Whether some devices of the group are on the gateway of another profile.
+    pub fn is_group_remote(&self, group: &DeviceGroup) -> bool {
+        group
+            .devices()
+            .iter()
+            .any(|member| self.local_device_id(member).is_none())
+    }
+
+    /// Device ID of the member of a group, if the device is on the gateway of this profile.
+    fn local_device_id<'a>(&self, member: &'a str) -> Option<&'a str> {
+        match device_group::parse_member(member) {
+            (Some(profile), device_id) if profile == self.profile => Some(device_id),
+            (Some(_), _) => None,
+            (None, device_id) => Some(device_id),
+        }
+    }
+
     pub fn create_group(&mut self, name: &str) -> Result<()> {
         if self.find_group_by_name(name).is_some() {
-            return Err(anyhow!("There is already a group named `{}`", name));
+            return Err(MatahoError::GroupAlreadyExists(name.to_string()));
         }
 
         let group = DeviceGroup::new(name);
+        info!("create group `{}` ({})", group.name(), group.id());
         self.groups.push(group);
-        Self::write_groups_to_file(&self.groups)?;
+        self.write_groups_to_file()?;
 
         Ok(())
     }
@@ -219,11 +460,11 @@ impl MatahoService {
     pub fn delete_group(&mut self, name: &str) -> Result<()> {
         if let Some(pos) = self.groups.iter().position(|group| group.name() == name) {
             self.groups.remove(pos);
-            Self::write_groups_to_file(&self.groups)?;
+            self.write_groups_to_file()?;
             return Ok(());
         }
 
-        Err(anyhow!("No such group: `{}`", name))
+        Err(MatahoError::GroupNotFound(name.to_string()))
     }
 
     pub fn add_to_group(&mut self, group_name: &str, device: &str) -> Result<()> {
@@ -232,14 +473,20 @@ impl MatahoService {
             device.id().to_string()
         };
 
+        self.add_member_to_group(group_name, &device_id)
+    }
+
+    /// Add a member built with [`device_group::member_id`], the device can be on the
+    /// gateway of another profile.
+    pub fn add_member_to_group(&mut self, group_name: &str, member: &str) -> Result<()> {
         if let Some(group) = self.find_group_by_name_mut(group_name) {
-            group.add_device(&device_id)?;
-            Self::write_groups_to_file(&self.groups)?;
+            group.add_device(member)?;
+            self.write_groups_to_file()?;
             return Ok(());
         }
 
         error!("No such group: `{}`", group_name);
-        Err(anyhow!("No such group: `{}`", group_name))
+        Err(MatahoError::GroupNotFound(group_name.to_string()))
     }
 
     pub fn remove_from_group(&mut self, group_name: &str, device: &str) -> Result<()> {
@@ -248,31 +495,280 @@ impl MatahoService {
             device.id().to_string()
         };
 
+        self.remove_member_from_group(group_name, &device_id)
+    }
+
+    /// Remove a member built with [`device_group::member_id`].
+    pub fn remove_member_from_group(&mut self, group_name: &str, member: &str) -> Result<()> {
         if let Some(group) = self.find_group_by_name_mut(group_name) {
-            info!("remove `{}` from group `{}`", device_id, group.name());
-            group.remove_device(&device_id)?;
+            info!("remove `{}` from group `{}`", member, group.name());
+            group.remove_device(member)?;
 
             info!("sync groups to file");
-            Self::write_groups_to_file(&self.groups)?;
+            self.write_groups_to_file()?;
 
             return Ok(());
         }
 
         error!("No such group: `{}`", group_name);
-        Err(anyhow!("No such group: `{}`", group_name))
+        Err(MatahoError::GroupNotFound(group_name.to_string()))
+    }
+
+    pub fn scenes(&self) -> &Vec<Scene> {
+        &self.scenes
+    }
+
+    pub fn find_scene_by_name(&self, name: &str) -> Option<&Scene> {
+        self.scenes.iter().find(|scene| scene.name() == name)
+    }
+
+    pub fn create_scene(&mut self, name: &str) -> Result<()> {
+        if self.find_scene_by_name(name).is_some() {
+            return Err(MatahoError::SceneAlreadyExists(name.to_string()));
+        }
+
+        let scene = Scene::new(name);
+        info!("create scene `{}` ({})", scene.name(), scene.id());
+        self.scenes.push(scene);
+        self.write_scenes_to_file()?;
+
+        Ok(())
+    }
+
+    pub fn delete_scene(&mut self, name: &str) -> Result<()> {
+        if let Some(pos) = self.scenes.iter().position(|scene| scene.name() == name) {
+            self.scenes.remove(pos);
+            self.write_scenes_to_file()?;
+            return Ok(());
+        }
+
+        Err(MatahoError::SceneNotFound(name.to_string()))
+    }
+
+    /// Append a step to the scene, once its devices are known to support the command.
+    pub fn add_scene_step(&mut self, scene_name: &str, step: SceneStep) -> Result<()> {
+        if self.find_scene_by_name(scene_name).is_none() {
+            return Err(MatahoError::SceneNotFound(scene_name.to_string()));
+        }
+        self.step_devices(&step)?;
+
+        let scene = self
+            .scenes
+            .iter_mut()
+            .find(|scene| scene.name() == scene_name)
+            .ok_or_else(|| MatahoError::SceneNotFound(scene_name.to_string()))?;
+        info!(
+            "add {} `{}` to scene `{}`",
+            step.target(),
+            step.command(),
+            scene_name
+        );
+        scene.add_step(step);
+        self.write_scenes_to_file()?;
+
+        Ok(())
+    }
+
+    /// Remove the step at the given position of the scene, starting at 1.
+    pub fn remove_scene_step(&mut self, scene_name: &str, position: usize) -> Result<()> {
+        let scene = self
+            .scenes
+            .iter_mut()
+            .find(|scene| scene.name() == scene_name)
+            .ok_or_else(|| MatahoError::SceneNotFound(scene_name.to_string()))?;
+
+        let step = scene.remove_step(position)?;
+        info!(
+            "remove {} `{}` from scene `{}`",
+            step.target(),
+            step.command(),
+            scene_name
+        );
+        self.write_scenes_to_file()?;
+
+        Ok(())
+    }
+
+    /// Label of the device or name of the group a step acts on.
+    pub fn scene_target_label<'a>(&'a self, target: &'a SceneTarget) -> &'a str {
+        match target {
+            SceneTarget::Device(device_id) => match self.find_device_by_id(device_id) {
+                Some(device) => device.label(),
+                None => device_id,
+            },
+            SceneTarget::Group(name) => name,
+        }
+    }
+
+    /// Devices a step acts on, all of them supporting its command and arguments.
+    fn step_devices(&self, step: &SceneStep) -> Result<Vec<&Device>> {
+        let command = step.command();
+
+        match step.target() {
+            SceneTarget::Device(device_id) => {
+                let device = self
+                    .find_device_by_id(device_id)
+                    .ok_or_else(|| MatahoError::DeviceNotFound(device_id.clone()))?;
+                ExecCommand::parse(device, command, step.params())?;
+
+                Ok(vec![device])
+            }
+            SceneTarget::Group(name) => {
+                let group = self
+                    .find_group_by_name(name)
+                    .ok_or_else(|| MatahoError::GroupNotFound(name.clone()))?;
+                if self.is_group_remote(group) {
+                    return Err(MatahoError::Other(format!(
+                        "Group `{}` has devices on the gateways of other profiles, scenes run on a single gateway",
+                        name
+                    )));
+                }
+
+                let devices = self.get_group_devices(group);
+                let unsupported: Vec<String> = devices
+                    .iter()
+                    .filter(|device| !device.supports_action(command))
+                    .map(|device| device.label().to_string())
+                    .collect();
+                if !unsupported.is_empty() {
+                    return Err(MatahoError::GroupCommandNotSupported {
+                        group: name.clone(),
+                        command: command.to_string(),
+                        devices: unsupported,
+                    });
+                }
+                ExecAction::same_command(&devices, command, step.params())?;
+
+                Ok(devices)
+            }
+        }
+    }
+
+    /// Commands of the scene by device, a device acted on by several steps getting
+    /// their commands in the order of the steps.
+    pub fn scene_actions(&self, scene: &Scene) -> Result<Vec<ExecAction<'_>>> {
+        let mut actions: Vec<ExecAction> = Vec::new();
+
+        for step in scene.steps().iter() {
+            for device in self.step_devices(step)? {
+                let command = ExecCommand::parse(device, step.command(), step.params())?;
+
+                match actions
+                    .iter_mut()
+                    .find(|action| action.device.url() == device.url())
+                {
+                    Some(action) => action.commands.push(command),
+                    None => actions.push(ExecAction {
+                        device,
+                        commands: vec![command],
+                    }),
+                }
+            }
+        }
+
+        Ok(actions)
     }
 
-    pub fn print_device_info(&self, device: &Device) {
-        println!("- label: {}", device.label());
-        println!("- url: {}", device.url());
-        println!("- id: {} (last part of URL)", device.id());
-        println!("- commands:");
+    /// Run every step of the scene as a single execution.
+    pub fn execute_scene(&self, api: &dyn TahomaApi, scene: &Scene) -> Result<String> {
+        let actions = self.scene_actions(scene)?;
+        if actions.is_empty() {
+            return Err(MatahoError::EmptyScene(scene.name().to_string()));
+        }
+
+        api.execute_batch(&format!("Scene {}", scene.name()), &actions)
+    }
 
-        for command in device.definition().actions().iter() {
-            println!("\t- {}", command);
+    /// Name of the profile whose groups are used.
+    pub fn profile(&self) -> &str {
+        &self.profile
+    }
+
+    /// Label of the device with the given URL, or the URL itself if the device is unknown.
+    pub fn device_label_from_url<'a>(&'a self, url: &'a str) -> &'a str {
+        match self.find_device_by_url(url) {
+            Some(device) => device.label(),
+            None => url,
         }
     }
 
+    pub fn execute_on_device(
+        &self,
+        api: &dyn TahomaApi,
+        device: &Device,
+        command: &str,
+        args: &[String],
+    ) -> Result<String> {
+        // Check the command and its arguments whatever the backend
+        ExecCommand::parse(device, command, args)?;
+
+        api.execute(device, command, args)
+    }
+
+    pub fn execute_on_group(
+        &self,
+        api: &dyn TahomaApi,
+        group: &DeviceGroup,
+        command: &str,
+        args: &[String],
+    ) -> Result<String> {
+        let devices = self.get_group_devices(group);
+
+        let unsupported: Vec<String> = devices
+            .iter()
+            .filter(|device| !device.supports_action(command))
+            .map(|device| device.label().to_string())
+            .collect();
+
+        if !unsupported.is_empty() {
+            return Err(MatahoError::GroupCommandNotSupported {
+                group: group.name().to_string(),
+                command: command.to_string(),
+                devices: unsupported,
+            });
+        }
+        ExecAction::same_command(&devices, command, args)?;
+
+        api.execute_multiple(devices, command, args)
+    }
+
+    /// Run the intent on the device with the native command it takes.
+    pub fn execute_intent_on_device(
+        &self,
+        api: &dyn TahomaApi,
+        device: &Device,
+        intent: Intent,
+    ) -> Result<String> {
+        let batch = intent
+            .actions(&[device])
+            .map_err(|_| MatahoError::CommandNotSupported {
+                device: device.label().to_string(),
+                command: intent.to_string(),
+            })?;
+
+        api.execute_batch(&exec_label(&intent.to_string(), &[device]), &batch)
+    }
+
+    /// Run the intent on every device of the group as a single execution, each
+    /// device getting the native command it takes.
+    pub fn execute_intent_on_group(
+        &self,
+        api: &dyn TahomaApi,
+        group: &DeviceGroup,
+        intent: Intent,
+    ) -> Result<String> {
+        let devices = self.get_group_devices(group);
+        let batch = intent.actions(&devices).map_err(|unsupported| {
+            MatahoError::GroupCommandNotSupported {
+                group: group.name().to_string(),
+                command: intent.to_string(),
+                devices: unsupported,
+            }
+        })?;
+
+        api.execute_batch(&exec_label(&intent.to_string(), &devices), &batch)
+    }
+
     pub fn find_device_by_label(&self, label: &str, match_mode: MatchMode) -> Result<&Device> {
         let label = label.to_lowercase();
 
@@ -312,15 +808,14 @@ impl MatahoService {
         if best_candidates.clone().count() > 1 {
             error!("fuzzy: multiple best candidates: {:#?}", best_candidates);
 
-            let candidates_labels: String = best_candidates
-                .map(|tuple| format!("`{}`", tuple.0.label()))
-                .collect::<Vec<String>>()
-                .join(", ");
+            let candidates = best_candidates
+                .map(|tuple| tuple.0.label().to_string())
+                .collect();
 
-            return Err(anyhow!(
-                "Failed to find a single best match, there are several candidates: {}",
-                candidates_labels
-            ));
+            return Err(MatahoError::AmbiguousMatch {
+                query: label,
+                candidates,
+            });
         }
 
         if let Some(best_match) = best_candidates.next() {
@@ -328,13 +823,17 @@ impl MatahoService {
         }
 
         error!("fuzzy: no match for: {}", label);
-        Err(anyhow!("Failed to find a device that matches: `{}`", label))
+        Err(MatahoError::DeviceNotFound(label))
     }
 
     pub fn find_device_by_id(&self, id: &str) -> Option<&Device> {
         self.devices.iter().find(|device| device.id() == id)
     }
 
+    pub fn find_device_by_url(&self, url: &str) -> Option<&Device> {
+        self.devices.iter().find(|device| device.url() == url)
+    }
+
     pub fn find_device(&self, identifier: &str, match_mode: MatchMode) -> Result<&Device> {
         if let Some(device) = self.find_device_by_id(identifier) {
             return Ok(device);
@@ -0,0 +1,184 @@
+use std::{cell::RefCell, collections::HashMap, fs};
+
+use log::info;
+
+use crate::{
+    api::TahomaApi,
+    cache::SetupCache,
+    controller::{check_supported, exec_label, ExecAction, ExecRequest, TahomaSetupResponse},
+    device::{Device, DeviceState, StateValue},
+    error::{MatahoError, Result},
+    events::{Event, EventAction, ExecutionState},
+    execution::Execution,
+    model::Configuration,
+    service::MatahoService,
+};
+
+/// In-memory gateway: executions complete instantly, update the closure states
+/// of the devices and emit the same events as a real gateway would.
+pub struct SimulatedTahomaApi {
+    setup: TahomaSetupResponse,
+    state: RefCell<SimulatorState>,
+}
+
+#[derive(Default)]
+struct SimulatorState {
+    exec_count: usize,
+    events: Vec<Event>,
+    device_states: HashMap<String, Vec<DeviceState>>,
+}
+
+impl SimulatedTahomaApi {
+    pub fn new(setup: TahomaSetupResponse) -> SimulatedTahomaApi {
+        SimulatedTahomaApi {
+            setup,
+            state: RefCell::new(SimulatorState::default()),
+        }
+    }
+
+    /// Simulate the devices of `simulator_setup` (a `/setup` JSON response),
+    /// or the cached setup of the real gateway when it is not set.
+    pub fn from_configuration(config: &Configuration) -> Result<SimulatedTahomaApi> {
+        let setup = match &config.simulator_setup {
+            Some(path) => {
+                info!("read simulator setup from `{}`", path.to_string_lossy());
+                let json = fs::read_to_string(path)?;
+                serde_json::from_str(&json)?
+            }
+            None => SetupCache::load_for(
+                &MatahoService::setup_cache_file_path(&config.profile)?,
+                config,
+            )?
+            .ok_or_else(|| {
+                MatahoError::Config(
+                    "The simulator needs `simulator_setup` or a setup cache".to_string(),
+                )
+            })?
+            .into_setup(),
+        };
+
+        Ok(SimulatedTahomaApi::new(setup))
+    }
+
+    fn apply(&self, label: &str, actions: &[ExecAction]) -> Result<String> {
+        check_supported(actions)?;
+        ExecRequest::new(label, actions).validate()?;
+
+        let mut state = self.state.borrow_mut();
+        state.exec_count += 1;
+        let exec_id = format!("simulated-{}", state.exec_count);
+
+        state.events.push(Event::ExecutionRegistered {
+            exec_id: exec_id.clone(),
+            label: Some(label.to_string()),
+            actions: actions
+                .iter()
+                .map(|action| EventAction::new(action.device.url()))
+                .collect(),
+        });
+
+        for action in actions.iter() {
+            for command in action.commands.iter() {
+                let closure = match (command.name.as_str(), command.params.first()) {
+                    ("open" | "up", _) => Some(0),
+                    ("close" | "down", _) => Some(100),
+                    ("setClosure" | "setPosition", Some(param)) => param.as_i64(),
+                    _ => None,
+                };
+
+                if let Some(closure) = closure {
+                    let device = action.device;
+                    let device_states = vec![
+                        DeviceState::new("core:ClosureState", StateValue::Integer(closure)),
+                        DeviceState::new(
+                            "core:OpenClosedState",
+                            StateValue::Text(
+                                if closure == 100 { "closed" } else { "open" }.to_string(),
+                            ),
+                        ),
+                    ];
+
+                    state
+                        .device_states
+                        .insert(device.url().to_string(), device_states.clone());
+                    state.events.push(Event::DeviceStateChanged {
+                        device_url: device.url().to_string(),
+                        device_states,
+                    });
+                }
+            }
+        }
+
+        state.events.push(Event::ExecutionStateChanged {
+            exec_id: exec_id.clone(),
+            new_state: ExecutionState::Completed,
+            old_state: Some(ExecutionState::InProgress),
+            failure_type: None,
+            failed_commands: None,
+        });
+
+        Ok(exec_id)
+    }
+}
+
+impl TahomaApi for SimulatedTahomaApi {
+    fn get_setup(&self) -> Result<TahomaSetupResponse> {
+        Ok(self.setup.clone())
+    }
+
+    fn execute(&self, device: &Device, command: &str, params: &[String]) -> Result<String> {
+        self.execute_multiple(vec![device], command, params)
+    }
+
+    fn execute_multiple(
+        &self,
+        devices: Vec<&Device>,
+        command: &str,
+        params: &[String],
+    ) -> Result<String> {
+        let batch = ExecAction::same_command(&devices, command, params)?;
+
+        self.apply(&exec_label(command, &devices), &batch)
+    }
+
+    fn execute_batch(&self, label: &str, batch: &[ExecAction]) -> Result<String> {
+        self.apply(label, batch)
+    }
+
+    fn get_device_states(&self, device: &Device) -> Result<Vec<DeviceState>> {
+        let state = self.state.borrow();
+        Ok(state
+            .device_states
+            .get(device.url())
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    fn get_current_executions(&self) -> Result<Vec<Execution>> {
+        // Simulated executions complete instantly
+        Ok(Vec::new())
+    }
+
+    fn cancel_execution(&self, exec_id: &str) -> Result<()> {
+        Err(MatahoError::Other(format!(
+            "No running execution `{}`",
+            exec_id
+        )))
+    }
+
+    fn cancel_all_executions(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn register_event_listener(&self) -> Result<String> {
+        Ok("simulator".to_string())
+    }
+
+    fn fetch_events(&self, _listener_id: &str) -> Result<Vec<Event>> {
+        Ok(self.state.borrow_mut().events.drain(..).collect())
+    }
+
+    fn unregister_event_listener(&self, _listener_id: &str) -> Result<()> {
+        Ok(())
+    }
+}
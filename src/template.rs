@@ -0,0 +1,160 @@
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+/// Line template of `--format`, e.g. `{{id}}\t{{label}}\t{{state.core:ClosureState}}`.
+///
+/// Fields are those of the JSON output of the command (see `view`), dotted paths
+/// reaching nested ones. `state.<name>` is the value of a device state.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Field(String),
+}
+
+/// Prefix of the fields giving the value of a device state.
+const STATE_PREFIX: &str = "state.";
+
+impl Template {
+    /// Parse the template, `\t`, `\n` and `\\` being turned into a tab, a line
+    /// break and a backslash since shells do not do it in quoted arguments.
+    pub fn parse(template: &str) -> Result<Template> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut rest = template;
+
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix("{{") {
+                let end = after
+                    .find("}}")
+                    .ok_or_else(|| anyhow!("Unclosed `{{{{` in the format: `{}`", template))?;
+                let field = after[..end].trim();
+                if field.is_empty() {
+                    return Err(anyhow!(
+                        "Empty field `{{{{}}}}` in the format: `{}`",
+                        template
+                    ));
+                }
+
+                if !text.is_empty() {
+                    parts.push(Part::Text(std::mem::take(&mut text)));
+                }
+                parts.push(Part::Field(field.to_string()));
+                rest = &after[end + 2..];
+                continue;
+            }
+
+            let mut chars = rest.chars();
+            match (chars.next(), chars.clone().next()) {
+                (Some('\\'), Some('t')) => text.push('\t'),
+                (Some('\\'), Some('n')) => text.push('\n'),
+                (Some('\\'), Some('\\')) => text.push('\\'),
+                (Some(c), _) => {
+                    text.push(c);
+                    rest = chars.as_str();
+                    continue;
+                }
+                (None, _) => break,
+            }
+            // Skip the escaped character
+            chars.next();
+            rest = chars.as_str();
+        }
+
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+
+        Ok(Template { parts })
+    }
+
+    /// Whether some fields are device states, which have to be fetched from the gateway.
+    pub fn uses_states(&self) -> bool {
+        self.parts
+            .iter()
+            .any(|part| matches!(part, Part::Field(field) if field.starts_with(STATE_PREFIX)))
+    }
+
+    /// Fill the template with the fields of a JSON view. A device state the device
+    /// does not have is empty, an unknown field is an error.
+    pub fn render(&self, view: &Value) -> Result<String> {
+        let mut line = String::new();
+
+        for part in self.parts.iter() {
+            match part {
+                Part::Text(text) => line.push_str(text),
+                Part::Field(field) => line.push_str(&field_value(view, field)?),
+            }
+        }
+
+        Ok(line)
+    }
+}
+
+fn field_value(view: &Value, field: &str) -> Result<String> {
+    if let Some(name) = field.strip_prefix(STATE_PREFIX) {
+        let states = view
+            .get("states")
+            .and_then(Value::as_array)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Unknown field `{}` in the format: this command has no device states",
+                    field
+                )
+            })?;
+
+        let value = states
+            .iter()
+            .find(|state| state.get("name").and_then(Value::as_str) == Some(name))
+            .and_then(|state| state.get("value"));
+        return Ok(value.map(render_value).unwrap_or_default());
+    }
+
+    field
+        .split('.')
+        .try_fold(view, |value, key| value.get(key))
+        .map(render_value)
+        .ok_or_else(|| {
+            let known = match view.as_object() {
+                Some(fields) => fields
+                    .keys()
+                    .map(|key| key.as_str())
+                    .chain(view.get("states").map(|_| "state.<name>"))
+                    .collect::<Vec<&str>>()
+                    .join(", "),
+                None => String::new(),
+            };
+            anyhow!(
+                "Unknown field `{}` in the format, expected one of: {}",
+                field,
+                known
+            )
+        })
+}
+
+/// Text of a JSON value: lists are joined with `, `, their objects being
+/// represented by their label, name or ID.
+fn render_value(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(text) => text.clone(),
+        Value::Array(values) => values
+            .iter()
+            .map(|value| match value {
+                Value::Object(_) => ["label", "name", "id"]
+                    .iter()
+                    .filter_map(|key| value.get(key))
+                    .find(|value| !value.is_null())
+                    .map(render_value)
+                    .unwrap_or_else(|| value.to_string()),
+                value => render_value(value),
+            })
+            .collect::<Vec<String>>()
+            .join(", "),
+        value => value.to_string(),
+    }
+}
@@ -0,0 +1,336 @@
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use log::{debug, info, warn};
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{self, CryptoProvider},
+    pki_types::{CertificateDer, ServerName, UnixTime},
+    ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    error::{MatahoError, Result},
+    model::Configuration,
+};
+
+/// Root CA signing the certificates the Tahoma gateways present on the local API.
+const OVERKIZ_ROOT_CA: &[u8] = include_bytes!("../certs/overkiz-root-ca-2048.crt");
+
+/// How the certificate of the gateway is checked (`[tls]` section of the configuration).
+///
+/// By default, the gateway must present a certificate signed by the bundled Overkiz
+/// root CA or by one of the CAs of `ca_file`. A pinned `fingerprint` replaces these
+/// checks, and `insecure` disables them altogether.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TlsConfig {
+    /// PEM file with additional CA certificates to trust
+    pub ca_file: Option<PathBuf>,
+    /// SHA-256 fingerprint of the gateway certificate, trusted whatever its issuer
+    pub fingerprint: Option<String>,
+    /// Accept any certificate: the API token is sent to whoever answers
+    #[serde(default)]
+    pub insecure: bool,
+}
+
+/// Whether the gateway is reached over HTTPS, any address but an `http://` one.
+pub fn uses_tls(hostname: &str) -> bool {
+    !hostname.starts_with("http://")
+}
+
+/// Build the rustls configuration used to talk to the gateway.
+pub fn client_config(tls: &TlsConfig) -> Result<ClientConfig> {
+    let provider = Arc::new(crypto::ring::default_provider());
+    let builder = ClientConfig::builder_with_provider(Arc::clone(&provider))
+        .with_safe_default_protocol_versions()
+        .map_err(|err| MatahoError::Config(format!("Invalid TLS setup: {}", err)))?;
+
+    if tls.insecure {
+        warn!("TLS certificate checks are DISABLED (`tls.insecure = true`), the API token is sent to whoever answers on the gateway address");
+
+        let verifier = AcceptAnyCertificate::new(provider, None);
+        return Ok(builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(verifier))
+            .with_no_client_auth());
+    }
+
+    if let Some(fingerprint) = &tls.fingerprint {
+        info!("pin gateway certificate: {}", fingerprint);
+
+        let verifier = PinnedCertificate {
+            fingerprint: normalize_fingerprint(fingerprint),
+            provider,
+        };
+        return Ok(builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(verifier))
+            .with_no_client_auth());
+    }
+
+    let mut roots = RootCertStore::empty();
+    roots.add_parsable_certificates(bundled_root_certificates()?);
+
+    if let Some(ca_file) = &tls.ca_file {
+        info!("trust CA file: {}", ca_file.to_string_lossy());
+
+        let content = fs::read(ca_file).map_err(|err| {
+            MatahoError::Config(format!(
+                "Failed to read `tls.ca_file` ({}): {}",
+                ca_file.to_string_lossy(),
+                err
+            ))
+        })?;
+
+        let (added, ignored) = roots.add_parsable_certificates(read_certificates(&content)?);
+        debug!("CA file: {} certificates added, {} ignored", added, ignored);
+
+        if added == 0 {
+            return Err(MatahoError::Config(format!(
+                "No valid certificate in `tls.ca_file` ({})",
+                ca_file.to_string_lossy()
+            )));
+        }
+    }
+
+    Ok(builder.with_root_certificates(roots).with_no_client_auth())
+}
+
+/// Connect to the gateway without checking its certificate and return the
+/// SHA-256 fingerprint of that certificate. The API token is not sent.
+pub fn fetch_fingerprint(configuration: &Configuration) -> Result<String> {
+    let context = "Failed to get the gateway certificate";
+
+    let provider = Arc::new(crypto::ring::default_provider());
+    let seen = Arc::new(Mutex::new(None));
+    let verifier = AcceptAnyCertificate::new(provider, Some(Arc::clone(&seen)));
+
+    let tls = ClientConfig::builder_with_provider(verifier.provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|err| MatahoError::Config(format!("Invalid TLS setup: {}", err)))?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+
+    let client = reqwest::blocking::ClientBuilder::new()
+        .use_preconfigured_tls(tls)
+        .timeout(Duration::from_secs(configuration.timeout))
+        .build()
+        .map_err(|err| MatahoError::from_request(context, err))?;
+
+    let url = format!(
+        "{}:{}/enduser-mobile-web/1/apiVersion",
+        configuration.hostname, configuration.port
+    );
+    info!("GET {}", url);
+
+    // Whatever the answer, the handshake is over once it is received
+    let res = client.get(url).send();
+    debug!("result: {:?}", res);
+
+    let fingerprint = seen.lock().unwrap().take();
+    match (fingerprint, res) {
+        (Some(fingerprint), _) => Ok(fingerprint),
+        (None, Err(err)) => Err(MatahoError::from_request(context, err)),
+        (None, Ok(_)) => Err(MatahoError::Config(format!(
+            "{}: the gateway does not use TLS (`hostname` must start with `https://`)",
+            context
+        ))),
+    }
+}
+
+/// SHA-256 fingerprint of a DER certificate, as printed by `openssl x509 -fingerprint -sha256`.
+pub fn fingerprint(certificate: &[u8]) -> String {
+    Sha256::digest(certificate)
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<String>>()
+        .join(":")
+}
+
+/// Whether two fingerprints are the same, whatever their case and separators.
+pub fn same_fingerprint(left: &str, right: &str) -> bool {
+    normalize_fingerprint(left) == normalize_fingerprint(right)
+}
+
+fn normalize_fingerprint(fingerprint: &str) -> String {
+    fingerprint
+        .chars()
+        .filter(|c| c.is_ascii_hexdigit())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Certificates of the bundled Overkiz root CA, which must have at least one.
+fn bundled_root_certificates() -> Result<Vec<CertificateDer<'static>>> {
+    let certificates = read_certificates(OVERKIZ_ROOT_CA)?;
+    if certificates.is_empty() {
+        return Err(MatahoError::Config(
+            "No certificate in the bundled Overkiz root CA (certs/overkiz-root-ca-2048.crt)"
+                .to_string(),
+        ));
+    }
+
+    Ok(certificates)
+}
+
+fn read_certificates(pem: &[u8]) -> Result<Vec<CertificateDer<'static>>> {
+    let certificates = rustls_pemfile::certs(&mut &pem[..])
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|err| MatahoError::Config(format!("Invalid PEM certificate: {}", err)))?;
+
+    Ok(certificates)
+}
+
+/// Trust the gateway certificate if its fingerprint is the pinned one.
+#[derive(Debug)]
+struct PinnedCertificate {
+    /// Normalized with [`normalize_fingerprint`]
+    fingerprint: String,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinnedCertificate {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        let fingerprint = fingerprint(end_entity);
+        debug!("gateway certificate fingerprint: {}", fingerprint);
+
+        if normalize_fingerprint(&fingerprint) != self.fingerprint {
+            return Err(rustls::Error::General(format!(
+                "the certificate fingerprint `{}` is not the pinned one",
+                fingerprint
+            )));
+        }
+
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// Trust any certificate, optionally remembering the fingerprint of the last one seen.
+#[derive(Debug)]
+struct AcceptAnyCertificate {
+    provider: Arc<CryptoProvider>,
+    seen: Option<Arc<Mutex<Option<String>>>>,
+}
+
+impl AcceptAnyCertificate {
+    fn new(
+        provider: Arc<CryptoProvider>,
+        seen: Option<Arc<Mutex<Option<String>>>>,
+    ) -> AcceptAnyCertificate {
+        AcceptAnyCertificate { provider, seen }
+    }
+}
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        if let Some(seen) = &self.seen {
+            *seen.lock().unwrap() = Some(fingerprint(end_entity));
+        }
+
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_root_ca_is_a_root() {
+        let certificates = bundled_root_certificates()
+            .expect("certs/overkiz-root-ca-2048.crt must hold the Overkiz root CA PEM");
+
+        let mut roots = RootCertStore::empty();
+        let (added, _) = roots.add_parsable_certificates(certificates);
+        assert!(added >= 1);
+    }
+}
@@ -0,0 +1,728 @@
+//! What commands print with `--output json|yaml|csv|tsv` and `--format`.
+//!
+//! These view models are the stable interface for scripts: the JSON field names
+//! and their meaning do not change across releases, new fields may be added.
+//! They are built from the library types, which are free to evolve.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use mataho::{
+    catalog,
+    device::{self, Device, DeviceAction, DeviceState, StateValue},
+    device_group,
+    device_type::{Capability, Category},
+    discovery::DiscoveredGateway,
+    events::ExecutionResult,
+    execution::Execution,
+    fanout::GatewayOutcome,
+    params::Signature,
+    scene::{Scene, SceneTarget},
+    DeviceGroup, MatahoService,
+};
+use serde::Serialize;
+
+use crate::output::Rows;
+
+#[derive(Debug, Serialize)]
+pub struct DeviceView {
+    /// Profile of the gateway of the device
+    pub gateway: String,
+    pub id: String,
+    pub label: String,
+    /// Human type, `<unknown>` when no device type rule matches
+    #[serde(rename = "type")]
+    pub device_type: String,
+    /// `null` when the type is unknown
+    pub category: Option<Category>,
+    pub capabilities: Vec<Capability>,
+    pub controllable_name: String,
+    pub url: String,
+    /// Only fetched when a `--format` template uses device states
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub states: Option<Vec<StateView>>,
+}
+
+impl DeviceView {
+    pub fn new(service: &MatahoService, device: &Device) -> DeviceView {
+        let device_type = service.device_type(device);
+
+        DeviceView {
+            gateway: service.profile().to_string(),
+            id: device.id().to_string(),
+            label: device.label().to_string(),
+            device_type: device_type
+                .map_or("<unknown>", |device_type| device_type.name.as_str())
+                .to_string(),
+            category: device_type.map(|device_type| device_type.category),
+            capabilities: device_type
+                .map(|device_type| device_type.capabilities.clone())
+                .unwrap_or_default(),
+            controllable_name: device.controllable_name().to_string(),
+            url: device.url().to_string(),
+            states: None,
+        }
+    }
+
+    pub fn with_states(mut self, states: Option<&[DeviceState]>) -> DeviceView {
+        self.states = states.map(|states| states.iter().map(StateView::new).collect());
+        self
+    }
+
+    /// Columns of `ls`, `long_listing` for `ls -l`, `gateway` for `ls --all-profiles`.
+    pub fn rows(devices: &[DeviceView], long_listing: bool, gateway: bool) -> Rows {
+        let mut headers = match long_listing {
+            true => vec!["ID", "Label", "Controllable type", "URL"],
+            false => vec!["ID", "Label", "Type"],
+        };
+        if gateway {
+            headers.insert(0, "Gateway");
+        }
+
+        let rows = devices
+            .iter()
+            .map(|device| {
+                let mut row = match long_listing {
+                    true => vec![
+                        device.id.clone(),
+                        device.label.clone(),
+                        device.controllable_name.clone(),
+                        device.url.clone(),
+                    ],
+                    false => vec![
+                        device.id.clone(),
+                        device.label.clone(),
+                        device.device_type.clone(),
+                    ],
+                };
+                if gateway {
+                    row.insert(0, device.gateway.clone());
+                }
+                row
+            })
+            .collect();
+
+        Rows { headers, rows }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct CommandView {
+    pub name: String,
+    /// Number of parameters
+    pub params_count: i32,
+    /// Types of the parameters, as sent by the gateway (e.g. `p1:Integer`)
+    pub params_signature: Option<String>,
+    /// Typed parameters, e.g. `setClosure <closure: integer 0-100 %>`
+    pub usage: String,
+    /// What the command does, `null` when it is not in the catalog
+    pub description: Option<String>,
+}
+
+impl CommandView {
+    fn new(action: &DeviceAction) -> CommandView {
+        CommandView {
+            name: action.name().to_string(),
+            params_count: action.params_count(),
+            params_signature: action.params_signature().map(str::to_string),
+            usage: action.signature().to_string(),
+            description: catalog::builtin()
+                .command(action.name())
+                .map(|doc| doc.description.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeviceInfoView {
+    #[serde(flatten)]
+    pub device: DeviceView,
+    pub commands: Vec<CommandView>,
+}
+
+impl DeviceInfoView {
+    pub fn new(
+        service: &MatahoService,
+        device: &Device,
+        states: Option<&[DeviceState]>,
+    ) -> DeviceInfoView {
+        DeviceInfoView {
+            device: DeviceView::new(service, device).with_states(states),
+            commands: device
+                .definition()
+                .actions()
+                .iter()
+                .map(CommandView::new)
+                .collect(),
+        }
+    }
+
+    /// One row per command.
+    pub fn rows(&self) -> Rows {
+        Rows {
+            headers: vec![
+                "ID",
+                "Label",
+                "Command",
+                "Parameters",
+                "Signature",
+                "Usage",
+                "Description",
+            ],
+            rows: self
+                .commands
+                .iter()
+                .map(|command| {
+                    vec![
+                        self.device.id.clone(),
+                        self.device.label.clone(),
+                        command.name.clone(),
+                        command.params_count.to_string(),
+                        command.params_signature.clone().unwrap_or_default(),
+                        command.usage.clone(),
+                        command.description.clone().unwrap_or_default(),
+                    ]
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ParamView {
+    pub name: String,
+    /// Type, range and unit, e.g. `integer 0-100 %`
+    #[serde(rename = "type")]
+    pub param_type: String,
+    pub optional: bool,
+    pub description: Option<String>,
+}
+
+/// Documentation of a command or a device state, printed by `help-command`.
+#[derive(Debug, Serialize)]
+pub struct CommandHelpView {
+    pub name: String,
+    /// `command` or `state`
+    pub kind: String,
+    /// `null` when it is not in the catalog
+    pub description: Option<String>,
+    /// Typed parameters of a command (e.g. `setClosure <closure: integer 0-100 %>`),
+    /// type of the value of a state
+    pub usage: String,
+    pub params: Vec<ParamView>,
+    /// Labels of the devices of the cached setup having the command or state
+    pub devices: Vec<String>,
+}
+
+impl CommandHelpView {
+    /// Documentation of the command or state from the catalog, the gateway
+    /// definitions of the devices typing the parameters of unknown commands.
+    /// `None` when neither of them knows it.
+    pub fn new(devices: &[Device], name: &str) -> Option<CommandHelpView> {
+        let catalog = catalog::builtin();
+        let actions: Vec<(&Device, &DeviceAction)> = devices
+            .iter()
+            .filter_map(|device| Some((device, device.find_action(name)?)))
+            .collect();
+
+        if let Some(doc) = catalog.state(name) {
+            return Some(CommandHelpView {
+                name: name.to_string(),
+                kind: "state".to_string(),
+                description: Some(doc.description.clone()),
+                usage: doc.type_description(),
+                params: Vec::new(),
+                devices: devices
+                    .iter()
+                    .filter(|device| device.definition().has_state(name))
+                    .map(|device| device.label().to_string())
+                    .collect(),
+            });
+        }
+
+        let doc = catalog.command(name);
+        let signature = match (actions.first(), doc) {
+            (Some((_, action)), _) => action.signature(),
+            (None, Some(doc)) => Signature::from_doc(name, doc),
+            (None, None) => return None,
+        };
+
+        Some(CommandHelpView {
+            name: name.to_string(),
+            kind: "command".to_string(),
+            description: doc.map(|doc| doc.description.clone()),
+            usage: signature.to_string(),
+            params: signature
+                .params
+                .iter()
+                .map(|param| ParamView {
+                    name: param.name.clone(),
+                    param_type: param.type_description(),
+                    optional: param.optional,
+                    description: param.description.clone(),
+                })
+                .collect(),
+            devices: actions
+                .iter()
+                .map(|(device, _)| device.label().to_string())
+                .collect(),
+        })
+    }
+
+    pub fn rows(&self) -> Rows {
+        Rows {
+            headers: vec!["Name", "Kind", "Usage", "Description", "Devices"],
+            rows: vec![vec![
+                self.name.clone(),
+                self.kind.clone(),
+                self.usage.clone(),
+                self.description.clone().unwrap_or_default(),
+                self.devices.join(", "),
+            ]],
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct StateView {
+    pub name: String,
+    /// Number, text or `null`, typed as reported by the gateway
+    pub value: StateValue,
+}
+
+impl StateView {
+    fn new(state: &DeviceState) -> StateView {
+        StateView {
+            name: state.name().to_string(),
+            value: state.value().clone(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeviceStatesView {
+    /// Profile of the gateway of the device
+    pub gateway: String,
+    pub id: String,
+    pub label: String,
+    pub url: String,
+    pub states: Vec<StateView>,
+}
+
+impl DeviceStatesView {
+    pub fn new(
+        service: &MatahoService,
+        device: &Device,
+        states: &[DeviceState],
+    ) -> DeviceStatesView {
+        DeviceStatesView {
+            gateway: service.profile().to_string(),
+            id: device.id().to_string(),
+            label: device.label().to_string(),
+            url: device.url().to_string(),
+            states: states.iter().map(StateView::new).collect(),
+        }
+    }
+
+    /// One row per state.
+    pub fn rows(&self) -> Rows {
+        Rows {
+            headers: vec!["ID", "Label", "State", "Value"],
+            rows: self
+                .states
+                .iter()
+                .map(|state| {
+                    vec![
+                        self.id.clone(),
+                        self.label.clone(),
+                        state.name.clone(),
+                        state.value.to_string(),
+                    ]
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct GroupMemberView {
+    /// Profile of the gateway of the device
+    pub gateway: String,
+    pub id: String,
+    /// `null` when the device is unknown to the gateway of the current profile,
+    /// e.g. a device of another gateway
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GroupView {
+    pub name: String,
+    pub devices: Vec<GroupMemberView>,
+}
+
+impl GroupView {
+    pub fn new(service: &MatahoService, group: &DeviceGroup) -> GroupView {
+        let labels = service.group_device_labels(group);
+        let devices = group
+            .devices()
+            .iter()
+            .zip(labels)
+            .map(|(member, label)| {
+                let (profile, id) = device_group::parse_member(member);
+                GroupMemberView {
+                    gateway: profile.unwrap_or(service.profile()).to_string(),
+                    id: id.to_string(),
+                    label: (label != member).then(|| label.to_string()),
+                }
+            })
+            .collect();
+
+        GroupView {
+            name: group.name().to_string(),
+            devices,
+        }
+    }
+
+    pub fn rows(groups: &[GroupView]) -> Rows {
+        Rows {
+            headers: vec!["Name", "Devices", "Labels"],
+            rows: groups
+                .iter()
+                .map(|group| {
+                    let labels = group
+                        .devices
+                        .iter()
+                        .map(|device| device.label.as_deref().unwrap_or(&device.id))
+                        .collect::<Vec<&str>>()
+                        .join(", ");
+                    vec![group.name.clone(), group.devices.len().to_string(), labels]
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SceneStepView {
+    /// `device` or `group`
+    pub target_type: &'static str,
+    /// ID of the device or name of the group
+    pub target: String,
+    /// Label of the device, `null` for groups and unknown devices
+    pub label: Option<String>,
+    pub command: String,
+    pub params: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SceneView {
+    pub name: String,
+    pub steps: Vec<SceneStepView>,
+}
+
+impl SceneView {
+    pub fn new(service: &MatahoService, scene: &Scene) -> SceneView {
+        let steps = scene
+            .steps()
+            .iter()
+            .map(|step| {
+                let (target_type, target, label) = match step.target() {
+                    SceneTarget::Device(id) => (
+                        "device",
+                        id.clone(),
+                        service
+                            .find_device_by_id(id)
+                            .map(|device| device.label().to_string()),
+                    ),
+                    SceneTarget::Group(name) => ("group", name.clone(), None),
+                };
+
+                SceneStepView {
+                    target_type,
+                    target,
+                    label,
+                    command: step.command().to_string(),
+                    params: step.params().to_vec(),
+                }
+            })
+            .collect();
+
+        SceneView {
+            name: scene.name().to_string(),
+            steps,
+        }
+    }
+
+    /// One row per scene, for `scene ls`.
+    pub fn rows(scenes: &[SceneView]) -> Rows {
+        Rows {
+            headers: vec!["Name", "Steps"],
+            rows: scenes
+                .iter()
+                .map(|scene| vec![scene.name.clone(), scene.steps.len().to_string()])
+                .collect(),
+        }
+    }
+
+    /// One row per step, for `scene show`.
+    pub fn step_rows(&self) -> Rows {
+        Rows {
+            headers: vec!["Step", "Target", "Command", "Parameters"],
+            rows: self
+                .steps
+                .iter()
+                .enumerate()
+                .map(|(index, step)| {
+                    let target = match (step.target_type, &step.label) {
+                        ("group", _) => format!("group {}", step.target),
+                        (_, Some(label)) => label.clone(),
+                        (_, None) => step.target.clone(),
+                    };
+                    vec![
+                        (index + 1).to_string(),
+                        target,
+                        step.command.clone(),
+                        step.params.join(" "),
+                    ]
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct FailedCommandView {
+    pub device: String,
+    /// `null` when the gateway gave no reason
+    pub failure_type: Option<String>,
+}
+
+/// Execution sent by `exec` and `group exec`, one per gateway.
+#[derive(Debug, Serialize)]
+pub struct ExecutionView {
+    /// Profile of the gateway
+    pub gateway: String,
+    /// `null` when the gateway did not accept the execution
+    pub exec_id: Option<String>,
+    pub command: String,
+    /// Labels of the devices
+    pub devices: Vec<String>,
+    /// `STARTED` when not waited for (no `--wait`), `COMPLETED`, `FAILED` or
+    /// another final state otherwise, `ERROR` when it could not be sent or followed
+    pub state: String,
+    pub failure_type: Option<String>,
+    pub failed_commands: Vec<FailedCommandView>,
+    /// Why the execution could not be sent or followed
+    pub error: Option<String>,
+}
+
+impl ExecutionView {
+    pub fn new(
+        service: &MatahoService,
+        exec_id: &str,
+        command: &str,
+        devices: Vec<String>,
+        result: Option<&ExecutionResult>,
+    ) -> ExecutionView {
+        let mut view = ExecutionView {
+            gateway: service.profile().to_string(),
+            exec_id: Some(exec_id.to_string()),
+            command: command.to_string(),
+            devices,
+            state: "STARTED".to_string(),
+            failure_type: None,
+            failed_commands: Vec::new(),
+            error: None,
+        };
+
+        if let Some(result) = result {
+            view.state = result.state().to_string();
+            view.failure_type = result.failure_type().map(str::to_string);
+            view.failed_commands = result
+                .failed_commands()
+                .iter()
+                .map(|command| FailedCommandView {
+                    device: service
+                        .device_label_from_url(command.device_url())
+                        .to_string(),
+                    failure_type: command.failure_type().map(str::to_string),
+                })
+                .collect();
+        }
+
+        view
+    }
+
+    /// Execution on the gateway of another profile, whose devices are not known here.
+    pub fn from_outcome(outcome: &GatewayOutcome, command: &str) -> ExecutionView {
+        let mut view = ExecutionView {
+            gateway: outcome.profile.clone(),
+            exec_id: outcome.execution.as_ref().ok().cloned(),
+            command: command.to_string(),
+            devices: outcome.devices.clone(),
+            state: "STARTED".to_string(),
+            failure_type: None,
+            failed_commands: Vec::new(),
+            error: None,
+        };
+
+        match (&outcome.execution, &outcome.result) {
+            (Err(err), _) | (_, Some(Err(err))) => {
+                view.state = "ERROR".to_string();
+                view.error = Some(err.to_string());
+            }
+            (Ok(_), Some(Ok(result))) => {
+                view.state = result.state().to_string();
+                view.failure_type = result.failure_type().map(str::to_string);
+                view.failed_commands = result
+                    .failed_commands()
+                    .iter()
+                    .map(|command| FailedCommandView {
+                        device: device::id_from_url(command.device_url()).to_string(),
+                        failure_type: command.failure_type().map(str::to_string),
+                    })
+                    .collect();
+            }
+            (Ok(_), None) => {}
+        }
+
+        view
+    }
+
+    pub fn rows(executions: &[ExecutionView]) -> Rows {
+        Rows {
+            headers: vec![
+                "Gateway", "Exec ID", "Command", "Devices", "State", "Failure",
+            ],
+            rows: executions
+                .iter()
+                .map(|execution| {
+                    vec![
+                        execution.gateway.clone(),
+                        execution.exec_id.clone().unwrap_or_default(),
+                        execution.command.clone(),
+                        execution.devices.join(", "),
+                        execution.state.clone(),
+                        execution
+                            .error
+                            .clone()
+                            .or_else(|| execution.failure_type.clone())
+                            .unwrap_or_default(),
+                    ]
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Execution running on the gateway, listed by `jobs`.
+#[derive(Debug, Serialize)]
+pub struct JobView {
+    pub exec_id: String,
+    pub label: Option<String>,
+    pub owner: Option<String>,
+    pub state: Option<String>,
+    /// Milliseconds since the Unix epoch
+    pub start_time: Option<u64>,
+    /// Labels of the devices, or their IDs when they are unknown
+    pub devices: Vec<String>,
+}
+
+impl JobView {
+    pub fn new(service: &MatahoService, execution: &Execution) -> JobView {
+        JobView {
+            exec_id: execution.id().to_string(),
+            label: execution.label().map(str::to_string),
+            owner: execution.owner().map(str::to_string),
+            state: execution.state().map(|state| state.to_string()),
+            start_time: execution.start_time(),
+            devices: execution
+                .device_urls()
+                .iter()
+                .map(|url| {
+                    let id = device::id_from_url(url);
+                    match service.find_device_by_id(id) {
+                        Some(device) => device.label().to_string(),
+                        None => id.to_string(),
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    pub fn rows(jobs: &[JobView]) -> Rows {
+        Rows {
+            headers: vec!["Exec ID", "Label", "Owner", "State", "Started", "Devices"],
+            rows: jobs
+                .iter()
+                .map(|job| {
+                    let started = match job.start_time {
+                        Some(start_time) => {
+                            let start_time = UNIX_EPOCH + Duration::from_millis(start_time);
+                            let elapsed = SystemTime::now()
+                                .duration_since(start_time)
+                                .unwrap_or_default();
+                            format!("{}s ago", elapsed.as_secs())
+                        }
+                        None => "?".to_string(),
+                    };
+
+                    vec![
+                        job.exec_id.clone(),
+                        job.label.clone().unwrap_or_default(),
+                        job.owner.clone().unwrap_or_default(),
+                        job.state.clone().unwrap_or("?".to_string()),
+                        started,
+                        job.devices.join(", "),
+                    ]
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Gateway found by `discover`.
+#[derive(Debug, Serialize)]
+pub struct GatewayView {
+    pub pin: String,
+    pub hostname: String,
+    pub addresses: Vec<String>,
+    pub port: u16,
+    pub api_version: Option<String>,
+    pub firmware_version: Option<String>,
+}
+
+impl GatewayView {
+    pub fn new(gateway: &DiscoveredGateway) -> GatewayView {
+        GatewayView {
+            pin: gateway.pin.clone(),
+            hostname: gateway.hostname.clone(),
+            addresses: gateway
+                .addresses
+                .iter()
+                .map(|address| address.to_string())
+                .collect(),
+            port: gateway.port,
+            api_version: gateway.api_version.clone(),
+            firmware_version: gateway.firmware_version.clone(),
+        }
+    }
+
+    pub fn rows(gateways: &[GatewayView]) -> Rows {
+        Rows {
+            headers: vec!["PIN", "Hostname", "Address", "Port", "API", "Firmware"],
+            rows: gateways
+                .iter()
+                .map(|gateway| {
+                    vec![
+                        gateway.pin.clone(),
+                        gateway.hostname.clone(),
+                        gateway.addresses.join(", "),
+                        gateway.port.to_string(),
+                        gateway.api_version.clone().unwrap_or("?".to_string()),
+                        gateway.firmware_version.clone().unwrap_or("?".to_string()),
+                    ]
+                })
+                .collect(),
+        }
+    }
+}
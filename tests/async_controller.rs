@@ -0,0 +1,103 @@
+mod common;
+
+use common::{FakeGateway, API_TOKEN};
+use mataho::{AsyncTahomaApiController, Configuration, MatahoError, MatahoService};
+
+fn controller(gateway: &FakeGateway) -> AsyncTahomaApiController {
+    let config = Configuration {
+        hostname: "http://127.0.0.1".to_string(),
+        port: gateway.port() as i32,
+        api_token: API_TOKEN.to_string(),
+        ..Configuration::new()
+    };
+
+    AsyncTahomaApiController::new(&config).unwrap()
+}
+
+#[tokio::test]
+async fn async_controller_gets_setup_and_states() {
+    let gateway = FakeGateway::start();
+    let controller = controller(&gateway);
+
+    let service = MatahoService::new(controller.get_setup().await.unwrap());
+    let device = service.find_device_by_id("00000003").unwrap();
+
+    let states = controller.get_device_states(device).await.unwrap();
+    assert_eq!(states.len(), 5);
+}
+
+#[tokio::test]
+async fn async_controller_executes_while_polling_events() {
+    let gateway = FakeGateway::start();
+    let controller = controller(&gateway);
+
+    let service = MatahoService::new(controller.get_setup().await.unwrap());
+    let devices = vec![
+        service.find_device_by_id("00000004").unwrap(),
+        service.find_device_by_id("00000005").unwrap(),
+    ];
+
+    let listener_id = controller.register_event_listener().await.unwrap();
+    let exec_id = controller
+        .execute_multiple(devices, "close", &Vec::new())
+        .await
+        .unwrap();
+
+    let (events, executions) = tokio::join!(
+        controller.fetch_events(&listener_id),
+        controller.get_current_executions()
+    );
+    assert!(events
+        .unwrap()
+        .iter()
+        .any(|event| event.exec_id() == Some(exec_id.as_str())));
+    assert_eq!(executions.unwrap().len(), 1);
+
+    controller
+        .unregister_event_listener(&listener_id)
+        .await
+        .unwrap();
+    assert_eq!(
+        gateway.exec_payloads()[0]["actions"]
+            .as_array()
+            .unwrap()
+            .len(),
+        2
+    );
+}
+
+#[tokio::test]
+async fn async_controller_gets_one_execution() {
+    let gateway = FakeGateway::start();
+    let controller = controller(&gateway);
+
+    let execution = controller
+        .get_execution("4a1e7c3e-0000-0000-0000-000000000001")
+        .await
+        .unwrap();
+    assert!(execution.is_some());
+    assert!(controller.get_execution("gone").await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn async_controller_reads_token_from_its_source() {
+    let gateway = FakeGateway::start();
+    let config = Configuration {
+        hostname: "http://127.0.0.1".to_string(),
+        port: gateway.port() as i32,
+        api_token_command: Some(format!("echo {}", API_TOKEN)),
+        ..Configuration::new()
+    };
+
+    let controller = AsyncTahomaApiController::new(&config).unwrap();
+    assert!(controller.get_setup().await.is_ok());
+
+    let config = Configuration {
+        api_token_command: None,
+        ..config
+    };
+    assert!(matches!(
+        AsyncTahomaApiController::new(&config),
+        Err(MatahoError::Config(_))
+    ));
+}
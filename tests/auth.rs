@@ -0,0 +1,253 @@
+mod common;
+
+use common::{stderr, stdout, FakeGateway, Fault, Mataho, API_TOKEN};
+
+fn config_with(gateway: &FakeGateway, token_settings: &str) -> String {
+    format!(
+        "hostname = \"http://127.0.0.1\"\nport = {}\ntimeout = 2\n{}\n",
+        gateway.port(),
+        token_settings
+    )
+}
+
+#[test]
+fn token_is_read_from_file() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    let token_file = mataho.config_dir().join("token");
+    std::fs::write(&token_file, format!("{}\n", API_TOKEN)).unwrap();
+    mataho.write_config(&config_with(
+        &gateway,
+        &format!("api_token_file = \"{}\"", token_file.to_string_lossy()),
+    ));
+
+    let output = mataho.run(&["ls"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(gateway.count_requests("GET", "setup"), 1);
+}
+
+#[test]
+fn token_is_read_from_command() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    mataho.write_config(&config_with(
+        &gateway,
+        &format!("api_token_command = \"echo {}\"", API_TOKEN),
+    ));
+
+    let output = mataho.run(&["ls"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(gateway.count_requests("GET", "setup"), 1);
+}
+
+#[test]
+fn failing_token_command_is_reported() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    mataho.write_config(&config_with(
+        &gateway,
+        "api_token_command = \"echo 'vault is locked' >&2; exit 3\"",
+    ));
+
+    let output = mataho.run(&["ls"]);
+
+    assert_eq!(output.status.code(), Some(11));
+    let stderr = stderr(&output);
+    assert!(stderr.contains("Failed to get the API token from"));
+    assert!(stderr.contains("vault is locked"));
+    assert_eq!(gateway.count_requests("GET", "setup"), 0);
+}
+
+#[test]
+fn token_is_only_read_when_the_gateway_is_contacted() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    assert!(mataho.run(&["ls"]).status.success());
+    mataho.write_config(&config_with(
+        &gateway,
+        "api_token_command = \"echo 'vault is locked' >&2; exit 3\"",
+    ));
+
+    for args in [
+        &["group", "ls"][..],
+        &["--offline", "group", "create", "foo"],
+        &["--offline", "ls"],
+    ] {
+        let output = mataho.run(args);
+        assert!(output.status.success(), "{:?}: {}", args, stderr(&output));
+    }
+
+    let output = mataho.run(&["ls", "--refresh"]);
+    assert_eq!(output.status.code(), Some(11));
+    assert!(stderr(&output).contains("vault is locked"));
+    assert_eq!(gateway.count_requests("GET", "setup"), 1);
+}
+
+#[test]
+fn several_token_sources_are_rejected() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    mataho.write_config(&config_with(
+        &gateway,
+        &format!(
+            "api_token = \"{}\"\napi_token_command = \"echo {}\"",
+            API_TOKEN, API_TOKEN
+        ),
+    ));
+
+    let output = mataho.run(&["ls"]);
+
+    assert_eq!(output.status.code(), Some(11));
+    assert!(stderr(&output).contains("Several API token sources for profile `default`"));
+}
+
+#[test]
+fn environment_token_source_replaces_config_token() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    mataho.write_config(&config_with(&gateway, "api_token = \"wrong\""));
+    let command = format!("echo {}", API_TOKEN);
+
+    let output = mataho.run_with_env(&["ls"], &[("MATAHO_API_TOKEN_COMMAND", &command)]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+}
+
+#[test]
+fn profile_token_source_replaces_top_level_token() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    mataho.write_config(&config_with(
+        &gateway,
+        &format!(
+            "api_token = \"wrong\"\n\n[profiles.office]\napi_token_command = \"echo {}\"",
+            API_TOKEN
+        ),
+    ));
+
+    let output = mataho.run(&["--profile", "office", "ls"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let output = mataho.run(&["--profile", "office", "config", "show", "--origin"]);
+    let stdout = stdout(&output);
+    assert!(stdout.contains("api_token = \"\"  # default"));
+    assert!(stdout.contains("# config file, [profiles.office]"));
+}
+
+#[test]
+fn config_set_token_source_replaces_the_others() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&[
+        "config",
+        "set",
+        "api_token_command",
+        &format!("echo {}", API_TOKEN),
+    ]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let config = mataho.read_config();
+    assert!(config.contains("api_token_command = "));
+    assert!(!config.contains("api_token = "));
+    assert!(mataho.run(&["ls"]).status.success());
+}
+
+#[test]
+fn config_init_saves_token_file_as_is() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::without_config();
+    std::fs::create_dir_all(mataho.config_dir()).unwrap();
+    let token_file = mataho.config_dir().join("token");
+    std::fs::write(&token_file, API_TOKEN).unwrap();
+    let port = gateway.port().to_string();
+
+    let output = mataho.run(&[
+        "config",
+        "init",
+        "--host",
+        "http://127.0.0.1",
+        "--port",
+        &port,
+        "--token-file",
+        &token_file.to_string_lossy(),
+    ]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let config = mataho.read_config();
+    assert!(config.contains("api_token_file = "));
+    assert!(!config.contains(API_TOKEN));
+}
+
+#[test]
+fn auth_check_reports_token_source() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    mataho.write_config(&config_with(
+        &gateway,
+        &format!("api_token_command = \"echo {}\"", API_TOKEN),
+    ));
+
+    let output = mataho.run(&["auth", "check"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    assert!(stdout.contains(&format!(
+        "API token of profile `default`: command `echo {}` (config file)",
+        API_TOKEN
+    )));
+    assert!(stdout.contains("accepts the token (5 devices)"));
+    assert!(!stdout.contains("plain text"));
+}
+
+#[test]
+fn auth_check_suggests_keyring_for_plaintext_token() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&["auth", "check"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("plain text in the config file, `mataho auth login`"));
+}
+
+#[test]
+fn auth_check_explains_refused_token() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run_with_env(&["auth", "check"], &[("MATAHO_API_TOKEN", "expired")]);
+
+    assert_eq!(output.status.code(), Some(7));
+    assert!(stdout(&output).contains("(env MATAHO_API_TOKEN)"));
+    let stderr = stderr(&output);
+    assert!(stderr.contains("has expired or was revoked"));
+    assert!(stderr.contains("the gateway refused the API token (401)"));
+}
+
+#[test]
+fn auth_check_explains_denied_access() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    gateway.inject_fault("setup", Fault::Status(403));
+
+    let output = mataho.run(&["auth", "check"]);
+
+    assert_eq!(output.status.code(), Some(7));
+    assert!(stderr(&output).contains("denies access with it"));
+}
+
+#[test]
+fn auth_login_saves_nothing_when_token_is_refused() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    let before = mataho.read_config();
+
+    let output = mataho.run_with_input(&["auth", "login"], "wrong\n");
+
+    assert_eq!(output.status.code(), Some(7));
+    assert!(stderr(&output).contains("Nothing was saved"));
+    assert_eq!(mataho.read_config(), before);
+}
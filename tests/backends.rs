@@ -0,0 +1,191 @@
+mod common;
+
+use std::{cell::RefCell, fs, path::Path};
+
+use common::{stderr, stdout, FakeGateway, Mataho, API_TOKEN};
+use mataho::{
+    controller::ExecAction, events::Event, execution::Execution, Device, DeviceState,
+    MatahoService, Result, SimulatedTahomaApi, TahomaApi, TahomaSetupResponse,
+};
+
+/// Test double that only remembers which commands were sent to which devices.
+struct SpyApi {
+    setup: TahomaSetupResponse,
+    calls: RefCell<Vec<(String, Vec<String>)>>,
+}
+
+impl TahomaApi for SpyApi {
+    fn get_setup(&self) -> Result<TahomaSetupResponse> {
+        Ok(self.setup.clone())
+    }
+
+    fn execute(&self, device: &Device, command: &str, params: &[String]) -> Result<String> {
+        self.execute_multiple(vec![device], command, params)
+    }
+
+    fn execute_multiple(
+        &self,
+        devices: Vec<&Device>,
+        command: &str,
+        _params: &[String],
+    ) -> Result<String> {
+        let ids = devices
+            .iter()
+            .map(|device| device.id().to_string())
+            .collect();
+        self.calls.borrow_mut().push((command.to_string(), ids));
+        Ok("spy".to_string())
+    }
+
+    fn execute_batch(&self, _label: &str, batch: &[ExecAction]) -> Result<String> {
+        for action in batch.iter() {
+            for command in action.commands.iter() {
+                self.calls
+                    .borrow_mut()
+                    .push((command.name.clone(), vec![action.device.id().to_string()]));
+            }
+        }
+        Ok("spy".to_string())
+    }
+
+    fn get_device_states(&self, _device: &Device) -> Result<Vec<DeviceState>> {
+        Ok(Vec::new())
+    }
+
+    fn get_current_executions(&self) -> Result<Vec<Execution>> {
+        Ok(Vec::new())
+    }
+
+    fn cancel_execution(&self, _exec_id: &str) -> Result<()> {
+        Ok(())
+    }
+
+    fn cancel_all_executions(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn register_event_listener(&self) -> Result<String> {
+        Ok("spy".to_string())
+    }
+
+    fn fetch_events(&self, _listener_id: &str) -> Result<Vec<Event>> {
+        Ok(Vec::new())
+    }
+
+    fn unregister_event_listener(&self, _listener_id: &str) -> Result<()> {
+        Ok(())
+    }
+}
+
+fn fixture_setup() -> TahomaSetupResponse {
+    serde_json::from_value(common::fixture("setup.json")).unwrap()
+}
+
+fn fixture_path(name: &str) -> String {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(name)
+        .to_string_lossy()
+        .to_string()
+}
+
+#[test]
+fn service_runs_against_a_test_double() {
+    let spy = SpyApi {
+        setup: fixture_setup(),
+        calls: RefCell::new(Vec::new()),
+    };
+    let service = MatahoService::from_api(&spy).unwrap();
+
+    let device = service.find_device_by_id("00000002").unwrap();
+    service
+        .execute_on_device(&spy, device, "open", &Vec::new())
+        .unwrap();
+
+    assert_eq!(
+        spy.calls.borrow().as_slice(),
+        [("open".to_string(), vec!["00000002".to_string()])]
+    );
+}
+
+#[test]
+fn simulator_updates_states_and_emits_events() {
+    let simulator = SimulatedTahomaApi::new(fixture_setup());
+    let service = MatahoService::from_api(&simulator).unwrap();
+    let device = service.find_device_by_id("00000003").unwrap();
+
+    let listener_id = simulator.register_event_listener().unwrap();
+    let exec_id = service
+        .execute_on_device(&simulator, device, "setClosure", &["40".to_string()])
+        .unwrap();
+
+    let states = simulator.get_device_states(device).unwrap();
+    assert_eq!(states[0].to_string(), "core:ClosureState: 40");
+    assert_eq!(states[1].to_string(), "core:OpenClosedState: open");
+
+    let events = simulator.fetch_events(&listener_id).unwrap();
+    assert!(matches!(
+        events.last(),
+        Some(Event::ExecutionStateChanged { exec_id: id, .. }) if *id == exec_id
+    ));
+}
+
+#[test]
+fn cli_uses_simulator_backend_from_configuration() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    mataho.write_config(&format!(
+        "hostname = \"http://127.0.0.1\"\nport = 1\napi_token = \"unused\"\nbackend = \"simulator\"\nsimulator_setup = \"{}\"\n",
+        fixture_path("setup.json")
+    ));
+
+    let output = mataho.run(&["exec", "coko", "close", "--wait", "--timeout", "5"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Execution `simulated-1` completed"));
+    assert!(gateway.requests().is_empty());
+}
+
+#[test]
+fn simulator_profile_needs_no_gateway_settings() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    mataho.write_config(&format!(
+        "backend = \"simulator\"\nsimulator_setup = \"{}\"\n",
+        fixture_path("setup.json")
+    ));
+
+    let output = mataho.run(&["config", "validate"]);
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert!(stdout(&output).contains("- profile `default`: ok, simulated gateway (5 devices)"));
+
+    // Already set up: `config init` does not replace it by mistake
+    let output = mataho.run(&["config", "init", "--no-check"]);
+    assert_eq!(output.status.code(), Some(11));
+    assert!(stderr(&output).contains("--force"));
+}
+
+#[test]
+fn cli_records_executions_to_file() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    let record_file = mataho.config_dir().join("record.jsonl");
+    mataho.write_config(&format!(
+        "hostname = \"http://127.0.0.1\"\nport = {}\napi_token = \"{}\"\nrecord_file = \"{}\"\n",
+        gateway.port(),
+        API_TOKEN,
+        record_file.to_string_lossy()
+    ));
+
+    let output = mataho.run(&["exec", "garage", "open"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(gateway.exec_payloads().len(), 1);
+
+    let record = fs::read_to_string(record_file).unwrap();
+    let line: serde_json::Value = serde_json::from_str(record.lines().next().unwrap()).unwrap();
+    assert_eq!(line["call"], "execute");
+    assert_eq!(
+        line["payload"]["actions"][0]["deviceURL"],
+        "io://1234-5678-9012/00000002"
+    );
+}
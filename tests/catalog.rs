@@ -0,0 +1,75 @@
+mod common;
+
+use common::{stderr, stdout, FakeGateway, Mataho};
+use serde_json::Value;
+
+#[test]
+fn help_command_documents_parameters() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&["help-command", "setMemorized1Position"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let stdout = stdout(&output);
+    assert!(stdout.contains("setMemorized1Position <position: integer 0-100 %>"));
+    assert!(stdout.contains("favourite (`my`) position"));
+    assert!(stdout.contains("- position: integer 0-100 %. 0 = open, 100 = closed"));
+    assert_eq!(gateway.count_requests("GET", "setup"), 0);
+}
+
+#[test]
+fn help_command_lists_devices_of_cached_setup() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    assert!(mataho.run(&["ls"]).status.success());
+
+    let output = mataho.run(&["help-command", "setClosure", "-o", "json"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let help: Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(help["kind"], "command");
+    assert_eq!(help["usage"], "setClosure <closure: integer 0-100 %>");
+    assert_eq!(help["params"][0]["type"], "integer 0-100 %");
+    let devices = help["devices"].as_array().unwrap();
+    assert!(devices.contains(&Value::from("Coko's room")));
+    assert!(!devices.contains(&Value::from("Front gate")));
+    assert_eq!(gateway.count_requests("GET", "setup"), 1);
+}
+
+#[test]
+fn help_command_describes_states() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&["help-command", "core:ClosureState"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("core:ClosureState (state): integer 0-100 %"));
+}
+
+#[test]
+fn help_command_suggests_similar_names() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&["help-command", "memorized"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = stderr(&output);
+    assert!(
+        stderr.contains("Unknown command or state `memorized`"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("`setMemorized1Position`"), "{}", stderr);
+}
+
+#[test]
+fn info_describes_commands() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&["info", "coko"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output)
+        .contains("- setClosure <closure: integer 0-100 %>: Move to the given closure"));
+}
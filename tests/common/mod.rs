@@ -0,0 +1,489 @@
+//! Stand-in for a Tahoma gateway, serving the local API from JSON fixtures.
+//!
+//! The gateway records every request it receives and can be told to misbehave
+//! (HTTP errors, slow answers, malformed JSON) on a given route.
+
+#![allow(dead_code)]
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use serde_json::{json, Value};
+use tempfile::TempDir;
+use tiny_http::{Header, Method, Request, Response, Server, SslConfig};
+
+pub const API_TOKEN: &str = "test-token";
+const API_PREFIX: &str = "enduser-mobile-web/1/enduserAPI/";
+
+#[derive(Debug, Clone)]
+pub enum Fault {
+    /// Answer with the given HTTP status and an empty body
+    Status(u16),
+    /// Wait before answering normally
+    Delay(Duration),
+    /// Answer 200 with a body that is not valid JSON
+    MalformedJson,
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    /// Path relative to the API prefix (e.g. `exec/apply`)
+    pub path: String,
+    pub body: Option<Value>,
+}
+
+#[derive(Default)]
+struct GatewayState {
+    setup: Value,
+    states: Value,
+    executions: Value,
+    events: Vec<Value>,
+    requests: Vec<RecordedRequest>,
+    faults: HashMap<String, Fault>,
+    /// Faults for the next request on their route only
+    next_faults: HashMap<String, Fault>,
+    listeners: usize,
+    execution_failure: Option<String>,
+    exec_count: usize,
+}
+
+pub struct FakeGateway {
+    port: u16,
+    server: Arc<Server>,
+    state: Arc<Mutex<GatewayState>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl FakeGateway {
+    pub fn start() -> FakeGateway {
+        let server = Server::http("127.0.0.1:0").expect("failed to start fake gateway");
+        FakeGateway::serve(server)
+    }
+
+    /// Serve over HTTPS with the certificate of `tests/fixtures/tls/gateway.pem`,
+    /// issued for `localhost` and `127.0.0.1` by `tests/fixtures/tls/ca.pem`.
+    pub fn start_tls() -> FakeGateway {
+        let ssl = SslConfig {
+            certificate: fs::read(tls_fixture("gateway.pem")).unwrap(),
+            private_key: fs::read(tls_fixture("gateway.key")).unwrap(),
+        };
+
+        let server = Server::https("127.0.0.1:0", ssl).expect("failed to start fake gateway");
+        FakeGateway::serve(server)
+    }
+
+    fn serve(server: Server) -> FakeGateway {
+        let state = GatewayState {
+            setup: fixture("setup.json"),
+            states: fixture("states.json"),
+            executions: fixture("executions.json"),
+            ..Default::default()
+        };
+        let state = Arc::new(Mutex::new(state));
+
+        let server = Arc::new(server);
+        let port = server.server_addr().to_ip().unwrap().port();
+
+        let handle = {
+            let server = Arc::clone(&server);
+            let state = Arc::clone(&state);
+
+            thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    handle_request(&state, request);
+                }
+            })
+        };
+
+        FakeGateway {
+            port,
+            server,
+            state,
+            handle: Some(handle),
+        }
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Misbehave on every request whose path starts with `route` (e.g. `setup`, `exec/apply`).
+    pub fn inject_fault(&self, route: &str, fault: Fault) {
+        let mut state = self.state.lock().unwrap();
+        state.faults.insert(route.to_string(), fault);
+    }
+
+    /// Misbehave on the next request whose path starts with `route` only.
+    pub fn inject_fault_once(&self, route: &str, fault: Fault) {
+        let mut state = self.state.lock().unwrap();
+        state.next_faults.insert(route.to_string(), fault);
+    }
+
+    /// Make every following execution end in the `FAILED` state with the given failure type.
+    pub fn fail_executions(&self, failure_type: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.execution_failure = Some(failure_type.to_string());
+    }
+
+    pub fn push_event(&self, event: Value) {
+        let mut state = self.state.lock().unwrap();
+        state.events.push(event);
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// Payloads received on `/exec/apply`, in order.
+    pub fn exec_payloads(&self) -> Vec<Value> {
+        self.requests()
+            .into_iter()
+            .filter(|request| request.path == "exec/apply")
+            .filter_map(|request| request.body)
+            .collect()
+    }
+
+    pub fn count_requests(&self, method: &str, path: &str) -> usize {
+        self.requests()
+            .iter()
+            .filter(|request| request.method == method && request.path == path)
+            .count()
+    }
+}
+
+impl Drop for FakeGateway {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+}
+
+/// Path of a file of `tests/fixtures/tls`.
+pub fn tls_fixture(name: &str) -> PathBuf {
+    fixtures_dir().join("tls").join(name)
+}
+
+pub fn fixture(name: &str) -> Value {
+    let content = fs::read_to_string(fixtures_dir().join(name)).expect("missing fixture");
+    serde_json::from_str(&content).expect("invalid fixture")
+}
+
+fn handle_request(state: &Mutex<GatewayState>, mut request: Request) {
+    let method = request.method().to_string();
+    // The controller builds URLs as `host:port//enduser-mobile-web/...`
+    let path = request.url().trim_start_matches('/').to_string();
+    let path = path.strip_prefix(API_PREFIX).unwrap_or(&path).to_string();
+
+    let mut body = String::new();
+    let _ = request.as_reader().read_to_string(&mut body);
+    let body = serde_json::from_str(&body).ok();
+
+    let authorized = request.headers().iter().any(|header| {
+        header.field.equiv("Authorization") && header.value == format!("Bearer {}", API_TOKEN)
+    });
+
+    let fault = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(RecordedRequest {
+            method: method.clone(),
+            path: path.clone(),
+            body: body.clone(),
+        });
+
+        let next_fault = state
+            .next_faults
+            .keys()
+            .find(|route| path.starts_with(route.as_str()))
+            .cloned();
+
+        match next_fault {
+            Some(route) => state.next_faults.remove(&route),
+            None => state
+                .faults
+                .iter()
+                .find(|(route, _)| path.starts_with(route.as_str()))
+                .map(|(_, fault)| fault.clone()),
+        }
+    };
+
+    let response = match fault {
+        Some(Fault::Status(status)) => Response::from_string("").with_status_code(status),
+        Some(Fault::MalformedJson) => json_response("{\"devices\": [{\"label\": ", 200),
+        Some(Fault::Delay(delay)) => {
+            thread::sleep(delay);
+            route(state, request.method(), &path, body)
+        }
+        None if !authorized => json_response(
+            &json!({ "error": "Not authenticated", "errorCode": "RESOURCE_ACCESS_DENIED" })
+                .to_string(),
+            401,
+        ),
+        None => route(state, request.method(), &path, body),
+    };
+
+    let _ = request.respond(response);
+}
+
+fn route(
+    state: &Mutex<GatewayState>,
+    method: &Method,
+    path: &str,
+    body: Option<Value>,
+) -> Response<std::io::Cursor<Vec<u8>>> {
+    let mut state = state.lock().unwrap();
+    let segments: Vec<&str> = path.split('/').collect();
+
+    match (method, segments.as_slice()) {
+        (Method::Get, ["setup"]) => json_response(&state.setup.to_string(), 200),
+        (Method::Get, ["setup", "devices", device_url, "states"]) => {
+            let device_url = decode(device_url);
+            match state.states.get(&device_url) {
+                Some(states) => json_response(&states.to_string(), 200),
+                None => json_response(&json!([]).to_string(), 200),
+            }
+        }
+        (Method::Post, ["exec", "apply"]) => {
+            state.exec_count += 1;
+            let exec_id = format!("exec-{}", state.exec_count);
+            let actions = body
+                .as_ref()
+                .and_then(|body| body.get("actions"))
+                .cloned()
+                .unwrap_or(json!([]));
+
+            let final_event = match &state.execution_failure {
+                Some(failure_type) => {
+                    let failed_commands: Vec<Value> = actions
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .map(|action| {
+                            json!({
+                                "deviceURL": action["deviceURL"],
+                                "rank": 0,
+                                "failureType": failure_type,
+                            })
+                        })
+                        .collect();
+
+                    json!({
+                        "name": "ExecutionStateChangedEvent",
+                        "execId": exec_id,
+                        "oldState": "IN_PROGRESS",
+                        "newState": "FAILED",
+                        "failureType": failure_type,
+                        "failedCommands": failed_commands,
+                    })
+                }
+                None => json!({
+                    "name": "ExecutionStateChangedEvent",
+                    "execId": exec_id,
+                    "oldState": "IN_PROGRESS",
+                    "newState": "COMPLETED",
+                }),
+            };
+
+            state.events.push(json!({
+                "name": "ExecutionRegisteredEvent",
+                "execId": exec_id,
+                "label": body.as_ref().and_then(|body| body.get("label")).cloned(),
+                "actions": actions,
+            }));
+            state.events.push(json!({
+                "name": "ExecutionStateChangedEvent",
+                "execId": exec_id,
+                "oldState": "INITIALIZED",
+                "newState": "IN_PROGRESS",
+            }));
+            state.events.push(final_event);
+
+            json_response(&json!({ "execId": exec_id }).to_string(), 200)
+        }
+        (Method::Get, ["exec", "current"]) => json_response(&state.executions.to_string(), 200),
+        (Method::Get, ["exec", "current", exec_id]) => {
+            let execution = state
+                .executions
+                .as_array()
+                .into_iter()
+                .flatten()
+                .find(|execution| execution["id"] == *exec_id);
+
+            match execution {
+                Some(execution) => json_response(&execution.to_string(), 200),
+                None => json_response(&json!({ "error": "Unknown object" }).to_string(), 404),
+            }
+        }
+        (Method::Delete, ["exec", "current", "setup"]) => {
+            state.executions = json!([]);
+            json_response("", 200)
+        }
+        (Method::Delete, ["exec", "current", "setup", exec_id]) => {
+            if let Some(executions) = state.executions.as_array_mut() {
+                executions.retain(|execution| execution["id"] != *exec_id);
+            }
+            json_response("", 200)
+        }
+        (Method::Post, ["events", "register"]) => {
+            // Events queued for the previous listener are not sent to the new one
+            state.listeners += 1;
+            if state.listeners > 1 {
+                state.events.clear();
+            }
+            let id = format!("listener-{}", state.listeners);
+            json_response(&json!({ "id": id }).to_string(), 200)
+        }
+        (Method::Post, ["events", _, "fetch"]) => {
+            let events: Vec<Value> = state.events.drain(..).collect();
+            json_response(&Value::Array(events).to_string(), 200)
+        }
+        (Method::Post, ["events", _, "unregister"]) => json_response("", 200),
+        _ => json_response(&json!({ "error": "Unknown resource" }).to_string(), 404),
+    }
+}
+
+fn json_response(body: &str, status: u16) -> Response<std::io::Cursor<Vec<u8>>> {
+    let header = Header::from_bytes("Content-Type", "application/json").unwrap();
+
+    Response::from_string(body)
+        .with_status_code(status)
+        .with_header(header)
+}
+
+fn decode(value: &str) -> String {
+    let mut res = Vec::new();
+    let bytes = value.as_bytes();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let Ok(byte) = u8::from_str_radix(&value[i + 1..i + 3], 16) {
+                res.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+
+        res.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8(res).unwrap()
+}
+
+/// Isolated mataho installation (config dir) pointing at a [`FakeGateway`].
+pub struct Mataho {
+    dir: TempDir,
+}
+
+impl Mataho {
+    pub fn new(gateway: &FakeGateway) -> Mataho {
+        let dir = TempDir::new().unwrap();
+        let mataho = Mataho { dir };
+
+        fs::create_dir_all(mataho.config_dir()).unwrap();
+        mataho.write_config(&format!(
+            "hostname = \"http://127.0.0.1\"\nport = {}\napi_token = \"{}\"\ntimeout = 2\n",
+            gateway.port(),
+            API_TOKEN
+        ));
+
+        mataho
+    }
+
+    /// Installation without config file, as before `mataho config init`.
+    pub fn without_config() -> Mataho {
+        let dir = TempDir::new().unwrap();
+        Mataho { dir }
+    }
+
+    pub fn config_dir(&self) -> PathBuf {
+        self.dir.path().join("mataho")
+    }
+
+    pub fn write_config(&self, content: &str) {
+        fs::write(self.config_dir().join("config.toml"), content).unwrap();
+    }
+
+    pub fn read_config(&self) -> String {
+        fs::read_to_string(self.config_dir().join("config.toml")).unwrap()
+    }
+
+    pub fn run(&self, args: &[&str]) -> Output {
+        self.run_with_env(args, &[])
+    }
+
+    pub fn run_with_env(&self, args: &[&str], vars: &[(&str, &str)]) -> Output {
+        self.command(args)
+            .envs(vars.iter().copied())
+            .output()
+            .expect("failed to run mataho")
+    }
+
+    /// Run with `input` as the answers typed on the terminal.
+    pub fn run_with_input(&self, args: &[&str], input: &str) -> Output {
+        self.run_with_env_and_input(args, &[], input)
+    }
+
+    pub fn run_with_env_and_input(
+        &self,
+        args: &[&str],
+        vars: &[(&str, &str)],
+        input: &str,
+    ) -> Output {
+        let mut child = self
+            .command(args)
+            .envs(vars.iter().copied())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("failed to run mataho");
+
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(input.as_bytes())
+            .unwrap();
+
+        child.wait_with_output().expect("failed to run mataho")
+    }
+
+    fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_mataho"));
+        command
+            .args(args)
+            .env("XDG_CONFIG_HOME", self.dir.path())
+            .env_remove("MATAHO_CONFIG")
+            .env_remove("MATAHO_PROFILE")
+            .env_remove("MATAHO_MDNS_PORT")
+            .env("RUST_BACKTRACE", "0");
+
+        command
+    }
+}
+
+pub fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).to_string()
+}
@@ -0,0 +1,400 @@
+mod common;
+
+use common::{stderr, stdout, FakeGateway, Mataho, API_TOKEN};
+
+/// SHA-256 fingerprint of `tests/fixtures/tls/gateway.pem`
+const GATEWAY_FINGERPRINT: &str = "95:09:6E:C9:9B:32:45:AA:20:DB:92:01:71:D9:E7:28:46:EE:6E:7B:4A:33:0D:F5:D9:3E:0D:0C:D0:3B:C3:EA";
+
+fn init_args(gateway: &FakeGateway, scheme: &str) -> Vec<String> {
+    vec![
+        "config".to_string(),
+        "init".to_string(),
+        "--host".to_string(),
+        format!("{}://127.0.0.1", scheme),
+        "--port".to_string(),
+        gateway.port().to_string(),
+    ]
+}
+
+fn as_args(args: &[String]) -> Vec<&str> {
+    args.iter().map(String::as_str).collect()
+}
+
+#[test]
+fn missing_config_file_points_to_init() {
+    let mataho = Mataho::without_config();
+
+    let output = mataho.run(&["ls"]);
+
+    assert_eq!(output.status.code(), Some(11));
+    assert!(stderr(&output).contains("run `mataho config init`"));
+    assert!(!mataho.config_dir().join("config.toml").exists());
+}
+
+#[test]
+fn config_path_prints_config_file() {
+    let mataho = Mataho::without_config();
+
+    let output = mataho.run(&["config", "path"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let expected = mataho.config_dir().join("config.toml");
+    assert_eq!(stdout(&output).trim(), expected.to_string_lossy());
+}
+
+#[test]
+fn init_tests_connection_then_saves_toml() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::without_config();
+
+    let output = mataho.run_with_input(
+        &as_args(&init_args(&gateway, "http")),
+        &format!("{}\n", API_TOKEN),
+    );
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("(5 devices)"));
+    assert_eq!(gateway.count_requests("GET", "setup"), 1);
+
+    let config = mataho.read_config();
+    assert!(config.contains("hostname = \"http://127.0.0.1\""));
+    assert!(config.contains(&format!("port = {}", gateway.port())));
+    assert!(config.contains(&format!("api_token = \"{}\"", API_TOKEN)));
+
+    let output = mataho.run(&["ls"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+}
+
+#[test]
+fn init_asks_for_missing_settings() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::without_config();
+
+    let output = mataho.run_with_env_and_input(
+        &["config", "init", "--no-check"],
+        &[("MATAHO_MDNS_PORT", "1")],
+        &format!("gateway-1234-5678-9012.local\n\n{}\n", API_TOKEN),
+    );
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let config = mataho.read_config();
+    assert!(config.contains("hostname = \"https://gateway-1234-5678-9012.local\""));
+    assert!(config.contains("port = 8443"));
+    assert_eq!(gateway.count_requests("GET", "setup"), 0);
+}
+
+#[test]
+fn init_saves_nothing_when_token_is_refused() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::without_config();
+
+    let output = mataho.run_with_input(&as_args(&init_args(&gateway, "http")), "wrong-token\n");
+
+    assert_eq!(output.status.code(), Some(7));
+    assert!(stderr(&output).contains("refused the API token"));
+    assert!(stderr(&output).contains("Nothing was saved"));
+    assert!(!mataho.config_dir().join("config.toml").exists());
+}
+
+#[test]
+fn init_keeps_configured_profile_without_force() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    let before = mataho.read_config();
+
+    let output = mataho.run_with_input(
+        &as_args(&init_args(&gateway, "http")),
+        &format!("{}\n", API_TOKEN),
+    );
+
+    assert_eq!(output.status.code(), Some(11));
+    assert!(stderr(&output).contains("--force"));
+    assert_eq!(mataho.read_config(), before);
+
+    let mut args = init_args(&gateway, "http");
+    args.push("--force".to_string());
+    let output = mataho.run_with_input(&as_args(&args), &format!("{}\n", API_TOKEN));
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    // Settings that were not asked for are kept
+    assert!(mataho.read_config().contains("timeout = 2"));
+}
+
+#[test]
+fn init_offers_to_pin_untrusted_certificate() {
+    let gateway = FakeGateway::start_tls();
+    let mataho = Mataho::without_config();
+
+    let output = mataho.run_with_input(
+        &as_args(&init_args(&gateway, "https")),
+        &format!("{}\ny\n", API_TOKEN),
+    );
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains(GATEWAY_FINGERPRINT));
+
+    let config = mataho.read_config();
+    assert!(config.contains(&format!("fingerprint = \"{}\"", GATEWAY_FINGERPRINT)));
+
+    let output = mataho.run(&["ls"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+}
+
+#[test]
+fn init_adds_profile() {
+    let house = FakeGateway::start();
+    let office = FakeGateway::start();
+    let mataho = Mataho::new(&house);
+
+    let mut args = vec!["--profile", "office"];
+    let init = init_args(&office, "http");
+    args.extend(as_args(&init));
+    let output = mataho.run_with_input(&args, &format!("{}\n", API_TOKEN));
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let config = mataho.read_config();
+    assert!(config.contains("[profiles.office]"));
+    assert!(config.contains(&format!("port = {}", office.port())));
+
+    let output = mataho.run(&["--profile", "office", "ls"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(office.count_requests("GET", "setup"), 2);
+}
+
+#[test]
+fn show_redacts_token() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&["config", "show"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    assert!(stdout.contains("# profile `default`"));
+    assert!(stdout.contains("api_token = \"<redacted>\""));
+    assert!(stdout.contains("cache_ttl = 3600"));
+    assert!(!stdout.contains(API_TOKEN));
+}
+
+#[test]
+fn set_edits_config_file_in_place() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    mataho.write_config(&format!(
+        "# my gateway\nhostname = \"http://127.0.0.1\"\nport = {}\napi_token = \"{}\"\n",
+        gateway.port(),
+        API_TOKEN
+    ));
+
+    let output = mataho.run(&["config", "set", "timeout", "5"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let output = mataho.run(&["config", "set", "tls.insecure", "true"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let config = mataho.read_config();
+    assert!(config.starts_with("# my gateway\n"));
+    assert!(config.contains("timeout = 5"));
+    assert!(config.contains("[tls]\ninsecure = true"));
+}
+
+#[test]
+fn set_rejects_invalid_settings() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    let before = mataho.read_config();
+
+    for args in [
+        vec!["config", "set", "api-token", "x"],
+        vec!["config", "set", "port", "99999"],
+        vec!["config", "set", "tls.insecure", "maybe"],
+        vec!["config", "set", "backend", "carrier-pigeon"],
+        vec![
+            "--profile",
+            "office",
+            "config",
+            "set",
+            "default_profile",
+            "x",
+        ],
+    ] {
+        let output = mataho.run(&args);
+
+        assert_eq!(output.status.code(), Some(11), "{:?}", args);
+    }
+
+    assert_eq!(mataho.read_config(), before);
+}
+
+#[test]
+fn validate_checks_gateway_of_every_profile() {
+    let house = FakeGateway::start();
+    let office = FakeGateway::start();
+    let mataho = Mataho::new(&house);
+    let config = mataho.read_config();
+    mataho.write_config(&format!(
+        "{}\n[profiles.office]\nport = {}\n",
+        config,
+        office.port()
+    ));
+
+    let output = mataho.run(&["config", "validate"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    assert!(stdout.contains("- profile `default`: ok"));
+    assert!(stdout.contains("- profile `office`: ok"));
+    assert!(stdout.contains("The config file is valid"));
+    assert_eq!(office.count_requests("GET", "setup"), 1);
+}
+
+#[test]
+fn validate_reports_refused_token() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    mataho.write_config(&format!(
+        "hostname = \"http://127.0.0.1\"\nport = {}\napi_token = \"expired\"\n",
+        gateway.port()
+    ));
+
+    let output = mataho.run(&["config", "validate"]);
+
+    assert_eq!(output.status.code(), Some(7));
+    let stdout = stdout(&output);
+    assert!(stdout.contains("- profile `default`: Failed to get setup"));
+    assert!(stdout.contains("the gateway refused the API token (401)"));
+}
+
+#[test]
+fn validate_reports_schema_problems() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    mataho.write_config(&format!(
+        "hostname = \"127.0.0.1\"\nport = {}\napi_token = \"\"\napi-token = \"{}\"\n",
+        gateway.port(),
+        API_TOKEN
+    ));
+
+    let output = mataho.run(&["--offline", "config", "validate"]);
+
+    assert_eq!(output.status.code(), Some(11));
+    let stdout = stdout(&output);
+    assert!(stdout.contains("unknown setting `api-token`"));
+    assert!(stdout.contains("`hostname` must start with `https://`"));
+    assert!(stdout.contains("no API token"));
+    assert_eq!(gateway.count_requests("GET", "setup"), 0);
+}
+
+#[test]
+fn environment_replaces_config_file() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::without_config();
+    let port = gateway.port().to_string();
+
+    let output = mataho.run_with_env(
+        &["ls"],
+        &[
+            ("MATAHO_HOSTNAME", "http://127.0.0.1"),
+            ("MATAHO_PORT", &port),
+            ("MATAHO_API_TOKEN", API_TOKEN),
+        ],
+    );
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Coko's room"));
+    assert!(!mataho.config_dir().join("config.toml").exists());
+}
+
+#[test]
+fn flags_win_over_environment_and_config_file() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    mataho.write_config("hostname = \"http://127.0.0.1\"\nport = 1\napi_token = \"wrong\"\n");
+    let token_file = mataho.config_dir().join("token");
+    std::fs::write(&token_file, format!("{}\n", API_TOKEN)).unwrap();
+    let port = gateway.port().to_string();
+
+    let output = mataho.run_with_env(
+        &[
+            "--port",
+            &port,
+            "--token-file",
+            &token_file.to_string_lossy(),
+            "ls",
+        ],
+        &[("MATAHO_PORT", "2"), ("MATAHO_API_TOKEN", "also-wrong")],
+    );
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(gateway.count_requests("GET", "setup"), 1);
+}
+
+#[test]
+fn invalid_environment_value_is_reported() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run_with_env(&["ls"], &[("MATAHO_PORT", "http")]);
+
+    assert_eq!(output.status.code(), Some(11));
+    assert!(stderr(&output).contains("MATAHO_PORT: Invalid value `http` for `port`"));
+}
+
+#[test]
+fn show_origin_explains_each_value() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    mataho.write_config(&format!(
+        "hostname = \"http://127.0.0.1\"\nport = {}\napi_token = \"{}\"\ntimeout = 2\n\n[profiles.office]\ntimeout = 7\n",
+        gateway.port(),
+        API_TOKEN
+    ));
+
+    let output = mataho.run_with_env(
+        &[
+            "--profile",
+            "office",
+            "--host",
+            "http://localhost",
+            "config",
+            "show",
+            "--origin",
+        ],
+        &[("MATAHO_CACHE_TTL", "60")],
+    );
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    assert!(stdout.contains("hostname = \"http://localhost\"  # flag --host"));
+    assert!(stdout.contains(&format!("port = {}  # config file\n", gateway.port())));
+    assert!(stdout.contains("api_token = \"<redacted>\"  # config file"));
+    assert!(stdout.contains("cache_ttl = 60  # env MATAHO_CACHE_TTL"));
+    assert!(stdout.contains("timeout = 7  # config file, [profiles.office]"));
+    assert!(stdout.contains("backend = \"http\"  # default"));
+}
+
+#[test]
+fn mataho_config_sets_config_dir() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::without_config();
+    let other = tempfile::TempDir::new().unwrap();
+    std::fs::write(
+        other.path().join("config.toml"),
+        format!(
+            "hostname = \"http://127.0.0.1\"\nport = {}\napi_token = \"{}\"\n",
+            gateway.port(),
+            API_TOKEN
+        ),
+    )
+    .unwrap();
+    let dir = other.path().to_string_lossy().to_string();
+
+    let output = mataho.run_with_env(&["config", "path"], &[("MATAHO_CONFIG", &dir)]);
+    assert_eq!(
+        stdout(&output).trim(),
+        other.path().join("config.toml").to_string_lossy()
+    );
+
+    let output = mataho.run_with_env(&["ls"], &[("MATAHO_CONFIG", &dir)]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(other.path().join("groups.json").exists());
+}
@@ -0,0 +1,221 @@
+mod common;
+
+use common::{stderr, stdout, FakeGateway, Mataho};
+
+#[test]
+fn list_prints_every_device() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&["ls"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let stdout = stdout(&output);
+    for label in [
+        "Front gate",
+        "Garage",
+        "Coko's room",
+        "Bedroom 1",
+        "Bedroom 2",
+    ] {
+        assert!(
+            stdout.contains(label),
+            "missing `{}` in:\n{}",
+            label,
+            stdout
+        );
+    }
+    assert!(stdout.contains("sliding gate"));
+    assert!(stdout.contains("garage opener"));
+}
+
+#[test]
+fn list_filters_by_type() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&["ls", "--filter=gate"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let stdout = stdout(&output);
+    assert!(stdout.contains("Front gate"));
+    assert!(!stdout.contains("Garage"));
+    assert!(!stdout.contains("Coko's room"));
+}
+
+#[test]
+fn list_filters_by_category_type_or_controllable_name() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    for (filter, count) in [
+        ("--filter=cover", 4),
+        ("--filter=roller-shutter", 3),
+        ("--filter=garage-door", 1),
+        ("--filter=io:RollerShutter*", 3),
+        ("--filter=io:*Gate*", 1),
+        ("--filter=light", 0),
+    ] {
+        let output = mataho.run(&["ls", filter, "-o", "tsv", "--no-headers"]);
+        assert!(output.status.success(), "{}", stderr(&output));
+        assert_eq!(stdout(&output).lines().count(), count, "{}", filter);
+    }
+
+    let output = mataho.run(&["ls", "--filter=toaster"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("Unknown device type `toaster`"));
+}
+
+#[test]
+fn list_uses_user_device_types() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    std::fs::write(
+        mataho.config_dir().join("device_types.toml"),
+        r#"
+[[types]]
+controllable = "io:GarageOpenerIOComponent"
+type = "carport door"
+category = "gate"
+"#,
+    )
+    .unwrap();
+
+    let output = mataho.run(&["ls", "--filter=gate"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let stdout = stdout(&output);
+    assert!(stdout.contains("Front gate"));
+    assert!(stdout.contains("carport door"));
+    assert!(!stdout.contains("Coko's room"));
+}
+
+#[test]
+fn list_uses_setup_cache() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    assert!(mataho.run(&["ls"]).status.success());
+    assert!(mataho.run(&["ls"]).status.success());
+    assert_eq!(gateway.count_requests("GET", "setup"), 1);
+
+    assert!(mataho.run(&["ls", "--refresh"]).status.success());
+    assert_eq!(gateway.count_requests("GET", "setup"), 2);
+}
+
+#[test]
+fn setup_cache_of_another_gateway_is_ignored() {
+    let gateway = FakeGateway::start();
+    let other = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    let other_port = other.port().to_string();
+
+    assert!(mataho.run(&["ls"]).status.success());
+
+    let output = mataho.run(&["ls", "--offline", "--port", &other_port]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("No cached device setup"));
+
+    let output = mataho.run(&["ls", "--port", &other_port]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(other.count_requests("GET", "setup"), 1);
+
+    // The cache now holds the setup of the other gateway
+    assert!(mataho.run(&["ls"]).status.success());
+    assert_eq!(gateway.count_requests("GET", "setup"), 2);
+}
+
+#[test]
+fn info_prints_device_commands() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&["info", "00000003"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let stdout = stdout(&output);
+    assert!(stdout.contains("- label: Coko's room"));
+    assert!(stdout.contains("- url: io://1234-5678-9012/00000003"));
+    assert!(stdout.contains("setClosureAndLinearSpeed"));
+}
+
+#[test]
+fn info_fuzzy_matches_label() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&["info", "coko"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("- label: Coko's room"));
+
+    let output = mataho.run(&["info", "frnt gte"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("- label: Front gate"));
+}
+
+#[test]
+fn info_rejects_ambiguous_match() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&["info", "bedroom"]);
+    assert_eq!(output.status.code(), Some(4));
+
+    let stderr = stderr(&output);
+    assert!(stderr.contains("several candidates"), "{}", stderr);
+    assert!(stderr.contains("`Bedroom 1`"));
+    assert!(stderr.contains("`Bedroom 2`"));
+}
+
+#[test]
+fn info_exact_match_mode_skips_fuzzy_matching() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&["info", "--match-mode=exact", "coko"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(stderr(&output).contains("Failed to find a device that matches"));
+
+    let output = mataho.run(&["info", "--match-mode=exact", "garage"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("- label: Garage"));
+}
+
+#[test]
+fn state_prints_typed_states() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&["state", "coko"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let stdout = stdout(&output);
+    assert!(stdout.contains("core:ClosureState: 100"));
+    assert!(stdout.contains("core:OpenClosedState: closed"));
+    assert!(stdout.contains("core:RSSILevelState: 74.5"));
+    assert!(stdout.contains("core:MovingState: false"));
+}
+
+#[test]
+fn jobs_lists_running_executions() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&["jobs"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let stdout = stdout(&output);
+    assert!(stdout.contains("Close all shutters"));
+    assert!(stdout.contains("Coko's room, Bedroom 1"));
+
+    let output = mataho.run(&["cancel", "all"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(gateway.count_requests("DELETE", "exec/current/setup"), 1);
+    // The ID stays a single segment of the path
+    let output = mataho.run(&["cancel", "a b/c"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        gateway.count_requests("DELETE", "exec/current/setup/a%20b%2Fc"),
+        1
+    );
+}
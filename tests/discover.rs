@@ -0,0 +1,168 @@
+mod common;
+
+use std::net::UdpSocket;
+
+use common::{stderr, stdout, FakeGateway, Mataho, API_TOKEN};
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+
+/// mDNS responder announcing fake gateways on loopback, on a port of its own so
+/// that tests neither clash with each other nor with the system mDNS daemon.
+struct Responder {
+    daemon: ServiceDaemon,
+    port: u16,
+}
+
+impl Responder {
+    fn start() -> Responder {
+        let port = UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let daemon = ServiceDaemon::new_with_port(port).unwrap();
+
+        Responder { daemon, port }
+    }
+
+    fn announce(&self, pin: &str, address: &str) {
+        let instance = format!("gateway-{}", pin);
+        let properties = [
+            ("gateway_pin", pin),
+            ("api_version", "1"),
+            ("fw_version", "2025.1.4-11"),
+        ];
+
+        let info = ServiceInfo::new(
+            "_kizboxdev._tcp.local.",
+            &instance,
+            &format!("{}.local.", instance),
+            address,
+            8443,
+            &properties[..],
+        )
+        .unwrap();
+
+        self.daemon.register(info).unwrap();
+    }
+
+    fn mdns_port(&self) -> String {
+        self.port.to_string()
+    }
+}
+
+impl Drop for Responder {
+    fn drop(&mut self) {
+        let _ = self.daemon.shutdown();
+    }
+}
+
+#[test]
+fn discover_lists_gateways() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    let responder = Responder::start();
+    responder.announce("1234-5678-9012", "127.0.0.1");
+
+    let output = mataho.run_with_env(
+        &["discover", "--timeout", "2"],
+        &[("MATAHO_MDNS_PORT", &responder.mdns_port())],
+    );
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    assert!(stdout.contains("1234-5678-9012"));
+    assert!(stdout.contains("gateway-1234-5678-9012.local"));
+    assert!(stdout.contains("127.0.0.1"));
+    assert!(stdout.contains("8443"));
+    assert!(stdout.contains("2025.1.4-11"));
+}
+
+#[test]
+fn discover_without_gateway() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    let responder = Responder::start();
+
+    let output = mataho.run_with_env(
+        &["discover", "--timeout", "1"],
+        &[("MATAHO_MDNS_PORT", &responder.mdns_port())],
+    );
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("No gateway found"));
+}
+
+#[test]
+fn discover_saves_single_gateway() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    let responder = Responder::start();
+    responder.announce("1234-5678-9012", "127.0.0.1");
+
+    let output = mataho.run_with_env(
+        &["discover", "--timeout", "2", "--save"],
+        &[("MATAHO_MDNS_PORT", &responder.mdns_port())],
+    );
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let config = mataho.read_config();
+    assert!(config.contains("hostname = \"https://gateway-1234-5678-9012.local\""));
+    assert!(config.contains("port = 8443"));
+    assert!(config.contains("api_token = "));
+}
+
+#[test]
+fn discover_saves_chosen_gateway_by_address() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    let responder = Responder::start();
+    responder.announce("1234-5678-9012", "127.0.0.1");
+    responder.announce("2222-3333-4444", "127.0.0.2");
+
+    let output = mataho.run_with_env(
+        &["discover", "--timeout", "2", "--save"],
+        &[("MATAHO_MDNS_PORT", &responder.mdns_port())],
+    );
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Several gateways found"));
+
+    // A responder answering the same query again may leave out records it just sent
+    let responder = Responder::start();
+    responder.announce("1234-5678-9012", "127.0.0.1");
+    responder.announce("2222-3333-4444", "127.0.0.2");
+
+    let output = mataho.run_with_env(
+        &[
+            "discover",
+            "--timeout",
+            "2",
+            "--save=2222-3333-4444",
+            "--by-address",
+        ],
+        &[("MATAHO_MDNS_PORT", &responder.mdns_port())],
+    );
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(mataho
+        .read_config()
+        .contains("hostname = \"https://127.0.0.2\""));
+}
+
+#[test]
+fn config_init_proposes_discovered_gateway() {
+    let mataho = Mataho::without_config();
+    let responder = Responder::start();
+    responder.announce("1234-5678-9012", "127.0.0.1");
+
+    let output = mataho.run_with_env_and_input(
+        &["config", "init", "--no-check"],
+        &[("MATAHO_MDNS_PORT", &responder.mdns_port())],
+        &format!("\n{}\n", API_TOKEN),
+    );
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("[https://gateway-1234-5678-9012.local]"));
+    let config = mataho.read_config();
+    assert!(config.contains("hostname = \"https://gateway-1234-5678-9012.local\""));
+    assert!(config.contains("port = 8443"));
+}
@@ -0,0 +1,200 @@
+mod common;
+
+use common::{stderr, stdout, FakeGateway, Fault, Mataho};
+use serde_json::json;
+
+#[test]
+fn exec_posts_command_to_device() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&["exec", "coko", "close"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Executing `close` on `Coko's room`..."));
+
+    let payloads = gateway.exec_payloads();
+    assert_eq!(payloads.len(), 1);
+    assert_eq!(
+        payloads[0]["actions"],
+        json!([{
+            "deviceURL": "io://1234-5678-9012/00000003",
+            "commands": [{ "name": "close", "parameters": [] }]
+        }])
+    );
+}
+
+#[test]
+fn exec_sends_command_arguments() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&["exec", "coko", "setClosureAndLinearSpeed", "20", "lowspeed"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let payloads = gateway.exec_payloads();
+    assert_eq!(payloads.len(), 1);
+    assert_eq!(
+        payloads[0]["actions"][0]["commands"][0]["name"],
+        "setClosureAndLinearSpeed"
+    );
+    assert_eq!(
+        payloads[0]["actions"][0]["commands"][0]["parameters"],
+        json!([20, "lowspeed"])
+    );
+}
+
+#[test]
+fn exec_rejects_out_of_range_argument() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&["exec", "coko", "setClosure", "150"]);
+    assert_eq!(output.status.code(), Some(5));
+    let stderr = stderr(&output);
+    assert!(
+        stderr.contains("closure: 150 is out of range (integer 0-100)"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("Expected: setClosure <closure: integer 0-100 %>"),
+        "{}",
+        stderr
+    );
+    assert!(gateway.exec_payloads().is_empty());
+}
+
+#[test]
+fn exec_rejects_wrong_number_of_arguments() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&["exec", "coko", "close", "20"]);
+    assert_eq!(output.status.code(), Some(5));
+    assert!(stderr(&output).contains("wrong number of arguments (1 given, none expected)"));
+
+    let output = mataho.run(&["exec", "coko", "setClosure"]);
+    assert_eq!(output.status.code(), Some(5));
+    assert!(stderr(&output).contains("wrong number of arguments (0 given, 1 expected)"));
+    assert!(gateway.exec_payloads().is_empty());
+}
+
+#[test]
+fn exec_rejects_unsupported_command() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&["exec", "front gate", "setClosure", "50"]);
+    assert_eq!(output.status.code(), Some(5));
+    assert!(stderr(&output).contains("does not support the `setClosure` command"));
+    assert!(gateway.exec_payloads().is_empty());
+}
+
+#[test]
+fn exec_wait_reports_completion() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&["exec", "garage", "close", "--wait", "--timeout", "5"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Execution `exec-1` completed"));
+}
+
+#[test]
+fn exec_wait_reports_failure_per_device() {
+    let gateway = FakeGateway::start();
+    gateway.fail_executions("CMDCANCELLED");
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&["exec", "garage", "close", "--wait", "--timeout", "5"]);
+    assert_eq!(output.status.code(), Some(10));
+
+    let stdout = stdout(&output);
+    assert!(
+        stdout.contains("Execution `exec-1` FAILED: CMDCANCELLED"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("- `Garage`: CMDCANCELLED"));
+}
+
+#[test]
+fn exec_wait_checks_execution_after_missing_events() {
+    let gateway = FakeGateway::start();
+    // The listener is registered again, losing the events of the execution
+    gateway.inject_fault_once("events/listener-1/fetch", Fault::Status(400));
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&["exec", "garage", "close", "--wait", "--timeout", "5"]);
+    // It may as well have failed
+    assert_eq!(output.status.code(), Some(10));
+    assert!(stdout(&output).contains("Execution `exec-1` ended, outcome unknown"));
+    assert!(stderr(&output).contains("its outcome is unknown"));
+    assert_eq!(gateway.count_requests("GET", "exec/current/exec-1"), 1);
+    // The listener registered first is not left behind
+    assert_eq!(
+        gateway.count_requests("POST", "events/listener-1/unregister"),
+        1
+    );
+}
+
+#[test]
+fn group_exec_sends_one_action_per_device() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    assert!(mataho
+        .run(&["group", "create", "bedrooms"])
+        .status
+        .success());
+    assert!(mataho
+        .run(&["group", "join", "bedrooms", "00000004"])
+        .status
+        .success());
+    assert!(mataho
+        .run(&["group", "join", "bedrooms", "00000005"])
+        .status
+        .success());
+
+    let output = mataho.run(&["group", "exec", "bedrooms", "close"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Executing `close` on group `bedrooms (2 devices)`..."));
+
+    let payloads = gateway.exec_payloads();
+    assert_eq!(payloads.len(), 1);
+
+    let device_urls: Vec<&str> = payloads[0]["actions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|action| action["deviceURL"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        device_urls,
+        [
+            "io://1234-5678-9012/00000004",
+            "io://1234-5678-9012/00000005"
+        ]
+    );
+}
+
+#[test]
+fn group_exec_rejects_command_unsupported_by_a_member() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    assert!(mataho.run(&["group", "create", "outside"]).status.success());
+    assert!(mataho
+        .run(&["group", "join", "outside", "front gate"])
+        .status
+        .success());
+    assert!(mataho
+        .run(&["group", "join", "outside", "garage"])
+        .status
+        .success());
+
+    let output = mataho.run(&["group", "exec", "outside", "setClosure", "50"]);
+    assert_eq!(output.status.code(), Some(5));
+    assert!(stderr(&output).contains("not supported by all devices"));
+    assert!(gateway.exec_payloads().is_empty());
+}
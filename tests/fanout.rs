@@ -0,0 +1,268 @@
+mod common;
+
+use common::{stderr, stdout, FakeGateway, Fault, Mataho, API_TOKEN};
+
+/// Top-level settings point at `house`, the `office` profile at `office`.
+fn two_profiles(house: &FakeGateway, office: &FakeGateway) -> Mataho {
+    let mataho = Mataho::new(house);
+    mataho.write_config(&format!(
+        "hostname = \"http://127.0.0.1\"\nport = {}\napi_token = \"{}\"\ntimeout = 2\n\n[profiles.office]\nport = {}\n",
+        house.port(),
+        API_TOKEN,
+        office.port()
+    ));
+
+    mataho
+}
+
+/// Group `everything` of the default profile, with `Coko's room` of the house
+/// and `Bedroom 1` of the office.
+fn group_across_gateways(mataho: &Mataho) {
+    for args in [
+        vec!["group", "create", "everything"],
+        vec!["group", "join", "everything", "coko"],
+        vec![
+            "group",
+            "join",
+            "everything",
+            "bedroom 1",
+            "--from",
+            "office",
+        ],
+    ] {
+        let output = mataho.run(&args);
+        assert!(output.status.success(), "{}", stderr(&output));
+    }
+}
+
+fn exec_device_urls(gateway: &FakeGateway) -> Vec<Vec<String>> {
+    gateway
+        .exec_payloads()
+        .iter()
+        .map(|payload| {
+            payload["actions"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|action| action["deviceURL"].as_str().unwrap().to_string())
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn list_all_profiles_merges_inventories() {
+    let house = FakeGateway::start();
+    let office = FakeGateway::start();
+    let mataho = two_profiles(&house, &office);
+
+    let output = mataho.run(&["ls", "--all-profiles"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    assert!(stdout.contains("Gateway"));
+    assert_eq!(stdout.matches("| default ").count(), 5);
+    assert_eq!(stdout.matches("| office ").count(), 5);
+    assert_eq!(house.count_requests("GET", "setup"), 1);
+    assert_eq!(office.count_requests("GET", "setup"), 1);
+}
+
+#[test]
+fn list_all_profiles_reports_unreachable_gateway() {
+    let house = FakeGateway::start();
+    let office = FakeGateway::start();
+    office.inject_fault("setup", Fault::Status(500));
+    let mataho = two_profiles(&house, &office);
+
+    let output = mataho.run(&["ls", "--all-profiles"]);
+
+    assert_eq!(output.status.code(), Some(9));
+    assert!(stderr(&output).contains("skipping profile `office`"));
+    assert_eq!(stdout(&output).matches("| default ").count(), 5);
+}
+
+#[test]
+fn group_exec_sends_one_execution_per_gateway() {
+    let house = FakeGateway::start();
+    let office = FakeGateway::start();
+    let mataho = two_profiles(&house, &office);
+    group_across_gateways(&mataho);
+
+    let output = mataho.run(&["group", "ls"]);
+    assert!(stdout(&output).contains("everything(2): `Coko's room`, `office:00000004`"));
+
+    let output = mataho.run(&["group", "exec", "everything", "close"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    assert!(
+        stdout.contains("Executing `close` on group `everything (2 devices)` across 2 gateways...")
+    );
+    assert!(stdout.contains("- default (1 devices): execution `exec-1` started"));
+    assert!(stdout.contains("- office (1 devices): execution `exec-1` started"));
+
+    assert_eq!(exec_device_urls(&house), [["io://1234-5678-9012/00000003"]]);
+    assert_eq!(
+        exec_device_urls(&office),
+        [["io://1234-5678-9012/00000004"]]
+    );
+}
+
+#[test]
+fn group_exec_goes_on_when_a_gateway_is_down() {
+    let house = FakeGateway::start();
+    let office = FakeGateway::start();
+    let mataho = two_profiles(&house, &office);
+    group_across_gateways(&mataho);
+    office.inject_fault("setup", Fault::Status(500));
+
+    let output = mataho.run(&["group", "exec", "everything", "close", "--refresh"]);
+
+    assert_eq!(output.status.code(), Some(9));
+    let stdout = stdout(&output);
+    assert!(stdout.contains("- default (1 devices): execution `exec-1` started"));
+    assert!(
+        stdout.contains("- office (1 devices): failed to execute: Failed to get setup"),
+        "{}",
+        stdout
+    );
+    assert_eq!(exec_device_urls(&house), [["io://1234-5678-9012/00000003"]]);
+    assert!(office.exec_payloads().is_empty());
+}
+
+#[test]
+fn group_exec_wait_reports_per_gateway_results() {
+    let house = FakeGateway::start();
+    let office = FakeGateway::start();
+    office.fail_executions("CMDCANCELLED");
+    let mataho = two_profiles(&house, &office);
+    group_across_gateways(&mataho);
+
+    let output = mataho.run(&["group", "exec", "everything", "close", "--wait"]);
+
+    assert_eq!(output.status.code(), Some(10));
+    let stdout = stdout(&output);
+    assert!(stdout.contains("- default (1 devices): execution `exec-1` completed"));
+    assert!(stdout.contains("- office (1 devices): execution `exec-1` FAILED: CMDCANCELLED"));
+}
+
+#[test]
+fn group_exec_goes_on_when_a_gateway_rejects_it() {
+    let house = FakeGateway::start();
+    let office = FakeGateway::start();
+    let mataho = two_profiles(&house, &office);
+    group_across_gateways(&mataho);
+    house.inject_fault("exec/apply", Fault::Status(500));
+
+    let output = mataho.run(&["group", "exec", "everything", "close"]);
+
+    assert_eq!(output.status.code(), Some(9));
+    assert!(stdout(&output).contains("- default (1 devices): failed to execute"));
+    assert_eq!(exec_device_urls(&office).len(), 1);
+}
+
+#[test]
+fn group_exec_checks_every_gateway_before_sending() {
+    let house = FakeGateway::start();
+    let office = FakeGateway::start();
+    let mataho = two_profiles(&house, &office);
+    group_across_gateways(&mataho);
+
+    let output = mataho.run(&[
+        "group",
+        "join",
+        "everything",
+        "front gate",
+        "--from",
+        "office",
+    ]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let output = mataho.run(&["group", "exec", "everything", "my"]);
+
+    assert_eq!(output.status.code(), Some(5));
+    assert!(stderr(&output).contains("`Front gate`"));
+    assert!(house.exec_payloads().is_empty());
+    assert!(office.exec_payloads().is_empty());
+}
+
+#[test]
+fn group_leave_removes_remote_device() {
+    let house = FakeGateway::start();
+    let office = FakeGateway::start();
+    let mataho = two_profiles(&house, &office);
+    group_across_gateways(&mataho);
+
+    let output = mataho.run(&[
+        "group",
+        "leave",
+        "everything",
+        "bedroom 1",
+        "--from",
+        "office",
+    ]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let output = mataho.run(&["group", "ls"]);
+    assert!(stdout(&output).contains("everything(1): `Coko's room`"));
+}
+
+/// Settings of the default profile given by the environment only.
+fn gateway_env(gateway: &FakeGateway) -> Vec<(&'static str, String)> {
+    vec![
+        ("MATAHO_HOSTNAME", "http://127.0.0.1".to_string()),
+        ("MATAHO_PORT", gateway.port().to_string()),
+        ("MATAHO_API_TOKEN", API_TOKEN.to_string()),
+    ]
+}
+
+#[test]
+fn list_all_profiles_uses_environment() {
+    let house = FakeGateway::start();
+    let mataho = Mataho::without_config();
+    let env = gateway_env(&house);
+    let env: Vec<(&str, &str)> = env.iter().map(|(var, val)| (*var, val.as_str())).collect();
+
+    let output = mataho.run_with_env(&["ls", "--all-profiles"], &env);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output).matches("| default ").count(), 5);
+    assert_eq!(house.count_requests("GET", "setup"), 1);
+}
+
+#[test]
+fn group_exec_uses_environment_for_selected_profile() {
+    let house = FakeGateway::start();
+    let office = FakeGateway::start();
+    let mataho = Mataho::new(&office);
+    mataho.write_config(&format!(
+        "timeout = 2\n\n[profiles.office]\nhostname = \"http://127.0.0.1\"\nport = {}\napi_token = \"{}\"\n",
+        office.port(),
+        API_TOKEN
+    ));
+    let env = gateway_env(&house);
+    let env: Vec<(&str, &str)> = env.iter().map(|(var, val)| (*var, val.as_str())).collect();
+
+    for args in [
+        vec!["group", "create", "everything"],
+        vec!["group", "join", "everything", "coko"],
+        vec![
+            "group",
+            "join",
+            "everything",
+            "bedroom 1",
+            "--from",
+            "office",
+        ],
+        vec!["group", "exec", "everything", "close"],
+    ] {
+        let output = mataho.run_with_env(&args, &env);
+        assert!(output.status.success(), "{}", stderr(&output));
+    }
+
+    assert_eq!(exec_device_urls(&house), [["io://1234-5678-9012/00000003"]]);
+    assert_eq!(
+        exec_device_urls(&office),
+        [["io://1234-5678-9012/00000004"]]
+    );
+}
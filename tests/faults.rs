@@ -0,0 +1,74 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{stderr, FakeGateway, Fault, Mataho};
+
+#[test]
+fn unauthorized_token_fails() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    mataho.write_config(&format!(
+        "hostname = \"http://127.0.0.1\"\nport = {}\napi_token = \"wrong-token\"\n",
+        gateway.port()
+    ));
+
+    let output = mataho.run(&["ls"]);
+    assert_eq!(output.status.code(), Some(7));
+    assert!(stderr(&output).contains("401"), "{}", stderr(&output));
+}
+
+#[test]
+fn server_error_on_setup_fails() {
+    let gateway = FakeGateway::start();
+    gateway.inject_fault("setup", Fault::Status(500));
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&["ls"]);
+    assert_eq!(output.status.code(), Some(9));
+}
+
+#[test]
+fn server_error_on_exec_fails() {
+    let gateway = FakeGateway::start();
+    gateway.inject_fault("exec/apply", Fault::Status(500));
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&["exec", "garage", "open"]);
+    assert_eq!(output.status.code(), Some(9));
+    assert!(stderr(&output).contains("Failed to execute command"));
+    assert_eq!(gateway.count_requests("POST", "exec/apply"), 1);
+}
+
+#[test]
+fn malformed_setup_fails() {
+    let gateway = FakeGateway::start();
+    gateway.inject_fault("setup", Fault::MalformedJson);
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&["ls"]);
+    assert_eq!(output.status.code(), Some(9));
+}
+
+#[test]
+fn slow_gateway_times_out() {
+    let gateway = FakeGateway::start();
+    gateway.inject_fault("setup", Fault::Delay(Duration::from_secs(4)));
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&["ls"]);
+    assert_eq!(output.status.code(), Some(8));
+}
+
+#[test]
+fn offline_commands_do_not_contact_gateway() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    assert!(mataho
+        .run(&["group", "create", "bedrooms"])
+        .status
+        .success());
+    assert!(mataho.run(&["group", "ls"]).status.success());
+    assert!(gateway.requests().is_empty());
+}
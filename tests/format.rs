@@ -0,0 +1,138 @@
+mod common;
+
+use common::{stderr, stdout, FakeGateway, Mataho};
+
+fn formatted(mataho: &Mataho, args: &[&str]) -> String {
+    let output = mataho.run(args);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    stdout(&output)
+}
+
+#[test]
+fn list_prints_one_line_per_device() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let lines = formatted(
+        &mataho,
+        &["ls", "--format", r"{{id}}\t{{label}} ({{type}})"],
+    );
+
+    let lines: Vec<&str> = lines.lines().collect();
+    assert_eq!(lines.len(), 5);
+    assert_eq!(lines[0], "00000001\tFront gate (sliding gate)");
+    // Device states are only fetched when the template uses them
+    assert!(!gateway
+        .requests()
+        .iter()
+        .any(|request| request.path.ends_with("/states")));
+}
+
+#[test]
+fn list_fetches_states_used_by_template() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let lines = formatted(
+        &mataho,
+        &[
+            "ls",
+            "--format",
+            "{{label}}:{{state.core:ClosureState}}:{{state.core:NoSuchState}}",
+        ],
+    );
+
+    assert!(lines.lines().any(|line| line == "Coko's room:100:"));
+    assert!(gateway
+        .requests()
+        .iter()
+        .any(|request| request.path.ends_with("/states")));
+}
+
+#[test]
+fn info_joins_command_names() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let line = formatted(
+        &mataho,
+        &["info", "coko", "--format", "{{url}} {{commands}}"],
+    );
+
+    assert_eq!(
+        line,
+        "io://1234-5678-9012/00000003 open, close, stop, my, setClosure, setClosureAndLinearSpeed\n"
+    );
+}
+
+#[test]
+fn state_template_reads_typed_values() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let line = formatted(
+        &mataho,
+        &[
+            "state",
+            "coko",
+            "--format",
+            "{{state.core:OpenClosedState}} {{state.core:RSSILevelState}} {{state.core:MovingState}}",
+        ],
+    );
+
+    assert_eq!(line, "closed 74.5 false\n");
+}
+
+#[test]
+fn group_list_template() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    assert!(mataho
+        .run(&["group", "create", "bedrooms"])
+        .status
+        .success());
+    for device in ["00000004", "00000005"] {
+        assert!(mataho
+            .run(&["group", "join", "bedrooms", device])
+            .status
+            .success());
+    }
+
+    let line = formatted(
+        &mataho,
+        &["group", "ls", "--format", "{{name}}={{devices}}"],
+    );
+
+    assert_eq!(line, "bedrooms=Bedroom 1, Bedroom 2\n");
+}
+
+#[test]
+fn unknown_field_lists_known_ones() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&["ls", "--format", "{{name}}"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout(&output).is_empty());
+    let stderr = stderr(&output);
+    assert!(stderr.contains("Unknown field `name` in the format"));
+    assert!(stderr.contains("id, label"));
+}
+
+#[test]
+fn format_is_rejected_elsewhere() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&["jobs", "--format", "{{label}}"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("`--format` is only supported by"));
+
+    let output = mataho.run(&["ls", "--format", "{{label}}", "-o", "json"]);
+    assert_eq!(output.status.code(), Some(2));
+
+    let output = mataho.run(&["ls", "--format", "{{label"]);
+    assert!(stderr(&output).contains("Unclosed `{{`"));
+}
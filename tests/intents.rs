@@ -0,0 +1,116 @@
+mod common;
+
+use common::{stderr, stdout, FakeGateway, Mataho};
+use serde_json::{json, Value};
+
+fn run_ok(mataho: &Mataho, args: &[&str]) -> String {
+    let output = mataho.run(args);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    stdout(&output)
+}
+
+/// Group "home": a sliding gate, a garage opener and a roller shutter.
+fn home(mataho: &Mataho) {
+    run_ok(mataho, &["group", "create", "home"]);
+    for device in ["00000001", "00000002", "00000003"] {
+        run_ok(mataho, &["group", "join", "home", device]);
+    }
+}
+
+fn action(device_id: &str, name: &str, params: Value) -> Value {
+    json!({
+        "deviceURL": format!("io://1234-5678-9012/{}", device_id),
+        "commands": [{ "name": name, "parameters": params }]
+    })
+}
+
+#[test]
+fn set_sends_the_positioning_command_of_the_device() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let stdout = run_ok(&mataho, &["set", "coko", "30"]);
+    assert!(stdout.contains("Executing `set 30` on `Coko's room`..."));
+
+    let payloads = gateway.exec_payloads();
+    assert_eq!(payloads.len(), 1);
+    assert_eq!(
+        payloads[0]["actions"],
+        json!([action("00000003", "setClosure", json!([30]))])
+    );
+}
+
+#[test]
+fn group_of_mixed_devices_gets_one_execution() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    home(&mataho);
+
+    run_ok(&mataho, &["open", "--group", "home"]);
+    // The gate cannot be positioned, closing it is the closest
+    run_ok(&mataho, &["set", "--group", "home", "100"]);
+
+    let payloads = gateway.exec_payloads();
+    assert_eq!(payloads.len(), 2);
+    assert_eq!(
+        payloads[0]["actions"],
+        json!([
+            action("00000001", "open", json!([])),
+            action("00000002", "open", json!([])),
+            action("00000003", "open", json!([])),
+        ])
+    );
+    assert_eq!(
+        payloads[1]["actions"],
+        json!([
+            action("00000001", "close", json!([])),
+            action("00000002", "setClosure", json!([100])),
+            action("00000003", "setClosure", json!([100])),
+        ])
+    );
+}
+
+#[test]
+fn group_intent_is_checked_on_every_device_first() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    home(&mataho);
+
+    let output = mataho.run(&["set", "--group", "home", "40"]);
+    assert_eq!(output.status.code(), Some(5));
+    let stderr = stderr(&output);
+    assert!(
+        stderr.contains("`set 40` is not supported by `Front gate`"),
+        "{}",
+        stderr
+    );
+    assert!(gateway.exec_payloads().is_empty());
+}
+
+#[test]
+fn my_needs_a_favourite_position() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    run_ok(&mataho, &["my", "coko"]);
+    assert_eq!(
+        gateway.exec_payloads()[0]["actions"],
+        json!([action("00000003", "my", json!([]))])
+    );
+
+    let output = mataho.run(&["my", "garage"]);
+    assert_eq!(output.status.code(), Some(5));
+    assert!(stderr(&output).contains("Device `Garage` does not support the `my` command"));
+    assert_eq!(gateway.exec_payloads().len(), 1);
+}
+
+#[test]
+fn set_rejects_percentages_above_100() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&["set", "coko", "150"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(gateway.exec_payloads().is_empty());
+}
@@ -0,0 +1,354 @@
+mod common;
+
+use common::{FakeGateway, API_TOKEN};
+use mataho::{
+    catalog,
+    controller::{ExecAction, ExecCommand},
+    device_type::{Capability, Category, DeviceTypeRegistry},
+    events::Event,
+    intent::Intent,
+    model::{DeviceTypeFilter, MatchMode},
+    params::Signature,
+    Configuration, Device, MatahoError, MatahoService, TahomaApi, TahomaApiController,
+};
+use serde_json::json;
+
+fn controller(gateway: &FakeGateway) -> TahomaApiController {
+    let config = Configuration {
+        hostname: "http://127.0.0.1".to_string(),
+        port: gateway.port() as i32,
+        api_token: API_TOKEN.to_string(),
+        ..Configuration::new()
+    };
+
+    TahomaApiController::new(&config)
+}
+
+#[test]
+fn service_resolves_devices_without_printing() {
+    let gateway = FakeGateway::start();
+    let controller = controller(&gateway);
+    let service = MatahoService::new(controller.get_setup().unwrap());
+
+    assert_eq!(service.devices().len(), 5);
+
+    let device = service.find_device("coko", MatchMode::Fuzzy).unwrap();
+    assert_eq!(device.label(), "Coko's room");
+    assert_eq!(device.id(), "00000003");
+
+    match service.find_device("bedroom", MatchMode::Fuzzy) {
+        Err(MatahoError::AmbiguousMatch { candidates, .. }) => {
+            assert_eq!(candidates, vec!["Bedroom 1", "Bedroom 2"]);
+        }
+        res => panic!(
+            "expected an ambiguous match, got {:?}",
+            res.map(|d| d.label())
+        ),
+    }
+}
+
+#[test]
+fn service_executes_on_device() {
+    let gateway = FakeGateway::start();
+    let controller = controller(&gateway);
+    let service = MatahoService::new(controller.get_setup().unwrap());
+
+    let device = service.find_device("garage", MatchMode::Exact).unwrap();
+    let exec_id = service
+        .execute_on_device(&controller, device, "open", &Vec::new())
+        .unwrap();
+
+    assert_eq!(exec_id, "exec-1");
+    assert_eq!(gateway.exec_payloads().len(), 1);
+    let err = service
+        .execute_on_device(&controller, device, "setPedestrianPosition", &Vec::new())
+        .unwrap_err();
+    assert!(matches!(err, MatahoError::CommandNotSupported { .. }));
+    assert_eq!(err.exit_code(), 5);
+}
+
+#[test]
+fn controller_executes_batch_in_one_request() {
+    let gateway = FakeGateway::start();
+    let controller = controller(&gateway);
+    let service = MatahoService::new(controller.get_setup().unwrap());
+    let shutter = service.find_device("coko", MatchMode::Fuzzy).unwrap();
+    let gate = service.find_device("front gate", MatchMode::Fuzzy).unwrap();
+
+    let batch = [
+        ExecAction {
+            device: shutter,
+            commands: vec![
+                ExecCommand::new("setClosure", vec![json!(20)]),
+                ExecCommand::new("my", Vec::new()),
+            ],
+        },
+        ExecAction {
+            device: gate,
+            commands: vec![ExecCommand::new("stop", Vec::new())],
+        },
+    ];
+    let exec_id = controller.execute_batch("Evening", &batch).unwrap();
+
+    assert_eq!(exec_id, "exec-1");
+    assert_eq!(
+        gateway.exec_payloads(),
+        vec![json!({
+            "label": "Evening",
+            "actions": [
+                {
+                    "deviceURL": "io://1234-5678-9012/00000003",
+                    "commands": [
+                        { "name": "setClosure", "parameters": [20] },
+                        { "name": "my", "parameters": [] }
+                    ]
+                },
+                {
+                    "deviceURL": "io://1234-5678-9012/00000001",
+                    "commands": [{ "name": "stop", "parameters": [] }]
+                }
+            ]
+        })]
+    );
+}
+
+#[test]
+fn controller_rejects_invalid_batch_before_sending() {
+    let gateway = FakeGateway::start();
+    let controller = controller(&gateway);
+    let service = MatahoService::new(controller.get_setup().unwrap());
+    let shutter = service.find_device("coko", MatchMode::Fuzzy).unwrap();
+    let gate = service.find_device("front gate", MatchMode::Fuzzy).unwrap();
+
+    let err = controller.execute_batch("Nothing", &[]).unwrap_err();
+    assert!(matches!(err, MatahoError::InvalidExecution { .. }));
+
+    let twice = ExecAction::same_command(&[shutter, shutter], "open", &[]).unwrap();
+    let err = controller.execute_batch("Twice", &twice).unwrap_err();
+    assert!(err.to_string().contains("several actions for"));
+
+    let unsupported = [ExecAction {
+        device: gate,
+        commands: vec![ExecCommand::new("setClosure", vec![json!(20)])],
+    }];
+    let err = controller.execute_batch("Gate", &unsupported).unwrap_err();
+    assert!(matches!(err, MatahoError::CommandNotSupported { .. }));
+
+    assert!(gateway.exec_payloads().is_empty());
+}
+
+#[test]
+fn signature_is_documented_by_the_catalog() {
+    let signature = Signature::parse("setClosureAndLinearSpeed", 2, Some("p1,p2"));
+    assert_eq!(
+        signature.to_string(),
+        "setClosureAndLinearSpeed <closure: integer 0-100 %> <speed: text>"
+    );
+    assert_eq!(
+        signature.convert(&["20".to_string(), "lowspeed".to_string()]),
+        Ok(vec![json!(20), json!("lowspeed")])
+    );
+
+    // Names and types given by the gateway win
+    let signature = Signature::parse("setClosure", 1, Some("value:float"));
+    assert_eq!(signature.to_string(), "setClosure <value: number %>");
+
+    // Unknown commands keep the signature of the gateway
+    let signature = Signature::parse("setFoo", 1, Some("*p1"));
+    assert_eq!(signature.to_string(), "setFoo [p1: value]");
+    // Untyped arguments that read as numbers are sent as numbers
+    assert_eq!(signature.convert(&["007".to_string()]), Ok(vec![json!(7)]));
+    assert_eq!(
+        signature.convert(&["1e3".to_string()]),
+        Ok(vec![json!(1000.0)])
+    );
+    assert_eq!(
+        signature.convert(&["low".to_string()]),
+        Ok(vec![json!("low")])
+    );
+    assert!(catalog::builtin().command("setFoo").is_none());
+}
+
+#[test]
+fn float_rejects_non_finite() {
+    // No range to catch them
+    let signature = Signature::parse("setTargetTemperature", 1, None);
+    assert_eq!(
+        signature.convert(&["19.5".to_string()]),
+        Ok(vec![json!(19.5)])
+    );
+    for arg in ["NaN", "inf", "-inf", "infinity"] {
+        assert_eq!(
+            signature.convert(&[arg.to_string()]),
+            Err(format!("temperature: `{}` is not a number", arg))
+        );
+    }
+}
+
+#[test]
+fn intents_map_to_the_native_command_of_each_device() {
+    let awning: Device = serde_json::from_value(json!({
+        "label": "Terrace",
+        "controllableName": "io:HorizontalAwningIOComponent",
+        "deviceURL": "io://1234-5678-9012/00000010",
+        "enabled": true,
+        "definition": { "commands": [
+            { "commandName": "deploy", "nparams": 0 },
+            { "commandName": "undeploy", "nparams": 0 },
+            { "commandName": "setDeployment", "nparams": 1, "paramsSig": "p1" }
+        ] }
+    }))
+    .unwrap();
+    let rts: Device = serde_json::from_value(json!({
+        "label": "Kitchen",
+        "controllableName": "rts:RollerShutterRTSComponent",
+        "deviceURL": "rts://1234-5678-9012/16711680",
+        "enabled": true,
+        "definition": { "commands": [
+            { "commandName": "up", "nparams": 0 },
+            { "commandName": "down", "nparams": 0 },
+            { "commandName": "my", "nparams": 0 }
+        ] }
+    }))
+    .unwrap();
+    let gate: Device = serde_json::from_value(json!({
+        "label": "Front gate",
+        "controllableName": "io:SlidingDiscreteGateOpenerIOComponent",
+        "deviceURL": "io://1234-5678-9012/00000011",
+        "enabled": true,
+        "definition": { "commands": [
+            { "commandName": "open", "nparams": 0 },
+            { "commandName": "close", "nparams": 0 },
+            { "commandName": "setPosition", "nparams": 1, "paramsSig": "p1" }
+        ] }
+    }))
+    .unwrap();
+
+    let command = |intent: Intent, device: &Device| intent.command_for(device);
+    assert_eq!(
+        command(Intent::Open, &awning),
+        Some(ExecCommand::new("deploy", Vec::new()))
+    );
+    // An awning 30% closed is 70% deployed
+    assert_eq!(
+        command(Intent::Set(30), &awning),
+        Some(ExecCommand::new("setDeployment", vec![json!(70)]))
+    );
+    assert_eq!(
+        command(Intent::Close, &rts),
+        Some(ExecCommand::new("down", Vec::new()))
+    );
+    // `my` would move it to its favourite position rather than stop it
+    assert_eq!(command(Intent::Stop, &rts), None);
+    assert_eq!(command(Intent::Set(50), &rts), None);
+    // `setPosition` takes the closure, as `setClosure`
+    assert_eq!(
+        command(Intent::Set(30), &gate),
+        Some(ExecCommand::new("setPosition", vec![json!(30)]))
+    );
+}
+
+#[test]
+fn device_types_come_from_the_registry() {
+    let builtin = DeviceTypeRegistry::builtin();
+    let shutter = builtin
+        .lookup("io:RollerShutterWithLowSpeedManagementIOComponent")
+        .unwrap();
+    assert_eq!(shutter.name, "roller shutter");
+    assert_eq!(shutter.category, Category::Cover);
+    assert!(shutter.capabilities.contains(&Capability::Favourite));
+    assert_eq!(
+        builtin
+            .lookup("rts:SlidingGateOpenerRTSComponent")
+            .unwrap()
+            .category,
+        Category::Gate
+    );
+    assert!(builtin.lookup("io:ToasterIOComponent").is_none());
+
+    let registry = DeviceTypeRegistry::with_overrides(
+        r#"
+[[types]]
+controllable = "io:Toaster*"
+type = "toaster"
+category = "climate"
+capabilities = ["on-off", "temperature"]
+
+[[types]]
+controllable = "io:RollerShutter*"
+type = "shutter"
+category = "cover"
+"#,
+    )
+    .unwrap();
+    assert_eq!(
+        registry.lookup("io:ToasterIOComponent").unwrap().name,
+        "toaster"
+    );
+    assert_eq!(
+        registry
+            .lookup("io:RollerShutterGenericIOComponent")
+            .unwrap()
+            .name,
+        "shutter"
+    );
+    // Built-in types the user did not change are kept
+    assert_eq!(
+        registry.lookup("io:GarageOpenerIOComponent").unwrap().name,
+        "garage opener"
+    );
+
+    assert!(matches!(
+        DeviceTypeRegistry::with_overrides("[[types]]\ncontrollable = 1"),
+        Err(MatahoError::Config(_))
+    ));
+}
+
+#[test]
+#[allow(deprecated)]
+fn deprecated_device_type_methods_use_the_builtin_registry() {
+    let gate: Device = serde_json::from_value(json!({
+        "label": "Front gate",
+        "controllableName": "io:SlidingDiscreteGateOpenerIOComponent",
+        "deviceURL": "io://1234-5678-9012/00000001",
+        "enabled": true,
+        "definition": { "commands": [] }
+    }))
+    .unwrap();
+
+    assert_eq!(gate.device_type(), "sliding gate");
+    assert!(gate.has_type(DeviceTypeFilter::All));
+    assert!(gate.has_type("gate".parse().unwrap()));
+    assert!(!gate.has_type("garage-door".parse().unwrap()));
+}
+
+#[test]
+fn failed_commands_may_have_no_failure_type() {
+    let events: Vec<Event> = serde_json::from_value(json!([
+        {
+            "name": "ExecutionStateChangedEvent",
+            "execId": "exec-1",
+            "oldState": "IN_PROGRESS",
+            "newState": "FAILED",
+            "failedCommands": [
+                { "deviceURL": "io://1234-5678-9012/00000002", "rank": 0 },
+                {
+                    "deviceURL": "io://1234-5678-9012/00000003",
+                    "rank": 0,
+                    "failureType": "CMDCANCELLED"
+                }
+            ]
+        }
+    ]))
+    .unwrap();
+
+    let Event::ExecutionStateChanged {
+        failed_commands: Some(failed_commands),
+        ..
+    } = &events[0]
+    else {
+        panic!("unexpected event: {:?}", events[0]);
+    };
+    assert_eq!(failed_commands[0].failure_type(), None);
+    assert_eq!(failed_commands[1].failure_type(), Some("CMDCANCELLED"));
+}
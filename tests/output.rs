@@ -0,0 +1,208 @@
+mod common;
+
+use common::{stderr, stdout, FakeGateway, Mataho};
+use serde_json::{json, Value};
+
+fn json_output(mataho: &Mataho, args: &[&str]) -> Value {
+    let output = mataho.run(args);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    serde_json::from_str(&stdout(&output)).expect("stdout is a single JSON document")
+}
+
+#[test]
+fn list_json_is_stable() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let devices = json_output(&mataho, &["ls", "--output", "json"]);
+
+    assert_eq!(devices.as_array().unwrap().len(), 5);
+    // Scripts rely on these names: only add fields, never rename or remove them
+    assert_eq!(
+        devices[0],
+        json!({
+            "gateway": "default",
+            "id": "00000001",
+            "label": "Front gate",
+            "type": "sliding gate",
+            "category": "gate",
+            "capabilities": ["open-close", "stop", "pedestrian"],
+            "controllable_name": "io:SlidingDiscreteGateOpenerIOComponent",
+            "url": "io://1234-5678-9012/00000001"
+        })
+    );
+}
+
+#[test]
+fn list_csv_and_tsv_follow_table_columns() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&["ls", "-o", "csv"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let csv = stdout(&output);
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 6);
+    assert_eq!(lines[0], "ID,Label,Type");
+    assert_eq!(lines[1], "00000001,Front gate,sliding gate");
+
+    let output = mataho.run(&["ls", "-l", "-o", "tsv", "--no-headers"]);
+    let tsv = stdout(&output);
+    let lines: Vec<&str> = tsv.lines().collect();
+    assert_eq!(lines.len(), 5);
+    assert_eq!(
+        lines[1],
+        "00000002\tGarage\tio:GarageOpenerIOComponent\tio://1234-5678-9012/00000002"
+    );
+}
+
+#[test]
+fn table_without_headers() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&["ls", "--no-headers"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    assert!(stdout.contains("| Front gate "));
+    assert!(!stdout.contains("| Label "));
+}
+
+#[test]
+fn info_yaml_lists_commands() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&["info", "coko", "-o", "yaml"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let info: Value = serde_yaml::from_str(&stdout(&output)).unwrap();
+
+    assert_eq!(info["label"], "Coko's room");
+    assert_eq!(info["commands"][4]["name"], "setClosure");
+    assert_eq!(info["commands"][4]["params_count"], 1);
+}
+
+#[test]
+fn state_json_keeps_value_types() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let view = json_output(&mataho, &["state", "coko", "-o", "json"]);
+
+    assert_eq!(view["id"], "00000003");
+    let states = view["states"].as_array().unwrap();
+    assert!(states.contains(&json!({ "name": "core:ClosureState", "value": 100 })));
+    assert!(states.contains(&json!({ "name": "core:RSSILevelState", "value": 74.5 })));
+    assert!(states.contains(&json!({ "name": "core:MovingState", "value": false })));
+}
+
+#[test]
+fn group_list_json() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    assert!(mataho
+        .run(&["group", "create", "bedrooms"])
+        .status
+        .success());
+    assert!(mataho
+        .run(&["group", "join", "bedrooms", "00000004"])
+        .status
+        .success());
+
+    let groups = json_output(&mataho, &["group", "ls", "-o", "json"]);
+
+    assert_eq!(
+        groups,
+        json!([{
+            "name": "bedrooms",
+            "devices": [{ "gateway": "default", "id": "00000004", "label": "Bedroom 1" }]
+        }])
+    );
+}
+
+#[test]
+fn exec_json_keeps_progress_off_stdout() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&["exec", "garage", "close", "-o", "json"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stderr(&output).contains("Executing `close` on `Garage`..."));
+    let execution: Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(execution["exec_id"], "exec-1");
+    assert_eq!(execution["state"], "STARTED");
+    assert_eq!(execution["devices"], json!(["Garage"]));
+}
+
+#[test]
+fn exec_json_reports_failure() {
+    let gateway = FakeGateway::start();
+    gateway.fail_executions("CMDCANCELLED");
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&[
+        "exec",
+        "garage",
+        "close",
+        "--wait",
+        "--timeout",
+        "5",
+        "-o",
+        "json",
+    ]);
+
+    assert_eq!(output.status.code(), Some(10));
+    let execution: Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(execution["state"], "FAILED");
+    assert_eq!(execution["failure_type"], "CMDCANCELLED");
+}
+
+#[test]
+fn group_exec_json_lists_executions() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    assert!(mataho
+        .run(&["group", "create", "bedrooms"])
+        .status
+        .success());
+    for device in ["00000004", "00000005"] {
+        assert!(mataho
+            .run(&["group", "join", "bedrooms", device])
+            .status
+            .success());
+    }
+
+    let executions = json_output(
+        &mataho,
+        &["group", "exec", "bedrooms", "close", "-o", "json"],
+    );
+
+    assert_eq!(executions.as_array().unwrap().len(), 1);
+    assert_eq!(executions[0]["gateway"], "default");
+    assert_eq!(executions[0]["devices"], json!(["Bedroom 1", "Bedroom 2"]));
+}
+
+#[test]
+fn jobs_json() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let jobs = json_output(&mataho, &["jobs", "-o", "json"]);
+
+    assert_eq!(jobs[0]["label"], "Close all shutters");
+    assert_eq!(jobs[0]["devices"], json!(["Coko's room", "Bedroom 1"]));
+}
+
+#[test]
+fn watch_rejects_tabular_formats() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+
+    let output = mataho.run(&["watch", "-o", "csv"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("`csv` is not supported"));
+}
@@ -0,0 +1,133 @@
+mod common;
+
+use common::{stderr, stdout, FakeGateway, Mataho, API_TOKEN};
+
+/// Top-level settings point at `house`, the `office` profile at `office`.
+fn two_profiles(house: &FakeGateway, office: &FakeGateway, extra: &str) -> Mataho {
+    let mataho = Mataho::new(house);
+    mataho.write_config(&format!(
+        "hostname = \"http://127.0.0.1\"\nport = {}\napi_token = \"{}\"\ntimeout = 2\n{}\n[profiles.office]\nport = {}\n",
+        house.port(),
+        API_TOKEN,
+        extra,
+        office.port()
+    ));
+
+    mataho
+}
+
+#[test]
+fn profile_flag_selects_gateway() {
+    let house = FakeGateway::start();
+    let office = FakeGateway::start();
+    let mataho = two_profiles(&house, &office, "");
+
+    let output = mataho.run(&["--profile", "office", "ls"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Coko's room"));
+    assert_eq!(office.count_requests("GET", "setup"), 1);
+    assert_eq!(house.count_requests("GET", "setup"), 0);
+
+    let output = mataho.run(&["ls"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(house.count_requests("GET", "setup"), 1);
+}
+
+#[test]
+fn profile_env_var_selects_gateway() {
+    let house = FakeGateway::start();
+    let office = FakeGateway::start();
+    let mataho = two_profiles(&house, &office, "");
+
+    let output = mataho.run_with_env(&["ls"], &[("MATAHO_PROFILE", "office")]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(office.count_requests("GET", "setup"), 1);
+    assert_eq!(house.count_requests("GET", "setup"), 0);
+
+    // The flag wins over the environment
+    let output = mataho.run_with_env(
+        &["--profile", "default", "ls"],
+        &[("MATAHO_PROFILE", "office")],
+    );
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(house.count_requests("GET", "setup"), 1);
+}
+
+#[test]
+fn default_profile_setting_selects_gateway() {
+    let house = FakeGateway::start();
+    let office = FakeGateway::start();
+    let mataho = two_profiles(&house, &office, "default_profile = \"office\"\n");
+
+    let output = mataho.run(&["ls"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(office.count_requests("GET", "setup"), 1);
+    assert_eq!(house.count_requests("GET", "setup"), 0);
+}
+
+#[test]
+fn unknown_profile_fails() {
+    let house = FakeGateway::start();
+    let office = FakeGateway::start();
+    let mataho = two_profiles(&house, &office, "");
+
+    let output = mataho.run(&["--profile", "garden", "ls"]);
+
+    assert_eq!(output.status.code(), Some(11));
+    assert!(stderr(&output).contains("No such profile: `garden`"));
+
+    let output = mataho.run(&["--profile", "../house", "ls"]);
+    assert_eq!(output.status.code(), Some(11));
+}
+
+#[test]
+fn groups_and_cache_are_per_profile() {
+    let house = FakeGateway::start();
+    let office = FakeGateway::start();
+    let mataho = two_profiles(&house, &office, "");
+
+    let output = mataho.run(&["--profile", "office", "group", "create", "blinds"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let output = mataho.run(&["--profile", "office", "group", "join", "blinds", "coko"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let office_dir = mataho.config_dir().join("profiles").join("office");
+    assert!(office_dir.join("groups.json").exists());
+    assert!(office_dir.join("setup_cache.json").exists());
+    assert!(!mataho.config_dir().join("setup_cache.json").exists());
+
+    let output = mataho.run(&["group", "ls"]);
+    assert!(stdout(&output).contains("No group"));
+
+    let output = mataho.run(&["--profile", "office", "group", "ls"]);
+    assert!(stdout(&output).contains("blinds(1): `Coko's room`"));
+}
+
+#[test]
+fn trust_pins_certificate_of_the_profile() {
+    let house = FakeGateway::start();
+    let office = FakeGateway::start_tls();
+    let mataho = Mataho::new(&house);
+    mataho.write_config(&format!(
+        "hostname = \"http://127.0.0.1\"\nport = {}\napi_token = \"{}\"\n\n[profiles.office]\nhostname = \"https://127.0.0.1\"\nport = {}\n",
+        house.port(),
+        API_TOKEN,
+        office.port()
+    ));
+
+    let output = mataho.run(&["--profile", "office", "trust", "--yes"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let config = mataho.read_config();
+    assert!(config.contains("[profiles.office.tls]"), "{}", config);
+
+    let output = mataho.run(&["--profile", "office", "ls"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let output = mataho.run(&["ls"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+}
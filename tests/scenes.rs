@@ -0,0 +1,156 @@
+mod common;
+
+use common::{stderr, stdout, FakeGateway, Mataho};
+use serde_json::{json, Value};
+
+fn run_ok(mataho: &Mataho, args: &[&str]) -> String {
+    let output = mataho.run(args);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    stdout(&output)
+}
+
+/// Scene "evening": close the bedrooms, set Coko's room to 30%, close the gate.
+fn evening(mataho: &Mataho) {
+    run_ok(mataho, &["group", "create", "bedrooms"]);
+    for device in ["00000004", "00000005"] {
+        run_ok(mataho, &["group", "join", "bedrooms", device]);
+    }
+
+    run_ok(mataho, &["scene", "create", "evening"]);
+    run_ok(
+        mataho,
+        &["scene", "add", "evening", "--group", "bedrooms", "close"],
+    );
+    run_ok(
+        mataho,
+        &["scene", "add", "evening", "coko", "setClosure", "30"],
+    );
+    run_ok(mataho, &["scene", "add", "evening", "front gate", "close"]);
+}
+
+fn action(device_id: &str, commands: Value) -> Value {
+    json!({
+        "commands": commands,
+        "deviceURL": format!("io://1234-5678-9012/{}", device_id)
+    })
+}
+
+#[test]
+fn scene_runs_as_a_single_execution() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    evening(&mataho);
+
+    let stdout = run_ok(&mataho, &["scene", "run", "evening"]);
+
+    assert!(stdout.contains("Running scene `evening` (3 steps on 4 devices)..."));
+    let payloads = gateway.exec_payloads();
+    assert_eq!(payloads.len(), 1);
+    assert_eq!(payloads[0]["label"], "Scene evening");
+    assert_eq!(
+        payloads[0]["actions"],
+        json!([
+            action("00000004", json!([{ "name": "close", "parameters": [] }])),
+            action("00000005", json!([{ "name": "close", "parameters": [] }])),
+            action(
+                "00000003",
+                json!([{ "name": "setClosure", "parameters": [30] }])
+            ),
+            action("00000001", json!([{ "name": "close", "parameters": [] }])),
+        ])
+    );
+}
+
+#[test]
+fn steps_on_the_same_device_are_merged() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    run_ok(&mataho, &["scene", "create", "airing"]);
+    run_ok(&mataho, &["scene", "add", "airing", "coko", "open"]);
+    run_ok(&mataho, &["scene", "add", "airing", "coko", "my"]);
+
+    run_ok(&mataho, &["scene", "run", "airing"]);
+
+    assert_eq!(
+        gateway.exec_payloads()[0]["actions"],
+        json!([action(
+            "00000003",
+            json!([
+                { "name": "open", "parameters": [] },
+                { "name": "my", "parameters": [] }
+            ])
+        )])
+    );
+}
+
+#[test]
+fn show_and_list_scenes() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    evening(&mataho);
+
+    let table = run_ok(&mataho, &["scene", "show", "evening"]);
+    assert!(table.contains("| 2    | Coko's room    | setClosure | 30 "));
+    assert!(table.contains("| group bedrooms "));
+
+    let scenes: Value =
+        serde_json::from_str(&run_ok(&mataho, &["scene", "ls", "-o", "json"])).unwrap();
+    assert_eq!(scenes[0]["name"], "evening");
+    assert_eq!(
+        scenes[0]["steps"][1],
+        json!({
+            "target_type": "device",
+            "target": "00000003",
+            "label": "Coko's room",
+            "command": "setClosure",
+            "params": ["30"]
+        })
+    );
+}
+
+#[test]
+fn unsupported_command_is_not_added() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    run_ok(&mataho, &["scene", "create", "evening"]);
+
+    let output = mataho.run(&["scene", "add", "evening", "front gate", "setClosure", "30"]);
+
+    assert_eq!(output.status.code(), Some(5));
+    let show = run_ok(&mataho, &["scene", "show", "evening"]);
+    assert!(show.contains("Scene `evening` has no steps"));
+}
+
+#[test]
+fn remove_steps_and_scenes() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    evening(&mataho);
+
+    run_ok(&mataho, &["scene", "rm", "evening", "1"]);
+    let table = run_ok(&mataho, &["scene", "show", "evening"]);
+    assert!(!table.contains("bedrooms"));
+    assert!(table.contains("| 1    | Coko's room "));
+
+    let output = mataho.run(&["scene", "rm", "evening", "3"]);
+    assert_eq!(output.status.code(), Some(6));
+    assert!(stderr(&output).contains("Scene `evening` has no step 3"));
+
+    run_ok(&mataho, &["scene", "rm", "evening"]);
+    let output = mataho.run(&["scene", "run", "evening"]);
+    assert_eq!(output.status.code(), Some(6));
+    assert!(stderr(&output).contains("No such scene: `evening`"));
+}
+
+#[test]
+fn empty_scene_is_not_run() {
+    let gateway = FakeGateway::start();
+    let mataho = Mataho::new(&gateway);
+    run_ok(&mataho, &["scene", "create", "evening"]);
+
+    let output = mataho.run(&["scene", "run", "evening"]);
+
+    assert_eq!(output.status.code(), Some(6));
+    assert!(gateway.exec_payloads().is_empty());
+}
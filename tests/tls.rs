@@ -0,0 +1,121 @@
+mod common;
+
+use common::{stderr, stdout, tls_fixture, FakeGateway, Mataho, API_TOKEN};
+
+/// SHA-256 fingerprint of `tests/fixtures/tls/gateway.pem`
+const GATEWAY_FINGERPRINT: &str = "95:09:6E:C9:9B:32:45:AA:20:DB:92:01:71:D9:E7:28:46:EE:6E:7B:4A:33:0D:F5:D9:3E:0D:0C:D0:3B:C3:EA";
+
+fn tls_mataho(gateway: &FakeGateway, tls: &str) -> Mataho {
+    let mataho = Mataho::new(gateway);
+    mataho.write_config(&format!(
+        "hostname = \"https://127.0.0.1\"\nport = {}\napi_token = \"{}\"\ntimeout = 2\n\n[tls]\n{}",
+        gateway.port(),
+        API_TOKEN,
+        tls
+    ));
+
+    mataho
+}
+
+#[test]
+fn unknown_certificate_is_rejected() {
+    let gateway = FakeGateway::start_tls();
+    let mataho = tls_mataho(&gateway, "");
+
+    let output = mataho.run(&["ls"]);
+
+    assert_eq!(output.status.code(), Some(12));
+    assert!(stderr(&output).contains("mataho trust"));
+    assert_eq!(gateway.count_requests("GET", "setup"), 0);
+}
+
+#[test]
+fn ca_file_trusts_gateway() {
+    let gateway = FakeGateway::start_tls();
+    let ca_file = tls_fixture("ca.pem");
+    let mataho = tls_mataho(
+        &gateway,
+        &format!("ca_file = \"{}\"\n", ca_file.to_string_lossy()),
+    );
+
+    let output = mataho.run(&["ls"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Coko's room"));
+}
+
+#[test]
+fn missing_ca_file_is_a_config_error() {
+    let gateway = FakeGateway::start_tls();
+    let mataho = tls_mataho(&gateway, "ca_file = \"/nonexistent/ca.pem\"\n");
+
+    let output = mataho.run(&["ls"]);
+
+    assert_eq!(output.status.code(), Some(11));
+    assert!(stderr(&output).contains("tls.ca_file"));
+}
+
+#[test]
+fn trust_pins_gateway_certificate() {
+    let gateway = FakeGateway::start_tls();
+    let mataho = tls_mataho(&gateway, "");
+
+    let output = mataho.run(&["trust", "--yes"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains(GATEWAY_FINGERPRINT));
+    assert_eq!(gateway.count_requests("GET", "setup"), 0);
+
+    let config = mataho.read_config();
+    assert!(config.contains(&format!("fingerprint = \"{}\"", GATEWAY_FINGERPRINT)));
+    assert!(config.contains(&format!("api_token = \"{}\"", API_TOKEN)));
+
+    let output = mataho.run(&["ls"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let output = mataho.run(&["trust"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("already trusted"));
+}
+
+#[test]
+fn trust_needs_confirmation() {
+    let gateway = FakeGateway::start_tls();
+    let mataho = tls_mataho(&gateway, "");
+
+    // stdin is empty, which does not count as a yes
+    let output = mataho.run(&["trust"]);
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("Certificate not trusted"));
+    assert!(!mataho.read_config().contains("fingerprint"));
+}
+
+#[test]
+fn wrong_pin_is_rejected() {
+    let gateway = FakeGateway::start_tls();
+    let mataho = tls_mataho(
+        &gateway,
+        &format!("fingerprint = \"{}\"\n", "AB:".repeat(32)),
+    );
+
+    let output = mataho.run(&["ls"]);
+
+    assert_eq!(output.status.code(), Some(12));
+    assert!(stderr(&output).contains("not the pinned one"));
+
+    let output = mataho.run(&["trust", "--yes"]);
+    assert!(output.status.success());
+    assert!(stderr(&output).contains("does not match the pinned certificate"));
+}
+
+#[test]
+fn insecure_mode_is_loud() {
+    let gateway = FakeGateway::start_tls();
+    let mataho = tls_mataho(&gateway, "insecure = true\n");
+
+    let output = mataho.run(&["ls"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stderr(&output).contains("TLS certificate checks are disabled"));
+}